@@ -0,0 +1,193 @@
+use crate::config::Config;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+/// A directive's output, along with the hash of the file it was extracted from
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+struct CacheEntry {
+    file_hash: u64,
+    /// The hashes of the other files the directive read, such as those it `include!`s
+    #[serde(default)]
+    dependencies: BTreeMap<PathBuf, u64>,
+    output: String,
+}
+
+impl CacheEntry {
+    /// Whether the files the output came from are unchanged
+    fn is_fresh(&self, file_hash: u64) -> bool {
+        self.file_hash == file_hash
+            && self
+                .dependencies
+                .iter()
+                .all(|(path, dependency_hash)| hash_file(path) == *dependency_hash)
+    }
+}
+
+/// Directive outputs kept between runs in a JSON file, so an unchanged book isn't re-parsed
+/// on every rebuild. Entries are keyed by the directive, the directory it is resolved against
+/// and the configuration, and are only used while the file they came from is unchanged.
+pub(crate) struct DirectiveCache {
+    path: Option<PathBuf>,
+    /// Entries loaded from the cache file
+    previous: BTreeMap<String, CacheEntry>,
+    /// Entries used in this run, which are the ones saved
+    current: RefCell<BTreeMap<String, CacheEntry>>,
+}
+
+impl DirectiveCache {
+    /// A cache that never stores anything
+    pub(crate) fn disabled() -> Self {
+        Self {
+            path: None,
+            previous: BTreeMap::new(),
+            current: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    /// Load the cache from a file, starting empty if it doesn't exist or can't be read
+    pub(crate) fn load(path: PathBuf) -> Self {
+        let previous = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        Self {
+            path: Some(path),
+            previous,
+            current: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    /// Get the cached output of a directive extracting from `file`, or run `extract` and
+    /// cache its output. `extract` also returns the paths of the files it read, and entries
+    /// for an older version of any of them are replaced. Errors are never cached.
+    pub(crate) fn get_or_insert_with(
+        &self,
+        base_dir: &Path,
+        directive: &str,
+        config: &Config,
+        file: &Path,
+        extract: impl FnOnce() -> Result<(String, Vec<PathBuf>)>,
+    ) -> Result<String> {
+        if self.path.is_none() {
+            return extract().map(|(output, _)| output);
+        }
+        let Ok(content) = fs::read(file) else {
+            return extract().map(|(output, _)| output);
+        };
+        let file_hash = hash(&content);
+        let key = format!(
+            "{:016x}",
+            hash(&(base_dir, directive, format!("{:?}", config)))
+        );
+
+        let cached = self
+            .current
+            .borrow()
+            .get(&key)
+            .or_else(|| self.previous.get(&key))
+            .filter(|entry| entry.is_fresh(file_hash))
+            .cloned();
+        let entry = match cached {
+            Some(entry) => entry,
+            None => {
+                let (output, read) = extract()?;
+                let dependencies = read
+                    .into_iter()
+                    .map(|path| {
+                        let dependency_hash = hash_file(&path);
+                        (path, dependency_hash)
+                    })
+                    .collect();
+                CacheEntry {
+                    file_hash,
+                    dependencies,
+                    output,
+                }
+            }
+        };
+        let output = entry.output.clone();
+        self.current.borrow_mut().insert(key, entry);
+        Ok(output)
+    }
+
+    /// Write the entries used in this run back to the cache file, if any changed
+    pub(crate) fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let current = self.current.borrow();
+        if *current == self.previous {
+            return Ok(());
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        fs::write(path, serde_json::to_string_pretty(&*current)?)
+            .with_context(|| format!("Failed to write cache file {}", path.display()))
+    }
+}
+
+/// Hash a file's content, which for a file that can't be read is the hash of its absence
+fn hash_file(path: &Path) -> u64 {
+    hash(&fs::read(path).ok())
+}
+
+/// Hash a value with the standard library's hasher
+fn hash(value: &impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn unchanged_file_is_not_extracted_again() {
+        let dir =
+            std::env::temp_dir().join(format!("include-rs-cache-unit-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("cached.rs");
+        let cache_file = dir.join("cache.json");
+        fs::write(&source, "struct Cached;\n").unwrap();
+
+        let extractions = Cell::new(0);
+        let run = || {
+            let cache = DirectiveCache::load(cache_file.clone());
+            let output = cache
+                .get_or_insert_with(
+                    &dir,
+                    "struct!(\"cached.rs\", Cached)",
+                    &Config::default(),
+                    &source,
+                    || {
+                        extractions.set(extractions.get() + 1);
+                        Ok((fs::read_to_string(&source)?, vec![source.clone()]))
+                    },
+                )
+                .unwrap();
+            cache.save().unwrap();
+            output
+        };
+
+        assert_eq!(run(), "struct Cached;\n");
+        assert_eq!(run(), "struct Cached;\n");
+        assert_eq!(extractions.get(), 1);
+
+        fs::write(&source, "struct Cached(u8);\n").unwrap();
+        assert_eq!(run(), "struct Cached(u8);\n");
+        assert_eq!(extractions.get(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,463 @@
+use anyhow::{Context, Result, bail};
+use mdbook::preprocess::PreprocessorContext;
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::Value;
+use toml::value::Table;
+
+/// How extracted items are rendered
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Render {
+    /// Use the item's original source text, preserving comments and formatting
+    #[default]
+    Source,
+    /// Re-render the item from its syntax tree with `prettyplease`
+    Pretty,
+}
+
+/// What to do with an item whose source text isn't available, as happens when its spans
+/// don't point into the file it was parsed from
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RenderFallback {
+    /// Fail the directive with an error
+    #[default]
+    Error,
+    /// Re-render the item from its syntax tree with `prettyplease`
+    Pretty,
+}
+
+/// Where hidden dependencies go relative to the visible code
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HiddenPlacement {
+    /// Hidden dependencies come first, then the visible code
+    #[default]
+    Before,
+    /// The visible code comes first, so readers see it immediately
+    After,
+}
+
+/// How the `with_path_header` comment naming a snippet's file is written
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PathHeaderStyle {
+    /// `// path: src/foo.rs`
+    #[default]
+    Comment,
+    /// `//- src/foo.rs`, as in rust-analyzer's test fixtures
+    Fixture,
+}
+
+/// The name of the preprocessor, and of its table in `book.toml`
+pub(crate) const PREPROCESSOR_NAME: &str = "include-rs";
+
+/// Environment variables starting with this override options from `book.toml`
+const ENV_PREFIX: &str = "MDBOOK_INCLUDE_RS_";
+
+/// Files larger than this many bytes are not parsed for item extraction by default
+pub(crate) const DEFAULT_MAX_PARSE_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Options read from the `[preprocessor.include-rs]` table in `book.toml`
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Config {
+    /// Directory that directive paths are resolved against, relative to the book root
+    pub base_dir: Option<String>,
+    /// How extracted items are rendered
+    pub render: Render,
+    /// Items with a line longer than this are re-rendered with `prettyplease`
+    pub max_line_width: Option<usize>,
+    /// What to do with an item whose source text isn't available
+    pub render_fallback: RenderFallback,
+    /// Files larger than this many bytes are refused for item extraction
+    pub max_parse_size: u64,
+    /// Where hidden dependencies go relative to the visible code
+    pub hidden_placement: HiddenPlacement,
+    /// Whether directives on their own line emit their own code fence
+    pub wrap_fences: bool,
+    /// Fence info strings for directive kinds, used when `wrap_fences` is on
+    pub fence_by_kind: BTreeMap<String, String>,
+    /// Whether directives may compile and run included examples
+    pub allow_run: bool,
+    /// Whether directives may expand a crate's macros with `cargo expand`
+    pub allow_expand: bool,
+    /// File that directive outputs are cached in between runs, relative to the book root
+    pub cache_file: Option<String>,
+    /// Whether the visible code of each snippet is checked to parse as Rust
+    pub verify_parse: bool,
+    /// Directory of the crate that `mod = "..."` module paths are resolved in
+    pub crate_root: Option<PathBuf>,
+    /// Whether every included file must be tracked by git
+    pub require_tracked: bool,
+    /// Whether blank lines at the start and end of an extracted body are removed
+    pub trim_body_blanks: bool,
+    /// How the `with_path_header` comment is written
+    pub path_header_style: PathHeaderStyle,
+    /// Whether carriage returns are removed from the processed chapters, so lines end in `\n`
+    pub normalize_line_endings: bool,
+    /// Whether extracted code is formatted with `rustfmt`, when it's installed
+    pub rustfmt: bool,
+    /// Whether a directive that fails fails the build, rather than having its error inlined
+    pub strict: bool,
+    /// Regexes whose matches are replaced with `***` in every directive's output
+    pub redact: Vec<String>,
+    /// Lines hidden at the top of every snippet, such as `#![allow(unused)]` and imports
+    pub hidden_preamble: Option<String>,
+    /// Options applied to every directive that doesn't set them itself
+    pub default_options: BTreeMap<String, String>,
+    /// Directories that a directive's file path can start with as `@name/`
+    pub path_aliases: BTreeMap<String, String>,
+    /// What hidden lines start with, `# ` as in doctests, or `None` to leave them out
+    pub hide_prefix: Option<String>,
+    /// The renderer the book is being preprocessed for, such as `html`
+    pub renderer: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            base_dir: None,
+            render: Render::default(),
+            max_line_width: None,
+            render_fallback: RenderFallback::default(),
+            max_parse_size: DEFAULT_MAX_PARSE_SIZE,
+            hidden_placement: HiddenPlacement::default(),
+            wrap_fences: false,
+            fence_by_kind: BTreeMap::new(),
+            allow_run: false,
+            allow_expand: false,
+            cache_file: None,
+            verify_parse: false,
+            crate_root: None,
+            require_tracked: false,
+            trim_body_blanks: true,
+            path_header_style: PathHeaderStyle::default(),
+            normalize_line_endings: true,
+            rustfmt: false,
+            strict: false,
+            redact: Vec::new(),
+            hidden_preamble: None,
+            default_options: BTreeMap::new(),
+            path_aliases: BTreeMap::new(),
+            hide_prefix: Some("# ".to_string()),
+            renderer: "html".to_string(),
+        }
+    }
+}
+
+impl Config {
+    /// Resolve the options for a preprocessor run: the `[preprocessor.include-rs]` table,
+    /// overridden by any `MDBOOK_INCLUDE_RS_<KEY>` environment variables
+    ///
+    /// Environment variables are named after the key in upper case, with `-` replaced by `_`,
+    /// and their values are read as TOML, falling back to a plain string. For example
+    /// `MDBOOK_INCLUDE_RS_MAX_LINE_WIDTH=80` sets `max-line-width`.
+    pub fn from_env_and_toml(ctx: &PreprocessorContext) -> Result<Self> {
+        let mut table = ctx
+            .config
+            .get_preprocessor(PREPROCESSOR_NAME)
+            .cloned()
+            .unwrap_or_default();
+        for (name, value) in std::env::vars() {
+            if let Some(key) = name.strip_prefix(ENV_PREFIX) {
+                table.insert(
+                    key.to_lowercase().replace('_', "-"),
+                    parse_env_value(&value),
+                );
+            }
+        }
+        let mut config = Self::from_table(Some(&table))?;
+        // Paths in the configuration are relative to the book root
+        config.crate_root = config.crate_root.map(|root| ctx.root.join(root));
+        config.renderer = ctx.renderer.clone();
+        Ok(config)
+    }
+
+    /// The configuration for a chapter with a sidecar file, like `intro.md.include.toml` for
+    /// `intro.md`. Its options are read like those in `book.toml` and override them, with the
+    /// tables of options, such as `default-options`, merged key by key
+    pub(crate) fn with_sidecar(&self, sidecar: &Path, book_root: &Path) -> Result<Self> {
+        let content = fs::read_to_string(sidecar)
+            .with_context(|| format!("Failed to read {}", sidecar.display()))?;
+        let table = toml::from_str::<Table>(&content)
+            .with_context(|| format!("Failed to parse {}", sidecar.display()))?;
+        let mut config = self.clone();
+        config
+            .merge_table(&table)
+            .with_context(|| format!("Invalid {}", sidecar.display()))?;
+        if table.contains_key("crate-root") {
+            config.crate_root = config.crate_root.map(|root| book_root.join(root));
+        }
+        Ok(config)
+    }
+
+    /// Parse the preprocessor's configuration table, if there is one
+    pub(crate) fn from_table(table: Option<&Table>) -> Result<Self> {
+        let mut config = Config::default();
+        if let Some(table) = table {
+            config.merge_table(table)?;
+        }
+        Ok(config)
+    }
+
+    /// Set the options given in a configuration table, leaving the others as they are
+    fn merge_table(&mut self, table: &Table) -> Result<()> {
+        let config = self;
+
+        if let Some(Value::String(dir)) = table.get("base-dir") {
+            config.base_dir = Some(dir.clone());
+        }
+
+        match table.get("render") {
+            None => {}
+            Some(Value::String(render)) if render == "source" => config.render = Render::Source,
+            Some(Value::String(render)) if render == "pretty" => config.render = Render::Pretty,
+            Some(other) => bail!(
+                "Invalid render mode {}, expected \"source\" or \"pretty\"",
+                other
+            ),
+        }
+
+        match table.get("max-line-width") {
+            None => {}
+            Some(Value::Integer(width)) if *width > 0 => {
+                config.max_line_width = Some(*width as usize);
+            }
+            Some(other) => bail!(
+                "Invalid max-line-width {}, expected a positive integer",
+                other
+            ),
+        }
+
+        match table.get("render-fallback") {
+            None => {}
+            Some(Value::String(fallback)) if fallback == "error" => {
+                config.render_fallback = RenderFallback::Error
+            }
+            Some(Value::String(fallback)) if fallback == "pretty" => {
+                config.render_fallback = RenderFallback::Pretty
+            }
+            Some(other) => bail!(
+                "Invalid render-fallback {}, expected \"error\" or \"pretty\"",
+                other
+            ),
+        }
+
+        match table.get("max-parse-size") {
+            None => {}
+            Some(Value::Integer(size)) if *size > 0 => config.max_parse_size = *size as u64,
+            Some(other) => bail!(
+                "Invalid max-parse-size {}, expected a positive number of bytes",
+                other
+            ),
+        }
+
+        match table.get("hidden-placement") {
+            None => {}
+            Some(Value::String(placement)) if placement == "before" => {
+                config.hidden_placement = HiddenPlacement::Before
+            }
+            Some(Value::String(placement)) if placement == "after" => {
+                config.hidden_placement = HiddenPlacement::After
+            }
+            Some(other) => bail!(
+                "Invalid hidden-placement {}, expected \"before\" or \"after\"",
+                other
+            ),
+        }
+
+        match table.get("wrap-fences") {
+            None => {}
+            Some(Value::Boolean(wrap)) => config.wrap_fences = *wrap,
+            Some(other) => bail!("Invalid wrap-fences {}, expected true or false", other),
+        }
+
+        match table.get("fence-by-kind") {
+            None => {}
+            Some(Value::Table(kinds)) => {
+                for (kind, info) in kinds {
+                    let Value::String(info) = info else {
+                        bail!(
+                            "Invalid fence-by-kind entry for {}: {}, expected a string",
+                            kind,
+                            info
+                        );
+                    };
+                    config.fence_by_kind.insert(kind.clone(), info.clone());
+                }
+            }
+            Some(other) => bail!(
+                "Invalid fence-by-kind {}, expected a table of directive kinds",
+                other
+            ),
+        }
+
+        match table.get("allow-run") {
+            None => {}
+            Some(Value::Boolean(allow)) => config.allow_run = *allow,
+            Some(other) => bail!("Invalid allow-run {}, expected true or false", other),
+        }
+
+        match table.get("allow-expand") {
+            None => {}
+            Some(Value::Boolean(allow)) => config.allow_expand = *allow,
+            Some(other) => bail!("Invalid allow-expand {}, expected true or false", other),
+        }
+
+        match table.get("cache-file") {
+            None => {}
+            Some(Value::String(file)) => config.cache_file = Some(file.clone()),
+            Some(other) => bail!("Invalid cache-file {}, expected a path", other),
+        }
+
+        match table.get("verify-parse") {
+            None => {}
+            Some(Value::Boolean(verify)) => config.verify_parse = *verify,
+            Some(other) => bail!("Invalid verify-parse {}, expected true or false", other),
+        }
+
+        match table.get("crate-root") {
+            None => {}
+            Some(Value::String(root)) => config.crate_root = Some(PathBuf::from(root)),
+            Some(other) => bail!("Invalid crate-root {}, expected a path", other),
+        }
+
+        match table.get("require-tracked") {
+            None => {}
+            Some(Value::Boolean(require)) => config.require_tracked = *require,
+            Some(other) => bail!("Invalid require-tracked {}, expected true or false", other),
+        }
+
+        match table.get("trim-body-blanks") {
+            None => {}
+            Some(Value::Boolean(trim)) => config.trim_body_blanks = *trim,
+            Some(other) => bail!("Invalid trim-body-blanks {}, expected true or false", other),
+        }
+
+        match table.get("path-header-style") {
+            None => {}
+            Some(Value::String(style)) if style == "comment" => {
+                config.path_header_style = PathHeaderStyle::Comment
+            }
+            Some(Value::String(style)) if style == "fixture" => {
+                config.path_header_style = PathHeaderStyle::Fixture
+            }
+            Some(other) => bail!(
+                "Invalid path-header-style {}, expected \"comment\" or \"fixture\"",
+                other
+            ),
+        }
+
+        match table.get("normalize-line-endings") {
+            None => {}
+            Some(Value::Boolean(normalize)) => config.normalize_line_endings = *normalize,
+            Some(other) => bail!(
+                "Invalid normalize-line-endings {}, expected true or false",
+                other
+            ),
+        }
+
+        match table.get("rustfmt") {
+            None => {}
+            Some(Value::Boolean(rustfmt)) => config.rustfmt = *rustfmt,
+            Some(other) => bail!("Invalid rustfmt {}, expected true or false", other),
+        }
+
+        match table.get("strict") {
+            None => {}
+            Some(Value::Boolean(strict)) => config.strict = *strict,
+            Some(other) => bail!("Invalid strict {}, expected true or false", other),
+        }
+
+        match table.get("redact") {
+            None => {}
+            Some(Value::Array(patterns)) => {
+                for pattern in patterns {
+                    let Value::String(pattern) = pattern else {
+                        bail!("Invalid redact pattern {}, expected a string", pattern);
+                    };
+                    if let Err(e) = Regex::new(pattern) {
+                        bail!("Invalid redact pattern '{}': {}", pattern, e);
+                    }
+                    config.redact.push(pattern.clone());
+                }
+            }
+            Some(other) => bail!("Invalid redact {}, expected an array of regexes", other),
+        }
+
+        match table.get("hidden-preamble") {
+            None => {}
+            Some(Value::String(preamble)) => config.hidden_preamble = Some(preamble.clone()),
+            Some(other) => bail!("Invalid hidden-preamble {}, expected a string", other),
+        }
+
+        match table.get("hide-prefix") {
+            None => {}
+            Some(Value::String(prefix)) => config.hide_prefix = hide_prefix(prefix),
+            Some(other) => bail!("Invalid hide-prefix {}, expected a string", other),
+        }
+
+        match table.get("default-options") {
+            None => {}
+            Some(Value::Table(options)) => {
+                for (key, value) in options {
+                    let value = match value {
+                        Value::String(value) => value.clone(),
+                        Value::Boolean(_) | Value::Integer(_) => value.to_string(),
+                        other => bail!(
+                            "Invalid default option {} = {}, expected a string, boolean or integer",
+                            key,
+                            other
+                        ),
+                    };
+                    config.default_options.insert(key.clone(), value);
+                }
+            }
+            Some(other) => bail!(
+                "Invalid default-options {}, expected a table of directive options",
+                other
+            ),
+        }
+
+        match table.get("path-aliases") {
+            None => {}
+            Some(Value::Table(aliases)) => {
+                for (alias, path) in aliases {
+                    let Value::String(path) = path else {
+                        bail!("Invalid path alias {}: {}, expected a string", alias, path);
+                    };
+                    config.path_aliases.insert(alias.clone(), path.clone());
+                }
+            }
+            Some(other) => bail!(
+                "Invalid path-aliases {}, expected a table of directories",
+                other
+            ),
+        }
+
+        Ok(())
+    }
+
+    /// The fence info string for a directive kind, `rust` unless configured otherwise
+    pub(crate) fn fence_info(&self, kind: &str) -> &str {
+        self.fence_by_kind.get(kind).map_or("rust", String::as_str)
+    }
+}
+
+/// Read an environment variable's value as a TOML value, or as a string if it isn't one
+fn parse_env_value(value: &str) -> Value {
+    toml::from_str::<Table>(&format!("value = {}", value))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| Value::String(value.to_string()))
+}
+
+/// The prefix of hidden lines given by a `hide-prefix` setting, separated from the code by a
+/// space, or `None` for an empty one, which leaves hidden lines out
+pub(crate) fn hide_prefix(value: &str) -> Option<String> {
+    match value {
+        "" => None,
+        prefix if prefix.ends_with(char::is_whitespace) => Some(prefix.to_string()),
+        prefix => Some(format!("{} ", prefix)),
+    }
+}
@@ -1,42 +1,414 @@
+use crate::config::Config;
+use crate::extractor::cfg::CfgSet;
+use crate::extractor::{ParsedFiles, Selector, resolve_module_file};
 use anyhow::Context;
+use globset::GlobBuilder;
 use regex::Regex;
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
 
-pub(crate) struct Directive {
+/// A directive's arguments, parsed from its `kind!(...)` text
+pub struct Directive {
+    /// The path of the file, empty if it's given as a module path with `mod = "..."`
     pub(crate) file_path: String,
     pub(crate) item: Option<String>,
+    /// Which of several items named `item` is meant, from a suffix like `run#2`
+    pub(crate) selector: Option<Selector>,
+    /// The build configuration an item must be enabled in, from a `cfg = "..."` option
+    pub(crate) cfg: Option<CfgSet>,
     pub(crate) extra_items: Vec<String>,
+    /// Every positional argument after the file path, starting with the item
+    pub(crate) args: Vec<String>,
+    /// `key = value` arguments, with quotes removed from string values
+    pub(crate) options: BTreeMap<String, String>,
+    /// A range of lines like `10..25`, for directives that include part of a file
+    pub(crate) line_range: Option<LineRange>,
 }
 
-/// Parse directive arguments (file path, item name, optional dependencies)
+/// A range of 1-indexed lines, written like a Rust range: `10..25`, `10..=25`, `10..` or `..25`
+pub(crate) struct LineRange {
+    /// The range as written in the directive
+    text: String,
+    start: Option<usize>,
+    end: Option<usize>,
+    inclusive: bool,
+}
+
+impl LineRange {
+    /// Parse a line range, returning `None` if the argument isn't one
+    fn parse(arg: &str) -> Option<Self> {
+        let re = Regex::new(r"^(\d+)?\.\.(=)?(\d+)?$").expect("valid line range regex");
+        let captures = re.captures(arg.trim())?;
+        let number = |i: usize| captures.get(i).and_then(|m| m.as_str().parse().ok());
+        let inclusive = captures.get(2).is_some();
+        // Like Rust, an inclusive range needs an end
+        if inclusive && captures.get(3).is_none() {
+            return None;
+        }
+        Some(Self {
+            text: arg.trim().to_string(),
+            start: number(1),
+            end: number(3),
+            inclusive,
+        })
+    }
+
+    /// The 1-based number of the first line in the range
+    pub(crate) fn first_line(&self) -> usize {
+        self.start.unwrap_or(1)
+    }
+
+    /// Select the lines of `content` in the range, which must lie within it
+    pub(crate) fn slice(&self, content: &str) -> anyhow::Result<String> {
+        let lines = content.lines().collect::<Vec<_>>();
+        let start = self.start.unwrap_or(1);
+        // The end as an exclusive 1-indexed line number
+        let end = match self.end {
+            Some(end) if self.inclusive => end + 1,
+            Some(end) => end,
+            None => lines.len() + 1,
+        };
+        if start == 0 || end > lines.len() + 1 {
+            anyhow::bail!(
+                "Line range {} is out of bounds, the file has {} lines",
+                self.text,
+                lines.len()
+            );
+        }
+        if start >= end {
+            anyhow::bail!("Line range {} is empty", self.text);
+        }
+        Ok(lines[start - 1..end - 1].join("\n"))
+    }
+}
+
+impl Directive {
+    /// Parse the arguments of a directive written like `struct!("src/lib.rs", Config)`
+    pub fn parse(directive: &str) -> anyhow::Result<Self> {
+        parse_directive_args(directive)
+    }
+
+    /// The path of the file the directive reads, relative to the chapter, or empty if it's
+    /// given as a module path with `mod = "..."`
+    pub fn file_path(&self) -> &str {
+        &self.file_path
+    }
+
+    /// The item named after the file path, if any
+    pub fn item(&self) -> Option<&str> {
+        self.item.as_deref()
+    }
+
+    /// Every positional argument after the file path, starting with the item
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// Get the value of a `key = value` argument
+    pub fn option(&self, key: &str) -> Option<&str> {
+        self.options.get(key).map(String::as_str)
+    }
+
+    /// Resolve the file the directive reads, either its path relative to `base_dir`, or the
+    /// file of the module given with `mod = "crate::path"` under the configured crate root
+    /// With `require-tracked`, the file must also be tracked by git
+    pub(crate) fn resolve_path(
+        &self,
+        base_dir: &Path,
+        config: &Config,
+        files: &ParsedFiles,
+    ) -> anyhow::Result<PathBuf> {
+        let path = match self.option("mod") {
+            Some(module) => {
+                let crate_root = config.crate_root.as_ref().with_context(|| {
+                    format!(
+                        "crate-root must be configured to resolve mod = \"{}\"",
+                        module
+                    )
+                })?;
+                resolve_module_file(crate_root, module)?
+            }
+            None => join_path(base_dir, &self.file_path),
+        };
+        if config.require_tracked {
+            files.tracked().ensure_tracked(&path)?;
+        }
+        Ok(path)
+    }
+
+    /// Resolve a file path with glob wildcards like `examples/part_*.rs` to every file it
+    /// matches, in lexicographic order so that the output is reproducible, or `None` if the
+    /// path has no wildcards. `*` and `?` don't cross directories but `**` does
+    /// With `require-tracked`, every matched file must also be tracked by git
+    pub(crate) fn resolve_glob(
+        &self,
+        base_dir: &Path,
+        config: &Config,
+        files: &ParsedFiles,
+    ) -> anyhow::Result<Option<Vec<PathBuf>>> {
+        let is_wildcard = |component: &str| component.contains(['*', '?', '[', '{']);
+        if self.option("mod").is_some() || !is_wildcard(&self.file_path) {
+            return Ok(None);
+        }
+
+        // Only the directory before the first wildcard has to be searched
+        let components = self.file_path.split('/').collect::<Vec<_>>();
+        let literal = components.iter().take_while(|c| !is_wildcard(c)).count();
+        let root = join_path(base_dir, &components[..literal].join("/"));
+        let pattern = components[literal..].join("/");
+        // globset rather than the glob crate, which has no `{a,b}` alternatives
+        let matcher = GlobBuilder::new(&pattern)
+            .literal_separator(true)
+            .build()
+            .with_context(|| format!("Invalid glob pattern '{}'", self.file_path))?
+            .compile_matcher();
+        let max_depth = if pattern.contains("**") {
+            usize::MAX
+        } else {
+            components.len() - literal
+        };
+
+        let mut paths = Vec::new();
+        collect_files(&root, &root, max_depth, &mut |relative, path| {
+            if matcher.is_match(relative) {
+                paths.push(path.to_path_buf());
+            }
+        })?;
+        if paths.is_empty() {
+            anyhow::bail!("Pattern '{}' matched no files", self.file_path);
+        }
+        paths.sort();
+        if config.require_tracked {
+            for path in &paths {
+                files.tracked().ensure_tracked(path)?;
+            }
+        }
+        Ok(Some(paths))
+    }
+
+    /// Get the names of a `key = [a, b]` argument, which may also be a single `key = a`
+    pub(crate) fn list_option(&self, key: &str) -> Option<Vec<String>> {
+        self.option(key).map(|value| {
+            let list = value
+                .strip_prefix('[')
+                .and_then(|value| value.strip_suffix(']'))
+                .unwrap_or(value);
+            split_args(list)
+                .into_iter()
+                .map(|name| unquote(&name).unwrap_or(name))
+                .filter(|name| !name.is_empty())
+                .collect()
+        })
+    }
+
+    /// Get the value of a `key = true` or `key = false` argument, which defaults to false
+    /// A flag may also be set by writing its key alone after the item, as `strip_docs`
+    pub(crate) fn flag(&self, key: &str) -> anyhow::Result<bool> {
+        match self.option(key) {
+            None => Ok(self.args.iter().skip(1).any(|arg| arg == key)),
+            Some("false") => Ok(false),
+            Some("true") => Ok(true),
+            Some(other) => {
+                anyhow::bail!("Invalid {} option '{}', expected true or false", key, other)
+            }
+        }
+    }
+}
+
+/// Join a path relative to a chapter onto its directory, resolving `..` against the
+/// chapter's path rather than the filesystem, so that it works the same whether or not the
+/// chapter's directory exists or is a symlink
+pub(crate) fn join_path(base_dir: &Path, relative: &str) -> PathBuf {
+    let mut path = PathBuf::new();
+    for component in base_dir.join(relative).components() {
+        match component {
+            Component::ParentDir
+                if matches!(path.components().next_back(), Some(Component::Normal(_))) =>
+            {
+                path.pop();
+            }
+            Component::CurDir => {}
+            component => path.push(component),
+        }
+    }
+    path
+}
+
+/// Visit the files under a directory, down to `max_depth` levels, with their paths relative
+/// to `root`. A directory that doesn't exist has no files
+fn collect_files(
+    root: &Path,
+    dir: &Path,
+    max_depth: usize,
+    visit: &mut dyn FnMut(&Path, &Path),
+) -> anyhow::Result<()> {
+    if max_depth == 0 || !dir.is_dir() {
+        return Ok(());
+    }
+    let entries = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read directory: {}", dir.display()))?;
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(root, &path, max_depth - 1, visit)?;
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            visit(relative, &path);
+        }
+    }
+    Ok(())
+}
+
+/// Parse directive arguments (file path, item name, optional dependencies and options)
+///
+/// The general shape is `directive_name!("path/to/file.rs", item_name, [deps...], key = "value")`.
+/// The item may be written bare or as a string literal; everything after it, including a
+/// line range like `10..25`, is optional and may appear in any order. Directives that take
+/// more than one positional argument read them from `args`.
 pub(crate) fn parse_directive_args(directive: &str) -> anyhow::Result<Directive> {
-    // Basic regex to parse directive: directive_name!("path/to/file.rs", item_name, [deps...])
-    let re =
-        Regex::new(r#"([a-z_]+)!\s*\(\s*"([^"]+)"\s*(?:,\s*([^,\[\]]+))?(?:,\s*\[(.*)\])?\s*\)"#)?;
+    let re = Regex::new(r"(?s)^\s*[a-z_]+!\s*\((.*)\)\s*$")?;
 
     let captures = re
         .captures(directive)
         .with_context(|| format!("Failed to parse directive: {}", directive))?;
 
-    let file_path = captures
-        .get(2)
-        .map(|m| m.as_str().to_string())
-        .with_context(|| "File path is required")?;
+    let mut args = split_args(&captures[1]).into_iter().peekable();
+    let option_re = Regex::new(r"(?s)^([a-z_]+)\s*=\s*(.*)$")?;
 
-    let item = captures.get(3).map(|m| m.as_str().trim().to_string());
+    // The file may instead be given as a module path, with `mod = "crate::path"`
+    let file_path = if args.peek().is_some_and(|arg| option_re.is_match(arg)) {
+        String::new()
+    } else {
+        args.next()
+            .and_then(|arg| unquote(&arg))
+            .with_context(|| format!("File path is required: {}", directive))?
+    };
 
-    let dependencies = captures
-        .get(4)
-        .map(|m| {
-            m.as_str()
-                .split(',')
-                .map(|s| s.trim().to_string())
-                .collect()
-        })
-        .unwrap_or_default();
+    let mut positional = Vec::new();
+    let mut extra_items = Vec::new();
+    let mut options = BTreeMap::new();
+    let mut line_range = None;
+
+    for arg in args {
+        if let Some(list) = arg.strip_prefix('[').and_then(|a| a.strip_suffix(']')) {
+            extra_items.extend(split_args(list));
+        } else if let Some(captures) = option_re.captures(&arg) {
+            let value = unquote(&captures[2]).unwrap_or_else(|| captures[2].to_string());
+            options.insert(captures[1].to_string(), value);
+        } else if let Some(range) = LineRange::parse(&arg) {
+            line_range = Some(range);
+        } else {
+            positional.push(unquote(&arg).unwrap_or(arg));
+        }
+    }
+
+    if file_path.is_empty() && !options.contains_key("mod") {
+        anyhow::bail!("File path is required: {}", directive);
+    }
+
+    // The item's name may end with a selector, which isn't part of the name
+    let (item, selector) = match positional.first() {
+        Some(first) => {
+            let (name, selector) = Selector::split(first)?;
+            (Some(name.to_string()), selector)
+        }
+        None => (None, None),
+    };
+
+    let cfg = options
+        .get("cfg")
+        .map(|spec| CfgSet::parse(spec))
+        .transpose()?;
 
     Ok(Directive {
         file_path,
         item,
-        extra_items: dependencies,
+        selector,
+        cfg,
+        extra_items,
+        args: positional,
+        options,
+        line_range,
     })
 }
+
+/// Parse the tabs of a `tabs!([("Label", directive!(...)), ...])` directive
+/// into pairs of label and directive
+pub(crate) fn parse_tabs_args(directive: &str) -> anyhow::Result<Vec<(String, String)>> {
+    let re = Regex::new(r"(?s)^\s*[a-z_]+!\s*\(\s*\[(.*)\]\s*\)\s*$")?;
+
+    let captures = re.captures(directive).with_context(|| {
+        format!(
+            "Expected a list of (\"Label\", directive) tabs: {}",
+            directive
+        )
+    })?;
+
+    split_args(&captures[1])
+        .into_iter()
+        .map(|tab| {
+            let parts = tab
+                .strip_prefix('(')
+                .and_then(|t| t.strip_suffix(')'))
+                .map(split_args)
+                .unwrap_or_default();
+            match parts.as_slice() {
+                [label, directive] => {
+                    let label = unquote(label)
+                        .with_context(|| format!("Tab label must be a string: {}", label))?;
+                    Ok((label, directive.clone()))
+                }
+                _ => anyhow::bail!("Expected a (\"Label\", directive) tab, got '{}'", tab),
+            }
+        })
+        .collect()
+}
+
+/// Split a comma separated argument list, ignoring commas nested in quotes or brackets
+fn split_args(args: &str) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut prev = ' ';
+
+    for c in args.chars() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+        } else {
+            match c {
+                '"' => in_string = true,
+                '(' | '[' | '{' | '<' => depth += 1,
+                // `->` and `=>` aren't closing brackets
+                '>' if prev == '-' || prev == '=' => {}
+                ')' | ']' | '}' | '>' => depth = depth.saturating_sub(1),
+                ',' if depth == 0 => {
+                    result.push(current.trim().to_string());
+                    current.clear();
+                    prev = c;
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        current.push(c);
+        prev = c;
+    }
+
+    if !current.trim().is_empty() {
+        result.push(current.trim().to_string());
+    }
+    result
+}
+
+/// Remove the quotes from a string literal argument, returning `None` if it isn't quoted
+fn unquote(arg: &str) -> Option<String> {
+    let inner = arg.strip_prefix('"')?.strip_suffix('"')?;
+    Some(inner.replace("\\\"", "\"").replace("\\\\", "\\"))
+}
@@ -0,0 +1,29 @@
+use anyhow::{Context, Result, bail};
+use std::path::Path;
+use std::process::Command;
+
+use crate::parser::get_relative_path;
+
+/// Expand the macros of a crate with `cargo expand`, returning the expanded source of its
+/// library, or of its binary if it has no library
+/// Needs `cargo-expand`, which is reported as missing rather than as a failed expansion
+pub(crate) fn expand_crate(crate_dir: &Path) -> Result<String> {
+    let mut command = Command::new("cargo");
+    command.arg("expand").current_dir(crate_dir);
+    if crate_dir.join("src").join("lib.rs").is_file() {
+        command.arg("--lib");
+    }
+    let expanded = command.output().context("Failed to run cargo")?;
+    if !expanded.status.success() {
+        let stderr = String::from_utf8_lossy(&expanded.stderr);
+        if stderr.contains("no such command") {
+            bail!("cargo-expand isn't installed, install it with `cargo install cargo-expand`");
+        }
+        bail!(
+            "Failed to expand the crate in {}:\n{}",
+            get_relative_path(crate_dir),
+            stderr.trim_end()
+        );
+    }
+    Ok(String::from_utf8_lossy(&expanded.stdout).into_owned())
+}
@@ -0,0 +1,59 @@
+//! Extract snippets from Rust source files without mdBook, for build scripts and other doc
+//! tools. [`extract`] processes a directive as a chapter would, and the `find_*` functions
+//! look items up in a file parsed with `syn::parse_file`.
+
+use crate::config::Config;
+use crate::extractor::ParsedFiles;
+use crate::parser::process_standalone_directive;
+use anyhow::Result;
+use std::path::Path;
+use syn::Item;
+
+pub use crate::directive::Directive;
+pub use crate::extractor::const_finder::{find_const, find_consts, find_static, find_statics};
+pub use crate::extractor::enum_finder::{find_enum, find_enums, find_variant};
+pub use crate::extractor::find_item_by_spec;
+pub use crate::extractor::function_extractor::{find_function, find_functions};
+pub use crate::extractor::impl_finder::{
+    find_implementors, find_impls_of, find_struct_impl, find_struct_impls, find_trait_impl,
+    find_trait_impls,
+};
+pub use crate::extractor::macro_finder::{find_macro, find_macros};
+pub use crate::extractor::method_extractor::{
+    find_assoc_item, find_assoc_items, find_method, find_methods, find_trait_method,
+};
+pub use crate::extractor::module_finder::find_module;
+pub use crate::extractor::struct_finder::{find_field, find_struct, find_structs};
+pub use crate::extractor::trait_finder::{find_trait, find_traits};
+pub use crate::extractor::type_finder::{find_type_alias, find_type_aliases};
+
+/// Process a directive and return the snippet it would put in a chapter, with the default
+/// configuration
+///
+/// `directive` is written as in a chapter, like `function_body!("src/lib.rs", run)`, with or
+/// without the surrounding `#![...]`, and its file path is resolved against `base_dir`.
+/// Code snippets are returned without a code fence, with the lines mdBook hides prefixed
+/// with `# `; directives that produce prose, like `loc!` or `const_table!`, return
+/// Markdown. A directive that fails returns its error rather than inlining it.
+pub fn extract(base_dir: &Path, directive: &str) -> Result<String> {
+    extract_with_config(base_dir, directive, &Config::default())
+}
+
+/// Process a directive like [`extract`], with the given configuration
+pub fn extract_with_config(base_dir: &Path, directive: &str, config: &Config) -> Result<String> {
+    let directive = directive.trim();
+    let directive = directive
+        .strip_prefix("#![")
+        .and_then(|directive| directive.strip_suffix(']'))
+        .unwrap_or(directive);
+    process_standalone_directive(base_dir, directive, config, &ParsedFiles::new())
+}
+
+/// Render an item found with one of the `find_*` functions as a directive would show it:
+/// as written in its file, or re-rendered with `prettyplease` under `render = "pretty"`
+///
+/// An item with no source text, such as one built with `syn::parse_quote!`, is an error
+/// unless `render_fallback` is [`RenderFallback::Pretty`](crate::RenderFallback::Pretty)
+pub fn render_item(item: &Item, config: &Config) -> Result<String> {
+    crate::formatter::render_item(item, config)
+}
@@ -0,0 +1,40 @@
+use regex::Regex;
+
+/// Find the lines between `// ANCHOR: name` and `// ANCHOR_END: name`, like mdBook's own
+/// `{{#include}}`. The lines of every marker are left out, so anchors may be nested or overlap.
+/// An anchor without an end runs to the end of the file, and `None` means it was never started
+pub(crate) fn find_anchor(content: &str, name: &str) -> Option<String> {
+    find_anchor_lines(content, name).map(|lines| {
+        lines
+            .into_iter()
+            .map(|(_, line)| line)
+            .collect::<Vec<_>>()
+            .join("\n")
+    })
+}
+
+/// Find the lines of an anchor like `find_anchor`, each with its 1-based line number in the file
+pub(crate) fn find_anchor_lines<'a>(content: &'a str, name: &str) -> Option<Vec<(usize, &'a str)>> {
+    let marker_re = Regex::new(r"ANCHOR(_END)?:\s*([\w-]+)").expect("valid anchor regex");
+
+    let mut lines = Vec::new();
+    let mut found = false;
+    let mut inside = false;
+    for (index, line) in content.lines().enumerate() {
+        match marker_re.captures(line) {
+            Some(captures) => {
+                if &captures[2] == name {
+                    let is_end = captures.get(1).is_some();
+                    if !is_end {
+                        found = true;
+                    }
+                    inside = !is_end;
+                }
+            }
+            None if inside => lines.push((index + 1, line)),
+            None => {}
+        }
+    }
+
+    found.then_some(lines)
+}
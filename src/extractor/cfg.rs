@@ -0,0 +1,100 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeSet;
+use syn::punctuated::Punctuated;
+use syn::{Attribute, Expr, Lit, Meta, Token};
+
+/// The configuration options a build is assumed to have, to tell which `#[cfg]`-gated items
+/// it would see. Written like the inside of a `cfg`, as a list of names like `unix` and
+/// `key = "value"` pairs like `feature = "serde"`; anything not listed is unset
+#[derive(Clone, Debug)]
+pub(crate) struct CfgSet {
+    names: BTreeSet<String>,
+    pairs: BTreeSet<(String, String)>,
+}
+
+impl CfgSet {
+    /// Parse a list like `unix, feature = "serde"`
+    pub(crate) fn parse(spec: &str) -> Result<Self> {
+        let options =
+            syn::parse::Parser::parse_str(Punctuated::<Meta, Token![,]>::parse_terminated, spec)
+                .with_context(|| {
+                    format!(
+                        "Invalid cfg '{}', expected names and key = \"value\" pairs",
+                        spec
+                    )
+                })?;
+
+        let mut names = BTreeSet::new();
+        let mut pairs = BTreeSet::new();
+        for option in options {
+            match &option {
+                Meta::Path(path) if path.get_ident().is_some() => {
+                    names.insert(path_name(path));
+                }
+                Meta::NameValue(name_value) => match string_value(&name_value.value) {
+                    Some(value) => {
+                        pairs.insert((path_name(&name_value.path), value));
+                    }
+                    None => anyhow::bail!("Invalid cfg '{}', values must be strings", spec),
+                },
+                _ => anyhow::bail!(
+                    "Invalid cfg '{}', expected names and key = \"value\" pairs",
+                    spec
+                ),
+            }
+        }
+        Ok(Self { names, pairs })
+    }
+
+    /// Whether every `#[cfg]` among some attributes holds, as it does when there are none
+    pub(crate) fn enables(&self, attrs: &[Attribute]) -> bool {
+        attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("cfg"))
+            .all(|attr| {
+                attr.parse_args::<Meta>()
+                    .is_ok_and(|meta| self.holds(&meta))
+            })
+    }
+
+    /// Evaluate a cfg predicate, with `all`, `any` and `not` combining others
+    fn holds(&self, predicate: &Meta) -> bool {
+        match predicate {
+            Meta::Path(path) => self.names.contains(&path_name(path)),
+            Meta::NameValue(name_value) => string_value(&name_value.value)
+                .is_some_and(|value| self.pairs.contains(&(path_name(&name_value.path), value))),
+            Meta::List(list) => {
+                let Ok(predicates) =
+                    list.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+                else {
+                    return false;
+                };
+                let predicates = predicates.into_iter().collect::<Vec<_>>();
+                match (path_name(&list.path).as_str(), predicates.as_slice()) {
+                    ("all", predicates) => predicates.iter().all(|p| self.holds(p)),
+                    ("any", predicates) => predicates.iter().any(|p| self.holds(p)),
+                    ("not", [predicate]) => !self.holds(predicate),
+                    _ => false,
+                }
+            }
+        }
+    }
+}
+
+fn path_name(path: &syn::Path) -> String {
+    path.segments
+        .iter()
+        .map(|segment| segment.ident.to_string())
+        .collect::<Vec<_>>()
+        .join("::")
+}
+
+fn string_value(expr: &Expr) -> Option<String> {
+    match expr {
+        Expr::Lit(expr_lit) => match &expr_lit.lit {
+            Lit::Str(text) => Some(text.value()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
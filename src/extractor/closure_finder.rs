@@ -0,0 +1,113 @@
+use proc_macro2::{TokenStream, TokenTree};
+use quote::ToTokens;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use syn::{File, Item, Type};
+
+/// The most items a closure collects, so a snippet of a large, tightly knit file doesn't
+/// hide the whole file one item at a time
+pub(crate) const MAX_CLOSURE_ITEMS: usize = 64;
+
+/// The top-level items of a file that are reachable from the given items by name: the types
+/// of their fields and signatures, the functions and macros they call, the traits they
+/// implement, and the impl blocks of every reachable type, followed transitively
+///
+/// Items are returned in file order, without the given items themselves. Names are matched
+/// by their last segment, so an item sharing a name with an external one is included too.
+/// The second value is whether the closure was cut short at `MAX_CLOSURE_ITEMS`
+pub(crate) fn find_closure(parsed_file: &File, roots: &[Item]) -> (Vec<Item>, bool) {
+    // Items by the name they're declared with, and impl blocks by the type they're for
+    let mut by_name: HashMap<String, Vec<usize>> = HashMap::new();
+    let mut impls_of: HashMap<String, Vec<usize>> = HashMap::new();
+    for (index, item) in parsed_file.items.iter().enumerate() {
+        if let Some(name) = item_name(item) {
+            by_name.entry(name).or_default().push(index);
+        } else if let Item::Impl(item_impl) = item {
+            if let Type::Path(type_path) = &*item_impl.self_ty {
+                if let Some(segment) = type_path.path.segments.last() {
+                    impls_of
+                        .entry(segment.ident.to_string())
+                        .or_default()
+                        .push(index);
+                }
+            }
+        }
+    }
+
+    let mut reached = BTreeSet::new();
+    let mut seen_names = HashSet::new();
+    let mut pending = roots
+        .iter()
+        .map(ToTokens::to_token_stream)
+        .collect::<Vec<_>>();
+    // A root that's a top-level type brings its impl blocks, as any reachable type does
+    for root in roots {
+        if let Some(name) = item_name(root) {
+            seen_names.insert(name.clone());
+            for &index in impls_of.get(&name).into_iter().flatten() {
+                if !roots.contains(&parsed_file.items[index]) && reached.insert(index) {
+                    pending.push(parsed_file.items[index].to_token_stream());
+                }
+            }
+        }
+    }
+
+    let mut truncated = false;
+    while let Some(tokens) = pending.pop() {
+        let mut names = Vec::new();
+        collect_idents(tokens, &mut names);
+        for name in names {
+            if !seen_names.insert(name.clone()) {
+                continue;
+            }
+            let declared = by_name.get(&name).into_iter().flatten();
+            let impls = impls_of.get(&name).into_iter().flatten();
+            for &index in declared.chain(impls) {
+                let item = &parsed_file.items[index];
+                if roots.contains(item) || reached.contains(&index) {
+                    continue;
+                }
+                if reached.len() == MAX_CLOSURE_ITEMS {
+                    truncated = true;
+                    continue;
+                }
+                reached.insert(index);
+                pending.push(item.to_token_stream());
+            }
+        }
+    }
+
+    let items = reached
+        .into_iter()
+        .map(|index| parsed_file.items[index].clone())
+        .collect();
+    (items, truncated)
+}
+
+/// The name a top-level item is declared with, for the kinds of items a name can refer to
+fn item_name(item: &Item) -> Option<String> {
+    let ident = match item {
+        Item::Const(item) => &item.ident,
+        Item::Enum(item) => &item.ident,
+        Item::Fn(item) => &item.sig.ident,
+        Item::Macro(item) => item.ident.as_ref()?,
+        Item::Static(item) => &item.ident,
+        Item::Struct(item) => &item.ident,
+        Item::Trait(item) => &item.ident,
+        Item::TraitAlias(item) => &item.ident,
+        Item::Type(item) => &item.ident,
+        Item::Union(item) => &item.ident,
+        _ => return None,
+    };
+    Some(ident.to_string())
+}
+
+/// Collect every identifier in a token stream, including those inside groups
+fn collect_idents(tokens: TokenStream, names: &mut Vec<String>) {
+    for token in tokens {
+        match token {
+            TokenTree::Ident(ident) => names.push(ident.to_string()),
+            TokenTree::Group(group) => collect_idents(group.stream(), names),
+            _ => {}
+        }
+    }
+}
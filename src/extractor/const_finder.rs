@@ -0,0 +1,80 @@
+use syn::{
+    File, ItemConst, ItemStatic,
+    visit::{self, Visit},
+};
+
+/// Find a const in a parsed Rust file
+pub fn find_const(parsed_file: &File, const_name: &str) -> Option<ItemConst> {
+    find_consts(parsed_file, const_name).into_iter().next()
+}
+
+/// Find every const with the name, in the order they appear in the file
+pub fn find_consts(parsed_file: &File, const_name: &str) -> Vec<ItemConst> {
+    let mut finder = ConstFinder::new(const_name);
+    finder.visit_file(parsed_file);
+    finder.const_items
+}
+
+/// A visitor that finds the consts with a name
+struct ConstFinder {
+    const_name: String,
+    const_items: Vec<ItemConst>,
+}
+
+impl ConstFinder {
+    pub fn new(const_name: &str) -> Self {
+        Self {
+            const_name: const_name.to_string(),
+            const_items: Vec::new(),
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for ConstFinder {
+    fn visit_item_const(&mut self, item_const: &'ast ItemConst) {
+        if item_const.ident == self.const_name {
+            self.const_items.push(item_const.clone());
+        }
+
+        // Continue visiting
+        visit::visit_item_const(self, item_const);
+    }
+}
+
+/// Find a static in a parsed Rust file
+pub fn find_static(parsed_file: &File, static_name: &str) -> Option<ItemStatic> {
+    find_statics(parsed_file, static_name).into_iter().next()
+}
+
+/// Find every static with the name, in the order they appear in the file
+pub fn find_statics(parsed_file: &File, static_name: &str) -> Vec<ItemStatic> {
+    let mut finder = StaticFinder::new(static_name);
+    finder.visit_file(parsed_file);
+    finder.static_items
+}
+
+/// A visitor that finds the statics with a name
+struct StaticFinder {
+    static_name: String,
+    static_items: Vec<ItemStatic>,
+}
+
+impl StaticFinder {
+    pub fn new(static_name: &str) -> Self {
+        Self {
+            static_name: static_name.to_string(),
+            static_items: Vec::new(),
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for StaticFinder {
+    fn visit_item_static(&mut self, item_static: &'ast ItemStatic) {
+        if item_static.ident == self.static_name {
+            self.static_items.push(item_static.clone());
+        }
+
+        // Continue visiting
+        visit::visit_item_static(self, item_static);
+    }
+}
@@ -1,34 +1,58 @@
+use crate::extractor::ItemPath;
 use syn::{
-    File, ItemEnum,
+    File, ItemEnum, ItemMod, Variant,
     visit::{self, Visit},
 };
 
 /// Find an enum in a parsed Rust file
-pub(crate) fn find_enum(parsed_file: &File, enum_name: &str) -> Option<ItemEnum> {
+/// The name may be qualified with the inline modules the enum is in, like `network::Protocol`
+pub fn find_enum(parsed_file: &File, enum_name: &str) -> Option<ItemEnum> {
+    find_enums(parsed_file, enum_name).into_iter().next()
+}
+
+/// Find every enum with the name, in the order they appear in the file
+pub fn find_enums(parsed_file: &File, enum_name: &str) -> Vec<ItemEnum> {
     let mut finder = EnumFinder::new(enum_name);
     finder.visit_file(parsed_file);
-    finder.enum_item
+    finder.enum_items
+}
+
+/// Find a variant of an enum by name
+pub fn find_variant(item_enum: &ItemEnum, variant_name: &str) -> Option<Variant> {
+    item_enum
+        .variants
+        .iter()
+        .find(|variant| variant.ident == variant_name)
+        .cloned()
 }
 
-/// A visitor that finds an enum by name
+/// A visitor that finds the enums with a name
 struct EnumFinder {
-    enum_name: String,
-    enum_item: Option<ItemEnum>,
+    enum_path: ItemPath,
+    modules: Vec<String>,
+    enum_items: Vec<ItemEnum>,
 }
 
 impl EnumFinder {
     pub fn new(enum_name: &str) -> Self {
         Self {
-            enum_name: enum_name.to_string(),
-            enum_item: None,
+            enum_path: ItemPath::parse(enum_name),
+            modules: Vec::new(),
+            enum_items: Vec::new(),
         }
     }
 }
 
 impl<'ast> Visit<'ast> for EnumFinder {
+    fn visit_item_mod(&mut self, item_mod: &'ast ItemMod) {
+        self.modules.push(item_mod.ident.to_string());
+        visit::visit_item_mod(self, item_mod);
+        self.modules.pop();
+    }
+
     fn visit_item_enum(&mut self, item_enum: &'ast ItemEnum) {
-        if item_enum.ident == self.enum_name {
-            self.enum_item = Some(item_enum.clone());
+        if self.enum_path.matches(&self.modules, &item_enum.ident) {
+            self.enum_items.push(item_enum.clone());
         }
 
         // Continue visiting
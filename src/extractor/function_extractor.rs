@@ -4,23 +4,32 @@ use syn::{
 };
 
 /// Find a function in a parsed Rust file
-pub(crate) fn find_function(parsed_file: &File, function_name: &str) -> Option<ItemFn> {
+/// When several share the name, such as alternatives behind `#[cfg]`, the first in the file is
+/// returned, as are the first matches of the other finders
+pub fn find_function(parsed_file: &File, function_name: &str) -> Option<ItemFn> {
+    find_functions(parsed_file, function_name)
+        .into_iter()
+        .next()
+}
+
+/// Find every function with the name, in the order they appear in the file
+pub fn find_functions(parsed_file: &File, function_name: &str) -> Vec<ItemFn> {
     let mut finder = FunctionFinder::new(function_name);
     finder.visit_file(parsed_file);
-    finder.function_item
+    finder.function_items
 }
 
-/// A visitor that finds a function by name
+/// A visitor that finds the functions with a name
 pub struct FunctionFinder {
     function_name: String,
-    function_item: Option<ItemFn>,
+    function_items: Vec<ItemFn>,
 }
 
 impl FunctionFinder {
     pub fn new(function_name: &str) -> Self {
         Self {
             function_name: function_name.to_string(),
-            function_item: None,
+            function_items: Vec::new(),
         }
     }
 }
@@ -28,7 +37,7 @@ impl FunctionFinder {
 impl<'ast> Visit<'ast> for FunctionFinder {
     fn visit_item_fn(&mut self, item_fn: &'ast ItemFn) {
         if item_fn.sig.ident == self.function_name {
-            self.function_item = Some(item_fn.clone());
+            self.function_items.push(item_fn.clone());
         }
 
         // Continue visiting
@@ -1,62 +1,120 @@
+use crate::extractor::without_whitespace;
+use quote::ToTokens;
 use syn::{
-    File, ItemImpl, Path, Type,
+    File, ItemImpl, ItemMod, Type,
     visit::{self, Visit},
 };
 
 /// Find a struct implementation in a parsed Rust file
-pub(crate) fn find_struct_impl(parsed_file: &File, struct_name: &str) -> Option<ItemImpl> {
+pub fn find_struct_impl(parsed_file: &File, struct_name: &str) -> Option<ItemImpl> {
+    find_struct_impls(parsed_file, struct_name)
+        .into_iter()
+        .next()
+}
+
+/// Find every inherent implementation of a struct, in the order they appear in the file
+pub fn find_struct_impls(parsed_file: &File, struct_name: &str) -> Vec<ItemImpl> {
     let mut finder = StructImplFinder::new(struct_name);
     finder.visit_file(parsed_file);
-    finder.impl_item
+    finder.impl_items
 }
 
 /// Find a trait implementation for a struct in a parsed Rust file
-pub(crate) fn find_trait_impl(
+pub fn find_trait_impl(
     parsed_file: &File,
     trait_name: &str,
     struct_name: &str,
 ) -> Option<ItemImpl> {
-    let mut finder = TraitImplFinder::new(trait_name, struct_name);
+    find_trait_impls(parsed_file, trait_name, struct_name)
+        .into_iter()
+        .next()
+}
+
+/// Find every implementation of a trait for a struct, in the order they appear in the file
+pub fn find_trait_impls(parsed_file: &File, trait_name: &str, struct_name: &str) -> Vec<ItemImpl> {
+    let mut finder = TraitImplFinder::new(trait_name, Some(struct_name));
     finder.visit_file(parsed_file);
-    finder.impl_item
+    finder.impl_items
+}
+
+/// Find every implementation of a trait, for any type, in the order they appear in the file
+pub fn find_implementors(parsed_file: &File, trait_name: &str) -> Vec<ItemImpl> {
+    let mut finder = TraitImplFinder::new(trait_name, None);
+    finder.visit_file(parsed_file);
+    finder.impl_items
+}
+
+/// Find every implementation for a type, inherent or of a trait, in the order they appear
+pub fn find_impls_of(parsed_file: &File, type_name: &str) -> Vec<ItemImpl> {
+    let mut finder = ImplsOfFinder::new(type_name);
+    finder.visit_file(parsed_file);
+    finder.impl_items
+}
+
+/// Whether the self type of an impl block, inside the inline `modules`, is the type a
+/// directive names. A bare name like `Wrapper` matches the type with any generic arguments,
+/// as `Wrapper<T>`, while a name with generic arguments, as `Wrapper<u8>`, matches only
+/// those. A name with a module path, as `shapes::Circle`, has to match the end of the
+/// type's path, starting from the modules the impl block is in
+fn type_matches(ty: &Type, modules: &[String], spec: &str) -> bool {
+    let Type::Path(type_path) = ty else {
+        return false;
+    };
+    let spec = without_whitespace(spec);
+    let spec = spec.strip_prefix("crate::").unwrap_or(&spec);
+    let path = if spec.contains('<') {
+        without_whitespace(&type_path.path.to_token_stream().to_string())
+    } else {
+        type_path
+            .path
+            .segments
+            .iter()
+            .map(|segment| segment.ident.to_string())
+            .collect::<Vec<_>>()
+            .join("::")
+    };
+    let path = match path.strip_prefix("crate::") {
+        Some(path) => path.to_string(),
+        None => modules
+            .iter()
+            .map(String::as_str)
+            .chain([path.as_str()])
+            .collect::<Vec<_>>()
+            .join("::"),
+    };
+    path == spec || path.ends_with(&format!("::{}", spec))
 }
 
-/// A visitor that finds a struct implementation by struct name
+/// A visitor that finds the inherent implementations of a struct
 struct StructImplFinder {
     struct_name: String,
-    impl_item: Option<ItemImpl>,
+    modules: Vec<String>,
+    impl_items: Vec<ItemImpl>,
 }
 
 impl StructImplFinder {
     pub fn new(struct_name: &str) -> Self {
         Self {
             struct_name: struct_name.to_string(),
-            impl_item: None,
-        }
-    }
-
-    fn get_type_path<'a>(&self, ty: &'a Type) -> Option<&'a Path> {
-        if let Type::Path(type_path) = ty {
-            Some(&type_path.path)
-        } else {
-            None
+            modules: Vec::new(),
+            impl_items: Vec::new(),
         }
     }
 }
 
 impl<'ast> Visit<'ast> for StructImplFinder {
+    fn visit_item_mod(&mut self, item_mod: &'ast ItemMod) {
+        self.modules.push(item_mod.ident.to_string());
+        visit::visit_item_mod(self, item_mod);
+        self.modules.pop();
+    }
+
     fn visit_item_impl(&mut self, item_impl: &'ast ItemImpl) {
         // Check if this is a struct implementation (not a trait implementation)
-        if item_impl.trait_.is_none() {
-            if let Some(path) = self.get_type_path(&item_impl.self_ty) {
-                if path
-                    .segments
-                    .last()
-                    .is_some_and(|seg| seg.ident == self.struct_name)
-                {
-                    self.impl_item = Some(item_impl.clone());
-                }
-            }
+        if item_impl.trait_.is_none()
+            && type_matches(&item_impl.self_ty, &self.modules, &self.struct_name)
+        {
+            self.impl_items.push(item_impl.clone());
         }
 
         // Continue visiting
@@ -64,32 +122,32 @@ impl<'ast> Visit<'ast> for StructImplFinder {
     }
 }
 
-/// A visitor that finds a trait implementation for a struct
+/// A visitor that finds the implementations of a trait for a struct, or for any type
 pub struct TraitImplFinder {
     trait_name: String,
-    struct_name: String,
-    impl_item: Option<ItemImpl>,
+    struct_name: Option<String>,
+    modules: Vec<String>,
+    impl_items: Vec<ItemImpl>,
 }
 
 impl TraitImplFinder {
-    pub fn new(trait_name: &str, struct_name: &str) -> Self {
+    pub fn new(trait_name: &str, struct_name: Option<&str>) -> Self {
         Self {
             trait_name: trait_name.to_string(),
-            struct_name: struct_name.to_string(),
-            impl_item: None,
-        }
-    }
-
-    fn get_type_path<'a>(&self, ty: &'a Type) -> Option<&'a Path> {
-        if let Type::Path(type_path) = ty {
-            Some(&type_path.path)
-        } else {
-            None
+            struct_name: struct_name.map(str::to_string),
+            modules: Vec::new(),
+            impl_items: Vec::new(),
         }
     }
 }
 
 impl<'ast> Visit<'ast> for TraitImplFinder {
+    fn visit_item_mod(&mut self, item_mod: &'ast ItemMod) {
+        self.modules.push(item_mod.ident.to_string());
+        visit::visit_item_mod(self, item_mod);
+        self.modules.pop();
+    }
+
     fn visit_item_impl(&mut self, item_impl: &'ast ItemImpl) {
         // Check if this is a trait implementation
         if let Some((_, trait_path, _)) = &item_impl.trait_ {
@@ -97,16 +155,44 @@ impl<'ast> Visit<'ast> for TraitImplFinder {
                 .segments
                 .last()
                 .is_some_and(|seg| seg.ident == self.trait_name)
+                && self.struct_name.as_ref().is_none_or(|struct_name| {
+                    type_matches(&item_impl.self_ty, &self.modules, struct_name)
+                })
+            {
+                self.impl_items.push(item_impl.clone());
+            }
+        }
+
+        // Continue visiting
+        visit::visit_item_impl(self, item_impl);
+    }
+}
+
+/// A visitor that collects every implementation for a type
+struct ImplsOfFinder {
+    type_name: String,
+    impl_items: Vec<ItemImpl>,
+}
+
+impl ImplsOfFinder {
+    pub fn new(type_name: &str) -> Self {
+        Self {
+            type_name: type_name.to_string(),
+            impl_items: Vec::new(),
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for ImplsOfFinder {
+    fn visit_item_impl(&mut self, item_impl: &'ast ItemImpl) {
+        if let Type::Path(type_path) = &*item_impl.self_ty {
+            if type_path
+                .path
+                .segments
+                .last()
+                .is_some_and(|seg| seg.ident == self.type_name)
             {
-                if let Some(path) = self.get_type_path(&item_impl.self_ty) {
-                    if path
-                        .segments
-                        .last()
-                        .is_some_and(|seg| seg.ident == self.struct_name)
-                    {
-                        self.impl_item = Some(item_impl.clone());
-                    }
-                }
+                self.impl_items.push(item_impl.clone());
             }
         }
 
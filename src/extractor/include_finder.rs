@@ -0,0 +1,88 @@
+use crate::config::Config;
+use crate::extractor::ParsedFiles;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use syn::{
+    File, Item, ItemMacro, LitStr,
+    visit::{self, Visit},
+};
+
+/// Search the files brought in with `include!("...")` for an item that isn't in the file
+/// itself, following their own `include!`s in turn. Returns the parsed file the item was
+/// found in, so its dependencies can be taken from the same file, along with the item.
+///
+/// This is best-effort: only `include!` invocations in item position with a string literal
+/// path are followed, without regard to any `cfg` on them. Paths built with `concat!` or
+/// `env!`, such as those into `OUT_DIR`, aren't resolved.
+pub(crate) fn find_in_included_files(
+    parsed_file: &File,
+    file_path: &Path,
+    config: &Config,
+    files: &ParsedFiles,
+    finder: &impl Fn(&File) -> Option<Item>,
+) -> Option<(Rc<File>, Item)> {
+    let mut visited = vec![file_path.to_path_buf()];
+    search_includes(parsed_file, file_path, config, files, finder, &mut visited)
+}
+
+/// Search the files included by `parsed_file`, depth first, skipping any already visited
+fn search_includes(
+    parsed_file: &File,
+    file_path: &Path,
+    config: &Config,
+    files: &ParsedFiles,
+    finder: &impl Fn(&File) -> Option<Item>,
+    visited: &mut Vec<PathBuf>,
+) -> Option<(Rc<File>, Item)> {
+    let dir = file_path.parent().unwrap_or(Path::new(""));
+    for included in find_includes(parsed_file) {
+        let path = dir.join(included);
+        if visited.contains(&path) {
+            continue;
+        }
+        visited.push(path.clone());
+
+        let Ok(included_file) = files.get(&path, config) else {
+            continue;
+        };
+        if let Some(item) = finder(&included_file) {
+            return Some((included_file, item));
+        }
+        if let Some(found) = search_includes(&included_file, &path, config, files, finder, visited)
+        {
+            return Some(found);
+        }
+    }
+    None
+}
+
+/// Find the paths of the `include!("...")` invocations in a parsed Rust file
+fn find_includes(parsed_file: &File) -> Vec<String> {
+    let mut finder = IncludeFinder::new();
+    finder.visit_file(parsed_file);
+    finder.paths
+}
+
+/// A visitor that collects the paths of `include!` invocations
+struct IncludeFinder {
+    paths: Vec<String>,
+}
+
+impl IncludeFinder {
+    pub fn new() -> Self {
+        Self { paths: Vec::new() }
+    }
+}
+
+impl<'ast> Visit<'ast> for IncludeFinder {
+    fn visit_item_macro(&mut self, item_macro: &'ast ItemMacro) {
+        if item_macro.mac.path.is_ident("include") {
+            if let Ok(path) = item_macro.mac.parse_body::<LitStr>() {
+                self.paths.push(path.value());
+            }
+        }
+
+        // Continue visiting
+        visit::visit_item_macro(self, item_macro);
+    }
+}
@@ -0,0 +1,54 @@
+use syn::{
+    Block, Item, Local, Stmt,
+    visit::{self, Visit},
+};
+
+/// Find the `let` bindings in a function body
+/// Only the body's own statements are searched unless `nested` is set, in which case
+/// bindings inside nested blocks and closures are included too. Nested items never are.
+pub(crate) fn find_locals(block: &Block, nested: bool) -> Vec<Local> {
+    let mut finder = LocalFinder::new(nested);
+    finder.visit_block(block);
+    finder.locals
+}
+
+/// A visitor that collects `let` bindings
+struct LocalFinder {
+    nested: bool,
+    depth: usize,
+    locals: Vec<Local>,
+}
+
+impl LocalFinder {
+    pub fn new(nested: bool) -> Self {
+        Self {
+            nested,
+            depth: 0,
+            locals: Vec::new(),
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for LocalFinder {
+    fn visit_block(&mut self, block: &'ast Block) {
+        // The function body itself is at depth 1; closures with a body are blocks too
+        self.depth += 1;
+        if self.depth == 1 || self.nested {
+            visit::visit_block(self, block);
+        }
+        self.depth -= 1;
+    }
+
+    fn visit_stmt(&mut self, stmt: &'ast Stmt) {
+        if let Stmt::Local(local) = stmt {
+            self.locals.push(local.clone());
+        }
+
+        // Continue visiting
+        visit::visit_stmt(self, stmt);
+    }
+
+    fn visit_item(&mut self, _item: &'ast Item) {
+        // Bindings in nested items belong to those items
+    }
+}
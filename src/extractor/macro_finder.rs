@@ -0,0 +1,101 @@
+use proc_macro2::{Spacing, TokenStream, TokenTree};
+use syn::spanned::Spanned;
+use syn::{
+    File, ItemMacro,
+    visit::{self, Visit},
+};
+
+/// Find a `macro_rules!` definition in a parsed Rust file
+/// Invocations of the macro, which are `ItemMacro`s too, never match
+pub fn find_macro(parsed_file: &File, macro_name: &str) -> Option<ItemMacro> {
+    find_macros(parsed_file, macro_name).into_iter().next()
+}
+
+/// Find every `macro_rules!` definition with the name, in the order they appear in the file
+pub fn find_macros(parsed_file: &File, macro_name: &str) -> Vec<ItemMacro> {
+    let mut finder = MacroFinder::new(macro_name);
+    finder.visit_file(parsed_file);
+    finder.macro_items
+}
+
+/// Find the `macro_rules!` definitions in a parsed Rust file that are invoked in `tokens`,
+/// along with those the definitions invoke in turn, in the order they appear in the file
+///
+/// Invocations are resolved by name only, so a macro shadowed in a nested scope resolves to
+/// the definition `find_macro` picks. A macro that invokes itself is only returned once.
+pub(crate) fn find_invoked_macros(parsed_file: &File, tokens: TokenStream) -> Vec<ItemMacro> {
+    let mut found: Vec<ItemMacro> = Vec::new();
+    let mut pending = invoked_macro_names(tokens);
+    while let Some(name) = pending.pop() {
+        if found.iter().any(|item_macro| {
+            item_macro
+                .ident
+                .as_ref()
+                .is_some_and(|ident| ident == &name)
+        }) {
+            continue;
+        }
+        if let Some(item_macro) = find_macro(parsed_file, &name) {
+            pending.extend(invoked_macro_names(item_macro.mac.tokens.clone()));
+            found.push(item_macro);
+        }
+    }
+    found.sort_by_key(|item_macro| item_macro.span().start());
+    found
+}
+
+/// Collect the names of the macros invoked in `tokens`, as `name!` pairs
+/// Token streams are used rather than a syntax tree so macro bodies can be searched too
+fn invoked_macro_names(tokens: TokenStream) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut previous_ident = None;
+    for token in tokens {
+        match token {
+            // `a != b` is an ident followed by a joint `!`, not an invocation
+            TokenTree::Punct(punct)
+                if punct.as_char() == '!' && punct.spacing() == Spacing::Alone =>
+            {
+                names.extend(previous_ident.take());
+            }
+            TokenTree::Ident(ident) => previous_ident = Some(ident.to_string()),
+            TokenTree::Group(group) => {
+                previous_ident = None;
+                names.extend(invoked_macro_names(group.stream()));
+            }
+            TokenTree::Punct(_) | TokenTree::Literal(_) => previous_ident = None,
+        }
+    }
+    names
+}
+
+/// A visitor that finds the `macro_rules!` definitions with a name
+struct MacroFinder {
+    macro_name: String,
+    macro_items: Vec<ItemMacro>,
+}
+
+impl MacroFinder {
+    pub fn new(macro_name: &str) -> Self {
+        Self {
+            macro_name: macro_name.to_string(),
+            macro_items: Vec::new(),
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for MacroFinder {
+    fn visit_item_macro(&mut self, item_macro: &'ast ItemMacro) {
+        // Only `macro_rules!` definitions have an identifier after the macro's path
+        if item_macro.mac.path.is_ident("macro_rules")
+            && item_macro
+                .ident
+                .as_ref()
+                .is_some_and(|ident| ident == &self.macro_name)
+        {
+            self.macro_items.push(item_macro.clone());
+        }
+
+        // Continue visiting
+        visit::visit_item_macro(self, item_macro);
+    }
+}
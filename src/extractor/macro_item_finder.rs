@@ -0,0 +1,116 @@
+use proc_macro2::{Delimiter, TokenStream, TokenTree};
+use syn::{
+    File, ItemMacro,
+    visit::{self, Visit},
+};
+
+/// Find an item definition hidden inside a macro invocation such as `cfg_if!`
+///
+/// This is a best-effort textual fallback: no macro expansion happens. The macro's
+/// token stream is searched for `<keyword> <name>` and the definition is taken up to
+/// the first brace-delimited group or `;` that follows, together with any leading
+/// attributes and visibility. Returns the macro's name and the item's source text.
+pub(crate) fn find_item_in_macros(
+    parsed_file: &File,
+    keyword: &str,
+    item_name: &str,
+) -> Option<(String, String)> {
+    let mut finder = MacroItemFinder::new(keyword, item_name);
+    finder.visit_file(parsed_file);
+    finder.found
+}
+
+/// A visitor that searches macro invocations for an item definition
+struct MacroItemFinder {
+    keyword: String,
+    item_name: String,
+    found: Option<(String, String)>,
+}
+
+impl MacroItemFinder {
+    pub fn new(keyword: &str, item_name: &str) -> Self {
+        Self {
+            keyword: keyword.to_string(),
+            item_name: item_name.to_string(),
+            found: None,
+        }
+    }
+
+    /// Search a token stream (and any nested groups) for the item definition
+    fn search(&self, tokens: TokenStream) -> Option<String> {
+        let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+        for (i, window) in tokens.windows(2).enumerate() {
+            if let (TokenTree::Ident(keyword), TokenTree::Ident(name)) = (&window[0], &window[1]) {
+                if keyword == &self.keyword && name == &self.item_name {
+                    let start = Self::definition_start(&tokens, i);
+                    let end = Self::definition_end(&tokens, i + 2)?;
+                    let span = tokens[start].span().join(tokens[end].span())?;
+                    return span.source_text();
+                }
+            }
+        }
+
+        tokens.into_iter().find_map(|token| match token {
+            TokenTree::Group(group) => self.search(group.stream()),
+            _ => None,
+        })
+    }
+
+    /// Walk backwards from the keyword over visibility and attributes
+    fn definition_start(tokens: &[TokenTree], keyword_idx: usize) -> usize {
+        let mut start = keyword_idx;
+        loop {
+            match tokens.get(start.wrapping_sub(1)) {
+                // `pub`
+                Some(TokenTree::Ident(ident)) if ident == "pub" => start -= 1,
+                // `pub(crate)`
+                Some(TokenTree::Group(group))
+                    if group.delimiter() == Delimiter::Parenthesis
+                        && matches!(tokens.get(start.wrapping_sub(2)), Some(TokenTree::Ident(ident)) if ident == "pub") =>
+                {
+                    start -= 2
+                }
+                // `#[...]`
+                Some(TokenTree::Group(group))
+                    if group.delimiter() == Delimiter::Bracket
+                        && matches!(tokens.get(start.wrapping_sub(2)), Some(TokenTree::Punct(p)) if p.as_char() == '#') =>
+                {
+                    start -= 2
+                }
+                _ => return start,
+            }
+        }
+    }
+
+    /// Find the token that closes the definition: the first brace group or `;`
+    fn definition_end(tokens: &[TokenTree], from: usize) -> Option<usize> {
+        tokens[from..]
+            .iter()
+            .position(|token| match token {
+                TokenTree::Group(group) => group.delimiter() == Delimiter::Brace,
+                TokenTree::Punct(punct) => punct.as_char() == ';',
+                _ => false,
+            })
+            .map(|pos| from + pos)
+    }
+}
+
+impl<'ast> Visit<'ast> for MacroItemFinder {
+    fn visit_item_macro(&mut self, item_macro: &'ast ItemMacro) {
+        if self.found.is_none() {
+            if let Some(text) = self.search(item_macro.mac.tokens.clone()) {
+                let macro_name = item_macro
+                    .mac
+                    .path
+                    .segments
+                    .last()
+                    .map(|seg| seg.ident.to_string())
+                    .unwrap_or_default();
+                self.found = Some((macro_name, text));
+            }
+        }
+
+        // Continue visiting
+        visit::visit_item_macro(self, item_macro);
+    }
+}
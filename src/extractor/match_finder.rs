@@ -0,0 +1,131 @@
+use std::collections::BTreeSet;
+use syn::{
+    Arm, Block, ExprMatch, Item, Pat, PatIdent, Path,
+    visit::{self, Visit},
+};
+
+/// How the `match` expressions of a function body cover the variants of an enum
+pub(crate) struct MatchCoverage {
+    /// The variants named in some arm's pattern
+    pub(crate) handled: BTreeSet<String>,
+    /// Whether one of the matches has a `_` or catch-all binding arm without a guard
+    pub(crate) wildcard: bool,
+}
+
+/// Find which of an enum's variants the `match` expressions in a function body handle
+/// A variant counts as handled when an arm's pattern names it as `Enum::Variant`,
+/// `Self::Variant` or, after a glob import, as a bare `Variant`. Only matches with at least
+/// one such arm are considered, so a wildcard in an unrelated match isn't counted
+pub(crate) fn find_match_coverage(
+    block: &Block,
+    enum_name: &str,
+    variants: &[String],
+) -> MatchCoverage {
+    let mut finder = MatchFinder {
+        enum_name,
+        variants,
+        coverage: MatchCoverage {
+            handled: BTreeSet::new(),
+            wildcard: false,
+        },
+    };
+    finder.visit_block(block);
+    finder.coverage
+}
+
+/// A visitor that collects the variants handled by `match` arms
+struct MatchFinder<'a> {
+    enum_name: &'a str,
+    variants: &'a [String],
+    coverage: MatchCoverage,
+}
+
+impl MatchFinder<'_> {
+    /// The variants an arm's pattern names, including inside `|` alternatives
+    fn arm_variants(&self, arm: &Arm) -> BTreeSet<String> {
+        let mut finder = VariantFinder {
+            enum_name: self.enum_name,
+            variants: self.variants,
+            found: BTreeSet::new(),
+        };
+        finder.visit_pat(&arm.pat);
+        finder.found
+    }
+
+    /// Whether an arm matches anything, as `_` or a plain binding does
+    fn is_catch_all(&self, arm: &Arm) -> bool {
+        if arm.guard.is_some() {
+            return false;
+        }
+        match &arm.pat {
+            Pat::Wild(_) => true,
+            Pat::Ident(pat_ident) => {
+                pat_ident.subpat.is_none() && !self.variants.contains(&pat_ident.ident.to_string())
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for MatchFinder<'_> {
+    fn visit_expr_match(&mut self, expr_match: &'ast ExprMatch) {
+        let arm_variants = expr_match
+            .arms
+            .iter()
+            .map(|arm| self.arm_variants(arm))
+            .collect::<Vec<_>>();
+        if arm_variants.iter().any(|variants| !variants.is_empty()) {
+            self.coverage
+                .handled
+                .extend(arm_variants.into_iter().flatten());
+            self.coverage.wildcard |= expr_match.arms.iter().any(|arm| self.is_catch_all(arm));
+        }
+
+        // Continue visiting
+        visit::visit_expr_match(self, expr_match);
+    }
+
+    fn visit_item(&mut self, _item: &'ast Item) {
+        // Matches in nested items belong to those items
+    }
+}
+
+/// A visitor that collects the variants named in a pattern
+struct VariantFinder<'a> {
+    enum_name: &'a str,
+    variants: &'a [String],
+    found: BTreeSet<String>,
+}
+
+impl<'ast> Visit<'ast> for VariantFinder<'_> {
+    fn visit_path(&mut self, path: &'ast Path) {
+        let segments = path
+            .segments
+            .iter()
+            .map(|segment| segment.ident.to_string())
+            .collect::<Vec<_>>();
+        if let Some((variant, qualifier)) = segments.split_last() {
+            let qualified = match qualifier.last() {
+                None => true,
+                Some(owner) => owner == self.enum_name || owner == "Self",
+            };
+            if qualified && self.variants.contains(variant) {
+                self.found.insert(variant.clone());
+            }
+        }
+
+        // Continue visiting
+        visit::visit_path(self, path);
+    }
+
+    fn visit_pat_ident(&mut self, pat_ident: &'ast PatIdent) {
+        // A unit variant brought in with a glob import parses as a binding
+        let name = pat_ident.ident.to_string();
+        if pat_ident.subpat.is_none() && self.variants.contains(&name) {
+            self.found.insert(name);
+        }
+
+        // Continue visiting
+        visit::visit_pat_ident(self, pat_ident);
+    }
+}
@@ -1,49 +1,112 @@
+use crate::extractor::trait_finder::find_trait;
 use syn::{
-    File, ImplItem, ImplItemFn, ItemImpl,
+    File, Ident, ImplItem, ImplItemFn, ItemImpl, TraitItem, TraitItemFn,
     visit::{self, Visit},
 };
 
 /// Find a method in a parsed Rust file by searching through impl blocks
-pub(crate) fn find_method(parsed_file: &File, method_spec: &str) -> Option<ImplItemFn> {
-    // Parse method specification: "StructName::method_name" or "TraitName::method_name for StructName"
-    if let Some((type_part, method_name)) = method_spec.rsplit_once("::") {
+pub fn find_method(parsed_file: &File, method_spec: &str) -> Option<ImplItemFn> {
+    find_methods(parsed_file, method_spec).into_iter().next()
+}
+
+/// Find every method matching the specification, in the order they appear in the file
+pub fn find_methods(parsed_file: &File, method_spec: &str) -> Vec<ImplItemFn> {
+    find_impl_items(parsed_file, method_spec, false)
+        .into_iter()
+        .filter_map(|item| match item {
+            ImplItem::Fn(method) => Some(method),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Find an associated const or type in a parsed Rust file, written like a method as
+/// `StructName::NAME` or `TraitName for StructName::NAME`
+pub fn find_assoc_item(parsed_file: &File, item_spec: &str) -> Option<ImplItem> {
+    find_assoc_items(parsed_file, item_spec).into_iter().next()
+}
+
+/// Find every associated const or type matching the specification, in the order they appear
+/// A `StructName::NAME` specification searches the struct's trait implementations too, since
+/// that's where associated types usually are
+pub fn find_assoc_items(parsed_file: &File, item_spec: &str) -> Vec<ImplItem> {
+    find_impl_items(parsed_file, item_spec, true)
+        .into_iter()
+        .filter(|item| matches!(item, ImplItem::Const(_) | ImplItem::Type(_)))
+        .collect()
+}
+
+/// Find the items of impl blocks matching a specification like `StructName::name` or
+/// `TraitName for StructName::name`, including those of trait impls for the former if
+/// `trait_impls` is set
+fn find_impl_items(parsed_file: &File, item_spec: &str, trait_impls: bool) -> Vec<ImplItem> {
+    // Parse item specification: "StructName::item_name" or "TraitName for StructName::item_name"
+    if let Some((type_part, item_name)) = item_spec.rsplit_once("::") {
         if type_part.contains(" for ") {
-            // Handle trait impl methods: "TraitName for StructName::method_name"
+            // Handle trait impl items: "TraitName for StructName::item_name"
             if let Some((trait_name, struct_name)) = type_part.split_once(" for ") {
                 let mut finder =
-                    TraitMethodFinder::new(trait_name.trim(), struct_name.trim(), method_name);
+                    TraitMethodFinder::new(trait_name.trim(), struct_name.trim(), item_name);
                 finder.visit_file(parsed_file);
-                return finder.method_item;
+                return finder.impl_items;
             }
         } else {
-            // Handle struct impl methods: "StructName::method_name"
-            let mut finder = StructMethodFinder::new(type_part, method_name);
+            // Handle struct impl items: "StructName::item_name"
+            let mut finder = StructMethodFinder::new(type_part, item_name, trait_impls);
             finder.visit_file(parsed_file);
-            return finder.method_item;
+            return finder.impl_items;
         }
     }
-    None
+    Vec::new()
 }
 
-/// A visitor that finds a method in a struct implementation by struct and method name
+/// The name of a method, associated const or associated type
+fn impl_item_ident(item: &ImplItem) -> Option<&Ident> {
+    match item {
+        ImplItem::Fn(method) => Some(&method.sig.ident),
+        ImplItem::Const(item_const) => Some(&item_const.ident),
+        ImplItem::Type(item_type) => Some(&item_type.ident),
+        _ => None,
+    }
+}
+
+/// Find a method declared in a trait definition
+pub fn find_trait_method(
+    parsed_file: &File,
+    trait_name: &str,
+    method_name: &str,
+) -> Option<TraitItemFn> {
+    find_trait(parsed_file, trait_name)?
+        .items
+        .into_iter()
+        .find_map(|item| match item {
+            TraitItem::Fn(method) if method.sig.ident == method_name => Some(method),
+            _ => None,
+        })
+}
+
+/// A visitor that finds the items in a struct's implementations by struct and item name
 struct StructMethodFinder {
     struct_name: String,
-    method_name: String,
-    method_item: Option<ImplItemFn>,
+    item_name: String,
+    /// Whether the struct's trait implementations are searched too
+    trait_impls: bool,
+    impl_items: Vec<ImplItem>,
 }
 
 impl StructMethodFinder {
-    pub fn new(struct_name: &str, method_name: &str) -> Self {
+    pub fn new(struct_name: &str, item_name: &str, trait_impls: bool) -> Self {
         Self {
             struct_name: struct_name.to_string(),
-            method_name: method_name.to_string(),
-            method_item: None,
+            item_name: item_name.to_string(),
+            trait_impls,
+            impl_items: Vec::new(),
         }
     }
 
     fn matches_struct_impl(&self, item_impl: &ItemImpl) -> bool {
         // Check if this is a struct implementation (not a trait implementation)
-        if item_impl.trait_.is_some() {
+        if item_impl.trait_.is_some() && !self.trait_impls {
             return false;
         }
 
@@ -61,15 +124,16 @@ impl StructMethodFinder {
 impl<'ast> Visit<'ast> for StructMethodFinder {
     fn visit_item_impl(&mut self, item_impl: &'ast ItemImpl) {
         if self.matches_struct_impl(item_impl) {
-            // Look for the method in this impl block
-            for impl_item in &item_impl.items {
-                if let ImplItem::Fn(method) = impl_item {
-                    if method.sig.ident == self.method_name {
-                        self.method_item = Some(method.clone());
-                        return;
-                    }
-                }
-            }
+            // Look for the item in this impl block
+            self.impl_items.extend(
+                item_impl
+                    .items
+                    .iter()
+                    .filter(|item| {
+                        impl_item_ident(item).is_some_and(|ident| ident == &self.item_name)
+                    })
+                    .cloned(),
+            );
         }
 
         // Continue visiting
@@ -77,21 +141,21 @@ impl<'ast> Visit<'ast> for StructMethodFinder {
     }
 }
 
-/// A visitor that finds a method in a trait implementation
+/// A visitor that finds the items in a trait's implementations
 struct TraitMethodFinder {
     trait_name: String,
     struct_name: String,
-    method_name: String,
-    method_item: Option<ImplItemFn>,
+    item_name: String,
+    impl_items: Vec<ImplItem>,
 }
 
 impl TraitMethodFinder {
-    pub fn new(trait_name: &str, struct_name: &str, method_name: &str) -> Self {
+    pub fn new(trait_name: &str, struct_name: &str, item_name: &str) -> Self {
         Self {
             trait_name: trait_name.to_string(),
             struct_name: struct_name.to_string(),
-            method_name: method_name.to_string(),
-            method_item: None,
+            item_name: item_name.to_string(),
+            impl_items: Vec::new(),
         }
     }
 
@@ -122,15 +186,16 @@ impl TraitMethodFinder {
 impl<'ast> Visit<'ast> for TraitMethodFinder {
     fn visit_item_impl(&mut self, item_impl: &'ast ItemImpl) {
         if self.matches_trait_impl(item_impl) {
-            // Look for the method in this impl block
-            for impl_item in &item_impl.items {
-                if let ImplItem::Fn(method) = impl_item {
-                    if method.sig.ident == self.method_name {
-                        self.method_item = Some(method.clone());
-                        return;
-                    }
-                }
-            }
+            // Look for the item in this impl block
+            self.impl_items.extend(
+                item_impl
+                    .items
+                    .iter()
+                    .filter(|item| {
+                        impl_item_ident(item).is_some_and(|ident| ident == &self.item_name)
+                    })
+                    .cloned(),
+            );
         }
 
         // Continue visiting
@@ -1,18 +1,284 @@
+pub(crate) mod anchor_finder;
+pub(crate) mod cfg;
+pub(crate) mod closure_finder;
+pub(crate) mod const_finder;
 pub(crate) mod enum_finder;
 pub(crate) mod function_extractor;
 pub(crate) mod impl_finder;
+pub(crate) mod include_finder;
+pub(crate) mod local_finder;
+pub(crate) mod macro_finder;
+pub(crate) mod macro_item_finder;
+pub(crate) mod match_finder;
 pub(crate) mod method_extractor;
+pub(crate) mod module_finder;
+pub(crate) mod pattern_finder;
 pub(crate) mod struct_finder;
 pub(crate) mod trait_finder;
+pub(crate) mod type_finder;
+pub(crate) mod type_ref_finder;
+pub(crate) mod usage_finder;
 
+use crate::config::Config;
+use crate::expander::expand_crate;
+use crate::git::TrackedFiles;
 use crate::parser::get_relative_path;
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
+use cfg::CfgSet;
+use const_finder::{find_const, find_static};
+use enum_finder::find_enum;
+use function_extractor::find_function;
+use impl_finder::{find_struct_impl, find_trait_impl};
+use macro_finder::find_macro;
+use method_extractor::find_method;
+use proc_macro2::Span;
+use quote::ToTokens;
+use regex::Regex;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
 use std::fs;
-use std::path::Path;
-use syn::File;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use struct_finder::find_struct;
+use syn::spanned::Spanned;
+use syn::{Attribute, File, Ident, Item};
+use trait_finder::find_trait;
+use type_finder::find_type_alias;
+
+/// An item's name, optionally qualified with the inline modules it's in, like `network::Packet`
+/// An unqualified name matches the item in any module
+pub(crate) struct ItemPath {
+    modules: Vec<String>,
+    name: String,
+}
+
+impl ItemPath {
+    /// Parse a name like `Packet`, `network::Packet` or `crate::network::Packet`
+    pub(crate) fn parse(spec: &str) -> Self {
+        let mut segments = spec
+            .split("::")
+            .map(|segment| segment.trim().to_string())
+            .skip_while(|segment| segment == "crate")
+            .collect::<Vec<_>>();
+        let name = segments.pop().unwrap_or_default();
+        Self {
+            modules: segments,
+            name,
+        }
+    }
+
+    /// Whether an item named `ident`, inside the inline `modules`, is the one named
+    pub(crate) fn matches(&self, modules: &[String], ident: &Ident) -> bool {
+        ident == &self.name && (self.modules.is_empty() || self.modules == modules)
+    }
+}
+
+/// Which of several items with the same name a directive means, written after the name as
+/// `run#2` for the second in the file, or `run#cfg(feature = "x")` for the one with that `cfg`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Selector {
+    /// The match at this 1-based position in the file
+    Index(usize),
+    /// The match with this attribute, written without whitespace, like `cfg(feature="x")`
+    Attribute(String),
+}
+
+impl Selector {
+    /// Split a selector off the end of an item name, returning the bare name and the selector
+    pub(crate) fn split(spec: &str) -> Result<(&str, Option<Self>)> {
+        let re = Regex::new(r"^(.*?)#(\d+|cfg\s*\(.*\))\s*$").expect("valid selector regex");
+        let Some(captures) = re.captures(spec) else {
+            return Ok((spec, None));
+        };
+        let name = captures.get(1).map_or("", |m| m.as_str()).trim_end();
+        let selector = &captures[2];
+        if selector.starts_with("cfg") {
+            return Ok((name, Some(Self::Attribute(without_whitespace(selector)))));
+        }
+        let selector = match selector.parse::<usize>() {
+            Ok(0) => bail!("Item selector #0 is invalid, the first match is #1"),
+            Ok(index) => Self::Index(index),
+            Err(_) => bail!("Item selector #{} is an invalid index", selector),
+        };
+        Ok((name, Some(selector)))
+    }
+}
+
+/// A selector that picks none of the items with the name, though there are some
+#[derive(Debug)]
+pub(crate) struct SelectorMismatch {
+    selector: Selector,
+    count: usize,
+}
+
+impl fmt::Display for SelectorMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.selector {
+            Selector::Index(index) => write!(
+                f,
+                "#{} selects match {}, but only {} were found",
+                index, index, self.count
+            ),
+            Selector::Attribute(attribute) => {
+                write!(f, "None of the {} matches has #[{}]", self.count, attribute)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SelectorMismatch {}
+
+/// Choose among the items with a name, which are in file order: the one the selector picks,
+/// or the first without one. With a `cfg`, only the items it enables are considered.
+/// Returns `None` if there are none at all, and a `SelectorMismatch` if there are some but
+/// the selector picks none of them
+pub(crate) fn select_item<T>(
+    matches: Vec<T>,
+    selector: Option<&Selector>,
+    cfg: Option<&CfgSet>,
+    attrs: impl Fn(&T) -> &[Attribute],
+) -> Result<Option<T>> {
+    let matches = match cfg {
+        Some(cfg) => matches
+            .into_iter()
+            .filter(|item| cfg.enables(attrs(item)))
+            .collect(),
+        None => matches,
+    };
+    let count = matches.len();
+    if count == 0 {
+        return Ok(None);
+    }
+    let selected = match selector {
+        None => matches.into_iter().next(),
+        Some(Selector::Index(index)) => matches.into_iter().nth(index - 1),
+        Some(Selector::Attribute(attribute)) => matches.into_iter().find(|item| {
+            attrs(item).iter().any(|attr| {
+                without_whitespace(&attr.meta.to_token_stream().to_string()) == *attribute
+            })
+        }),
+    };
+    match (selected, selector) {
+        (Some(item), _) => Ok(Some(item)),
+        (None, Some(selector)) => Err(SelectorMismatch {
+            selector: selector.clone(),
+            count,
+        }
+        .into()),
+        (None, None) => Ok(None),
+    }
+}
+
+/// The attributes of an item, for selecting it by one
+pub(crate) fn item_attrs(item: &Item) -> &[Attribute] {
+    match item {
+        Item::Const(item) => &item.attrs,
+        Item::Enum(item) => &item.attrs,
+        Item::Fn(item) => &item.attrs,
+        Item::Impl(item) => &item.attrs,
+        Item::Macro(item) => &item.attrs,
+        Item::Mod(item) => &item.attrs,
+        Item::Static(item) => &item.attrs,
+        Item::Struct(item) => &item.attrs,
+        Item::Trait(item) => &item.attrs,
+        Item::Type(item) => &item.attrs,
+        _ => &[],
+    }
+}
+
+pub(crate) fn without_whitespace(text: &str) -> String {
+    text.chars().filter(|c| !c.is_whitespace()).collect()
+}
+
+/// Parsed files kept for the duration of a preprocessor run, so a chapter that includes
+/// several snippets from the same file only reads and parses it once. Keyed by canonical
+/// path; files that fail to read or parse aren't kept, so their error is reported each time.
+pub(crate) struct ParsedFiles {
+    files: RefCell<HashMap<PathBuf, Rc<File>>>,
+    /// The `cargo expand` output of each crate, keyed by its directory
+    expansions: RefCell<HashMap<PathBuf, Rc<File>>>,
+    /// Every path asked for, in order, so a cached directive can be checked against each
+    /// file it read, such as those brought in with `include!`
+    reads: RefCell<Vec<PathBuf>>,
+    /// The files git tracks, for `require-tracked`
+    tracked: TrackedFiles,
+}
+
+impl ParsedFiles {
+    pub(crate) fn new() -> Self {
+        Self {
+            files: RefCell::new(HashMap::new()),
+            expansions: RefCell::new(HashMap::new()),
+            reads: RefCell::new(Vec::new()),
+            tracked: TrackedFiles::default(),
+        }
+    }
+
+    /// The files git tracks, listed at most once per repository for the run
+    pub(crate) fn tracked(&self) -> &TrackedFiles {
+        &self.tracked
+    }
+
+    /// Run `f`, returning the paths of the files it asked for along with its result
+    pub(crate) fn tracking_reads<T>(&self, f: impl FnOnce() -> T) -> (T, Vec<PathBuf>) {
+        let start = self.reads.borrow().len();
+        let result = f();
+        let mut paths = self.reads.borrow()[start..].to_vec();
+        paths.sort();
+        paths.dedup();
+        (result, paths)
+    }
+
+    /// Get a crate with its macros expanded, running `cargo expand` the first time it's asked
+    /// for, since expanding a crate means building it
+    pub(crate) fn get_expanded(&self, crate_dir: &Path) -> Result<Rc<File>> {
+        if let Some(file) = self.expansions.borrow().get(crate_dir) {
+            return Ok(Rc::clone(file));
+        }
+        let expanded = expand_crate(crate_dir)?;
+        let file = Rc::new(syn::parse_file(&expanded).with_context(|| {
+            format!(
+                "Failed to parse the expansion of {}",
+                get_relative_path(crate_dir)
+            )
+        })?);
+        self.expansions
+            .borrow_mut()
+            .insert(crate_dir.to_path_buf(), Rc::clone(&file));
+        Ok(file)
+    }
+
+    /// Get a parsed file, reading and parsing it the first time it's asked for
+    pub(crate) fn get(&self, file_path: &Path, config: &Config) -> Result<Rc<File>> {
+        let key = file_path
+            .canonicalize()
+            .unwrap_or_else(|_| file_path.to_path_buf());
+        self.reads.borrow_mut().push(key.clone());
+        if let Some(file) = self.files.borrow().get(&key) {
+            return Ok(Rc::clone(file));
+        }
+        let file = Rc::new(read_and_parse_file(file_path, config)?);
+        self.files.borrow_mut().insert(key, Rc::clone(&file));
+        Ok(file)
+    }
+}
 
 /// Read and parse a Rust source file
-pub(crate) fn read_and_parse_file(file_path: &Path) -> Result<File> {
+/// Files larger than the configured `max-parse-size` are refused rather than parsed
+fn read_and_parse_file(file_path: &Path, config: &Config) -> Result<File> {
+    let size = fs::metadata(file_path)
+        .with_context(|| format!("Failed to read file: {}", get_relative_path(file_path)))?
+        .len();
+    if size > config.max_parse_size {
+        bail!(
+            "File too large for AST extraction: {} is {} bytes, above the max-parse-size of {} bytes; include it with source_file! instead",
+            get_relative_path(file_path),
+            size,
+            config.max_parse_size
+        );
+    }
+
     let content = fs::read_to_string(file_path)
         .with_context(|| format!("Failed to read file: {}", get_relative_path(file_path)))?;
 
@@ -21,3 +287,94 @@ pub(crate) fn read_and_parse_file(file_path: &Path) -> Result<File> {
         .with_context(|| format!("Failed to parse file: {}", get_relative_path(file_path)))?;
     Ok(syntax_tree)
 }
+
+/// Find the file of a module, given as a path like `crate::net::config`, in the crate at
+/// `crate_root`. Both `src/net/config.rs` and `src/net/config/mod.rs` are accepted, and the
+/// crate itself is `src/lib.rs`, or `src/main.rs` for a binary crate
+pub(crate) fn resolve_module_file(crate_root: &Path, module: &str) -> Result<PathBuf> {
+    let segments = module
+        .split("::")
+        .map(str::trim)
+        .skip_while(|segment| *segment == "crate")
+        .collect::<Vec<_>>();
+    if segments.iter().any(|segment| segment.is_empty()) {
+        bail!("Invalid module path '{}'", module);
+    }
+
+    let src_dir = crate_root.join("src");
+    let candidates = match segments.split_last() {
+        None => vec![src_dir.join("lib.rs"), src_dir.join("main.rs")],
+        Some((name, parents)) => {
+            let dir = parents.iter().fold(src_dir, |dir, parent| dir.join(parent));
+            vec![
+                dir.join(format!("{}.rs", name)),
+                dir.join(name).join("mod.rs"),
+            ]
+        }
+    };
+    candidates
+        .iter()
+        .find(|candidate| candidate.is_file())
+        .cloned()
+        .with_context(|| {
+            format!(
+                "Module '{}' not found, looked for {}",
+                module,
+                candidates
+                    .iter()
+                    .map(|candidate| get_relative_path(candidate))
+                    .collect::<Vec<_>>()
+                    .join(" and ")
+            )
+        })
+}
+
+/// Find the span of a single item, selected the same way as the directive of the same name
+/// `function` also matches methods given as `Type::method`, and `trait_impl` takes `Trait for Type`
+pub(crate) fn find_item_span(parsed_file: &File, kind: &str, name: &str) -> Option<Span> {
+    match kind {
+        "function" | "fn" => find_function(parsed_file, name)
+            .map(|f| f.span())
+            .or_else(|| find_method(parsed_file, name).map(|m| m.span())),
+        "struct" => find_struct(parsed_file, name).map(|s| s.span()),
+        "enum" => find_enum(parsed_file, name).map(|e| e.span()),
+        "trait" => find_trait(parsed_file, name).map(|t| t.span()),
+        "const" => find_const(parsed_file, name).map(|c| c.span()),
+        "static" => find_static(parsed_file, name).map(|s| s.span()),
+        "type" => find_type_alias(parsed_file, name).map(|t| t.span()),
+        "macro" => find_macro(parsed_file, name).map(|m| m.span()),
+        "impl" => find_struct_impl(parsed_file, name).map(|i| i.span()),
+        "trait_impl" => {
+            let (trait_name, struct_name) = name.split_once(" for ")?;
+            find_trait_impl(parsed_file, trait_name.trim(), struct_name.trim()).map(|i| i.span())
+        }
+        _ => None,
+    }
+}
+
+/// Find an item from a spec like those accepted in a directive's dependency list:
+/// `struct Foo`, `enum Foo`, `trait Foo`, `fn foo`, `const FOO`, `static FOO`,
+/// `type Foo`, `impl Foo` or `impl Trait for Foo`.
+/// A bare name is looked up as a struct, then an enum, then a function.
+pub fn find_item_by_spec(parsed_file: &File, spec: &str) -> Option<Item> {
+    let spec = spec.trim();
+    match spec.split_once(' ') {
+        Some(("struct", name)) => find_struct(parsed_file, name.trim()).map(Item::Struct),
+        Some(("enum", name)) => find_enum(parsed_file, name.trim()).map(Item::Enum),
+        Some(("trait", name)) => find_trait(parsed_file, name.trim()).map(Item::Trait),
+        Some(("fn", name)) => find_function(parsed_file, name.trim()).map(Item::Fn),
+        Some(("const", name)) => find_const(parsed_file, name.trim()).map(Item::Const),
+        Some(("static", name)) => find_static(parsed_file, name.trim()).map(Item::Static),
+        Some(("type", name)) => find_type_alias(parsed_file, name.trim()).map(Item::Type),
+        Some(("impl", rest)) => match rest.split_once(" for ") {
+            Some((trait_name, struct_name)) => {
+                find_trait_impl(parsed_file, trait_name.trim(), struct_name.trim()).map(Item::Impl)
+            }
+            None => find_struct_impl(parsed_file, rest.trim()).map(Item::Impl),
+        },
+        _ => find_struct(parsed_file, spec)
+            .map(Item::Struct)
+            .or_else(|| find_enum(parsed_file, spec).map(Item::Enum))
+            .or_else(|| find_function(parsed_file, spec).map(Item::Fn)),
+    }
+}
@@ -0,0 +1,65 @@
+use proc_macro2::Span;
+use syn::spanned::Spanned;
+use syn::{
+    File, Item, ItemMod,
+    visit::{self, Visit},
+};
+
+/// Find a module in a parsed Rust file, whether inline or a `mod name;` declaration
+pub fn find_module(parsed_file: &File, module_name: &str) -> Option<ItemMod> {
+    let mut finder = ModuleFinder::new(module_name);
+    finder.visit_file(parsed_file);
+    finder.module_item
+}
+
+/// A visitor that finds a module by name
+struct ModuleFinder {
+    module_name: String,
+    module_item: Option<ItemMod>,
+}
+
+impl ModuleFinder {
+    pub fn new(module_name: &str) -> Self {
+        Self {
+            module_name: module_name.to_string(),
+            module_item: None,
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for ModuleFinder {
+    fn visit_item_mod(&mut self, item_mod: &'ast ItemMod) {
+        if self.module_item.is_some() {
+            return;
+        }
+
+        if item_mod.ident == self.module_name {
+            self.module_item = Some(item_mod.clone());
+        }
+
+        // Continue visiting
+        visit::visit_item_mod(self, item_mod);
+    }
+}
+
+/// Find the inline modules enclosing the code at `span`, outermost first
+pub(crate) fn find_enclosing_modules(parsed_file: &File, span: Span) -> Vec<&ItemMod> {
+    let mut modules = Vec::new();
+    let mut items = &parsed_file.items;
+    while let Some(module) = items.iter().find_map(|item| match item {
+        Item::Mod(module) if contains(module.span(), span) => Some(module),
+        _ => None,
+    }) {
+        modules.push(module);
+        match &module.content {
+            Some((_, content)) => items = content,
+            None => break,
+        }
+    }
+    modules
+}
+
+/// Whether the source range of `outer` contains that of `inner`
+fn contains(outer: Span, inner: Span) -> bool {
+    outer.start() <= inner.start() && inner.end() <= outer.end()
+}
@@ -0,0 +1,85 @@
+use regex::Regex;
+use syn::{
+    File, Item, ItemConst, ItemEnum, ItemFn, ItemStruct,
+    visit::{self, Visit},
+};
+
+/// The kinds of item that can be collected by name pattern
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PatternKind {
+    Function,
+    Struct,
+    Enum,
+    Const,
+}
+
+/// Find every item of the given kind whose name matches the pattern, in source order
+pub(crate) fn find_items_matching(
+    parsed_file: &File,
+    kind: PatternKind,
+    pattern: &Regex,
+) -> Vec<Item> {
+    let mut finder = PatternFinder::new(kind, pattern);
+    finder.visit_file(parsed_file);
+    finder.items
+}
+
+/// A visitor that collects all items of one kind whose names match a pattern
+struct PatternFinder<'a> {
+    kind: PatternKind,
+    pattern: &'a Regex,
+    items: Vec<Item>,
+}
+
+impl<'a> PatternFinder<'a> {
+    pub fn new(kind: PatternKind, pattern: &'a Regex) -> Self {
+        Self {
+            kind,
+            pattern,
+            items: Vec::new(),
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for PatternFinder<'_> {
+    fn visit_item_fn(&mut self, item_fn: &'ast ItemFn) {
+        if self.kind == PatternKind::Function
+            && self.pattern.is_match(&item_fn.sig.ident.to_string())
+        {
+            self.items.push(Item::Fn(item_fn.clone()));
+        }
+
+        // Consts local to a function body aren't part of the file's API
+        if self.kind != PatternKind::Const {
+            visit::visit_item_fn(self, item_fn);
+        }
+    }
+
+    fn visit_item_struct(&mut self, item_struct: &'ast ItemStruct) {
+        if self.kind == PatternKind::Struct && self.pattern.is_match(&item_struct.ident.to_string())
+        {
+            self.items.push(Item::Struct(item_struct.clone()));
+        }
+
+        // Continue visiting
+        visit::visit_item_struct(self, item_struct);
+    }
+
+    fn visit_item_enum(&mut self, item_enum: &'ast ItemEnum) {
+        if self.kind == PatternKind::Enum && self.pattern.is_match(&item_enum.ident.to_string()) {
+            self.items.push(Item::Enum(item_enum.clone()));
+        }
+
+        // Continue visiting
+        visit::visit_item_enum(self, item_enum);
+    }
+
+    fn visit_item_const(&mut self, item_const: &'ast ItemConst) {
+        if self.kind == PatternKind::Const && self.pattern.is_match(&item_const.ident.to_string()) {
+            self.items.push(Item::Const(item_const.clone()));
+        }
+
+        // Continue visiting
+        visit::visit_item_const(self, item_const);
+    }
+}
@@ -1,34 +1,68 @@
+use crate::extractor::ItemPath;
 use syn::{
-    File, ItemStruct,
+    Field, Fields, File, ItemMod, ItemStruct,
     visit::{self, Visit},
 };
 
 /// Find a struct in a parsed Rust file
-pub(crate) fn find_struct(parsed_file: &File, struct_name: &str) -> Option<ItemStruct> {
+/// The name may be qualified with the inline modules the struct is in, like `network::Packet`
+pub fn find_struct(parsed_file: &File, struct_name: &str) -> Option<ItemStruct> {
+    find_structs(parsed_file, struct_name).into_iter().next()
+}
+
+/// Find a named field of a struct
+/// Returns `None` if the struct has no field with the name, including when its fields are
+/// unnamed
+pub fn find_field(item_struct: &ItemStruct, field_name: &str) -> Option<Field> {
+    match &item_struct.fields {
+        Fields::Named(fields) => fields
+            .named
+            .iter()
+            .find(|field| {
+                field
+                    .ident
+                    .as_ref()
+                    .is_some_and(|ident| ident == field_name)
+            })
+            .cloned(),
+        Fields::Unnamed(_) | Fields::Unit => None,
+    }
+}
+
+/// Find every struct with the name, in the order they appear in the file
+pub fn find_structs(parsed_file: &File, struct_name: &str) -> Vec<ItemStruct> {
     let mut finder = StructFinder::new(struct_name);
     finder.visit_file(parsed_file);
-    finder.struct_item
+    finder.struct_items
 }
 
-/// A visitor that finds a struct by name
+/// A visitor that finds the structs with a name
 struct StructFinder {
-    struct_name: String,
-    struct_item: Option<ItemStruct>,
+    struct_path: ItemPath,
+    modules: Vec<String>,
+    struct_items: Vec<ItemStruct>,
 }
 
 impl StructFinder {
     pub fn new(struct_name: &str) -> Self {
         Self {
-            struct_name: struct_name.to_string(),
-            struct_item: None,
+            struct_path: ItemPath::parse(struct_name),
+            modules: Vec::new(),
+            struct_items: Vec::new(),
         }
     }
 }
 
 impl<'ast> Visit<'ast> for StructFinder {
+    fn visit_item_mod(&mut self, item_mod: &'ast ItemMod) {
+        self.modules.push(item_mod.ident.to_string());
+        visit::visit_item_mod(self, item_mod);
+        self.modules.pop();
+    }
+
     fn visit_item_struct(&mut self, item_struct: &'ast ItemStruct) {
-        if item_struct.ident == self.struct_name {
-            self.struct_item = Some(item_struct.clone());
+        if self.struct_path.matches(&self.modules, &item_struct.ident) {
+            self.struct_items.push(item_struct.clone());
         }
 
         // Continue visiting
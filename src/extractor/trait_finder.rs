@@ -1,34 +1,49 @@
+use crate::extractor::ItemPath;
 use syn::{
-    File, ItemTrait,
+    File, ItemMod, ItemTrait,
     visit::{self, Visit},
 };
 
 /// Find a trait in a parsed Rust file
+/// The name may be qualified with the inline modules the trait is in, like `network::Transport`
 pub fn find_trait(parsed_file: &File, trait_name: &str) -> Option<ItemTrait> {
+    find_traits(parsed_file, trait_name).into_iter().next()
+}
+
+/// Find every trait with the name, in the order they appear in the file
+pub fn find_traits(parsed_file: &File, trait_name: &str) -> Vec<ItemTrait> {
     let mut finder = TraitFinder::new(trait_name);
     finder.visit_file(parsed_file);
-    finder.trait_item
+    finder.trait_items
 }
 
-/// A visitor that finds a trait by name
+/// A visitor that finds the traits with a name
 pub struct TraitFinder {
-    trait_name: String,
-    trait_item: Option<ItemTrait>,
+    trait_path: ItemPath,
+    modules: Vec<String>,
+    trait_items: Vec<ItemTrait>,
 }
 
 impl TraitFinder {
     pub fn new(trait_name: &str) -> Self {
         Self {
-            trait_name: trait_name.to_string(),
-            trait_item: None,
+            trait_path: ItemPath::parse(trait_name),
+            modules: Vec::new(),
+            trait_items: Vec::new(),
         }
     }
 }
 
 impl<'ast> Visit<'ast> for TraitFinder {
+    fn visit_item_mod(&mut self, item_mod: &'ast ItemMod) {
+        self.modules.push(item_mod.ident.to_string());
+        visit::visit_item_mod(self, item_mod);
+        self.modules.pop();
+    }
+
     fn visit_item_trait(&mut self, item_trait: &'ast ItemTrait) {
-        if item_trait.ident == self.trait_name {
-            self.trait_item = Some(item_trait.clone());
+        if self.trait_path.matches(&self.modules, &item_trait.ident) {
+            self.trait_items.push(item_trait.clone());
         }
 
         // Continue visiting
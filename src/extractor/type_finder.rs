@@ -0,0 +1,44 @@
+use syn::{
+    File, ItemType,
+    visit::{self, Visit},
+};
+
+/// Find a type alias in a parsed Rust file
+pub fn find_type_alias(parsed_file: &File, alias_name: &str) -> Option<ItemType> {
+    find_type_aliases(parsed_file, alias_name)
+        .into_iter()
+        .next()
+}
+
+/// Find every type alias with the name, in the order they appear in the file
+pub fn find_type_aliases(parsed_file: &File, alias_name: &str) -> Vec<ItemType> {
+    let mut finder = TypeAliasFinder::new(alias_name);
+    finder.visit_file(parsed_file);
+    finder.alias_items
+}
+
+/// A visitor that finds the type aliases with a name
+struct TypeAliasFinder {
+    alias_name: String,
+    alias_items: Vec<ItemType>,
+}
+
+impl TypeAliasFinder {
+    pub fn new(alias_name: &str) -> Self {
+        Self {
+            alias_name: alias_name.to_string(),
+            alias_items: Vec::new(),
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for TypeAliasFinder {
+    fn visit_item_type(&mut self, item_type: &'ast ItemType) {
+        if item_type.ident == self.alias_name {
+            self.alias_items.push(item_type.clone());
+        }
+
+        // Continue visiting
+        visit::visit_item_type(self, item_type);
+    }
+}
@@ -0,0 +1,142 @@
+use super::enum_finder::find_enum;
+use super::struct_finder::find_struct;
+use super::trait_finder::find_trait;
+use super::type_finder::find_type_alias;
+use std::collections::HashSet;
+use syn::{
+    Block, ExprPath, ExprStruct, File, Item, Path, Signature, TraitBound, TypePath,
+    spanned::Spanned,
+    visit::{self, Visit},
+};
+
+/// Types and traits from the prelude, and `Self`, which never name a type defined in the file
+const PRELUDE_TYPES: &[&str] = &[
+    "Self",
+    "Option",
+    "Result",
+    "Vec",
+    "String",
+    "Box",
+    "ToString",
+    "ToOwned",
+    "Clone",
+    "Copy",
+    "Send",
+    "Sync",
+    "Sized",
+    "Unpin",
+    "Drop",
+    "Fn",
+    "FnMut",
+    "FnOnce",
+    "Default",
+    "Debug",
+    "Iterator",
+    "IntoIterator",
+    "Extend",
+    "From",
+    "Into",
+    "TryFrom",
+    "TryInto",
+    "AsRef",
+    "AsMut",
+    "PartialEq",
+    "Eq",
+    "PartialOrd",
+    "Ord",
+];
+
+/// Find the structs, enums, traits and type aliases of a file that a function refers to, in
+/// its signature or body, and those they refer to in turn, in the order they appear in the file
+///
+/// A reference is a type, a trait bound, a struct literal or a path like `Type::new`, matched
+/// by its last type segment. Paths into `std`, `core` and `alloc`, and prelude names like
+/// `Option`, are skipped, and each name is only resolved once, so cycles end
+pub(crate) fn find_referenced_types(
+    parsed_file: &File,
+    sig: &Signature,
+    block: Option<&Block>,
+) -> Vec<Item> {
+    let mut finder = TypeRefFinder { names: Vec::new() };
+    finder.visit_signature(sig);
+    if let Some(block) = block {
+        finder.visit_block(block);
+    }
+
+    let mut seen = HashSet::new();
+    let mut items = Vec::new();
+    while let Some(name) = finder.names.pop() {
+        if !seen.insert(name.clone()) {
+            continue;
+        }
+        let Some(item) = find_type(parsed_file, &name) else {
+            continue;
+        };
+        // The types a found type refers to are needed too
+        finder.visit_item(&item);
+        items.push(item);
+    }
+    items.sort_by_key(|item| item.span().start());
+    items
+}
+
+/// Find a type defined in the file by name, with the existing finders
+fn find_type(parsed_file: &File, name: &str) -> Option<Item> {
+    find_struct(parsed_file, name)
+        .map(Item::Struct)
+        .or_else(|| find_enum(parsed_file, name).map(Item::Enum))
+        .or_else(|| find_trait(parsed_file, name).map(Item::Trait))
+        .or_else(|| find_type_alias(parsed_file, name).map(Item::Type))
+}
+
+/// A visitor that collects the names of the types a node refers to
+struct TypeRefFinder {
+    names: Vec<String>,
+}
+
+impl TypeRefFinder {
+    /// Record the type a path names, by its last segment, or with `skip_last` the one
+    /// before it, as for the `Type` of `Type::new`
+    fn record(&mut self, path: &Path, skip_last: bool) {
+        let external = path
+            .segments
+            .first()
+            .is_some_and(|segment| ["std", "core", "alloc"].contains(&&*segment.ident.to_string()));
+        if external {
+            return;
+        }
+        let index = path.segments.len().checked_sub(1 + usize::from(skip_last));
+        let Some(segment) = index.and_then(|index| path.segments.iter().nth(index)) else {
+            return;
+        };
+        let name = segment.ident.to_string();
+        if !PRELUDE_TYPES.contains(&name.as_str()) {
+            self.names.push(name);
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for TypeRefFinder {
+    fn visit_type_path(&mut self, type_path: &'ast TypePath) {
+        self.record(&type_path.path, false);
+        visit::visit_type_path(self, type_path);
+    }
+
+    fn visit_trait_bound(&mut self, bound: &'ast TraitBound) {
+        self.record(&bound.path, false);
+        visit::visit_trait_bound(self, bound);
+    }
+
+    fn visit_expr_struct(&mut self, expr: &'ast ExprStruct) {
+        self.record(&expr.path, false);
+        visit::visit_expr_struct(self, expr);
+    }
+
+    fn visit_expr_path(&mut self, expr: &'ast ExprPath) {
+        // A path of one segment is a variable or a function, not a type
+        if expr.path.segments.len() > 1 {
+            self.record(&expr.path, true);
+        }
+        visit::visit_expr_path(self, expr);
+    }
+}
@@ -0,0 +1,134 @@
+use proc_macro2::{TokenStream, TokenTree};
+use quote::ToTokens;
+use syn::{
+    File, ImplItemFn, ItemFn, ItemImpl, ItemTrait, Signature, TraitItemFn, Type,
+    spanned::Spanned,
+    visit::{self, Visit},
+};
+
+/// A function, method or impl block that mentions a type
+pub(crate) struct Usage {
+    /// The name of the function, `Type::method` for a method, or the header of an impl block
+    pub(crate) name: String,
+    pub(crate) line: usize,
+    /// Whether the type appears in the signature, or in the header of an impl block
+    pub(crate) in_signature: bool,
+    /// Whether the type appears in the body
+    pub(crate) in_body: bool,
+}
+
+/// Find the functions, methods and impl blocks that mention a type, in the order they appear
+/// in the file. A mention is any identifier with the type's name, so this is a best-effort
+/// index: a variable or a type in another module with the same name counts too
+pub(crate) fn find_usages(parsed_file: &File, type_name: &str) -> Vec<Usage> {
+    // A type named by its module path is matched by its last segment
+    let name = type_name.rsplit("::").next().unwrap_or(type_name).trim();
+    let mut finder = UsageFinder {
+        name,
+        owner: None,
+        usages: Vec::new(),
+    };
+    finder.visit_file(parsed_file);
+    finder.usages
+}
+
+/// A visitor that collects the functions, methods and impl blocks mentioning a type
+struct UsageFinder<'a> {
+    name: &'a str,
+    /// The type or trait whose methods are being visited
+    owner: Option<String>,
+    usages: Vec<Usage>,
+}
+
+impl UsageFinder<'_> {
+    fn mentions(&self, tokens: TokenStream) -> bool {
+        tokens.into_iter().any(|token| match token {
+            TokenTree::Ident(ident) => ident == self.name,
+            TokenTree::Group(group) => self.mentions(group.stream()),
+            _ => false,
+        })
+    }
+
+    /// Record a function or method if its signature or body mentions the type
+    fn record_fn(&mut self, sig: &Signature, body: Option<TokenStream>) {
+        let in_signature = self.mentions(sig.to_token_stream());
+        let in_body = body.is_some_and(|body| self.mentions(body));
+        if in_signature || in_body {
+            let name = match &self.owner {
+                Some(owner) => format!("{}::{}", owner, sig.ident),
+                None => sig.ident.to_string(),
+            };
+            self.usages.push(Usage {
+                name,
+                line: sig.ident.span().start().line,
+                in_signature,
+                in_body,
+            });
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for UsageFinder<'_> {
+    fn visit_item_fn(&mut self, item_fn: &'ast ItemFn) {
+        // Items nested in the body are part of it
+        self.record_fn(&item_fn.sig, Some(item_fn.block.to_token_stream()));
+    }
+
+    fn visit_impl_item_fn(&mut self, method: &'ast ImplItemFn) {
+        self.record_fn(&method.sig, Some(method.block.to_token_stream()));
+    }
+
+    fn visit_trait_item_fn(&mut self, method: &'ast TraitItemFn) {
+        let body = method.default.as_ref().map(ToTokens::to_token_stream);
+        self.record_fn(&method.sig, body);
+    }
+
+    fn visit_item_impl(&mut self, item_impl: &'ast ItemImpl) {
+        let mut header = item_impl.generics.to_token_stream();
+        header.extend(item_impl.self_ty.to_token_stream());
+        if let Some((_, path, _)) = &item_impl.trait_ {
+            header.extend(path.to_token_stream());
+        }
+        header.extend(item_impl.generics.where_clause.to_token_stream());
+        if self.mentions(header) {
+            let self_ty = render_node(&*item_impl.self_ty);
+            let name = match &item_impl.trait_ {
+                Some((bang, path, _)) => format!(
+                    "impl {}{} for {}",
+                    if bang.is_some() { "!" } else { "" },
+                    render_node(path),
+                    self_ty
+                ),
+                None => format!("impl {}", self_ty),
+            };
+            self.usages.push(Usage {
+                name,
+                line: item_impl.impl_token.span.start().line,
+                in_signature: true,
+                in_body: false,
+            });
+        }
+
+        // The methods are named after the type, without its generics
+        let owner = match &*item_impl.self_ty {
+            Type::Path(type_path) => type_path.path.segments.last().map(|s| s.ident.to_string()),
+            self_ty => Some(render_node(self_ty)),
+        };
+        let outer = std::mem::replace(&mut self.owner, owner);
+        visit::visit_item_impl(self, item_impl);
+        self.owner = outer;
+    }
+
+    fn visit_item_trait(&mut self, item_trait: &'ast ItemTrait) {
+        let outer = std::mem::replace(&mut self.owner, Some(item_trait.ident.to_string()));
+        visit::visit_item_trait(self, item_trait);
+        self.owner = outer;
+    }
+}
+
+/// Render a syntax node from its original source text, falling back to its tokens
+fn render_node<T: Spanned + ToTokens>(node: &T) -> String {
+    node.span()
+        .source_text()
+        .unwrap_or_else(|| node.to_token_stream().to_string())
+}
@@ -1,10 +1,23 @@
+use crate::config::{Config, Render, RenderFallback};
+use crate::extractor::match_finder::MatchCoverage;
+use crate::extractor::usage_finder::Usage;
+use quote::{ToTokens, format_ident, quote};
+use similar::{ChangeTag, TextDiff};
+use std::io::Write;
+use std::process::{Command, Stdio};
 use syn::spanned::Spanned;
-use syn::{ImplItemFn, Item};
+use syn::visit_mut::{self, VisitMut};
+use syn::{
+    Attribute, Block, Expr, Field, FnArg, GenericParam, Generics, ImplItem, ImplItemFn, Item,
+    ItemConst, ItemFn, ItemImpl, ItemMod, ItemStruct, ItemTrait, Lit, Local, Meta, Pat, ReturnType,
+    Signature, TraitBoundModifier, TraitItem, TraitItemFn, Type, TypeParam, TypeParamBound,
+    Variant, Visibility, parse_quote,
+};
 
 /// Remove common leading whitespace from all lines (similar to Python's textwrap.dedent)
 /// For method/function extraction, we skip the first line when calculating minimum indentation
 /// since the function signature should align to the left margin
-fn dedent(text: &str) -> String {
+pub(crate) fn dedent(text: &str) -> String {
     let lines: Vec<&str> = text.lines().collect();
     if lines.is_empty() {
         return String::new();
@@ -15,7 +28,7 @@ fn dedent(text: &str) -> String {
         .iter()
         .skip(1) // Skip first line (function signature)
         .filter(|line| !line.trim().is_empty()) // Skip empty lines
-        .map(|line| line.len() - line.trim_start().len()) // Count leading whitespace
+        .map(|line| indent_width(line))
         .min()
         .unwrap_or(0);
 
@@ -29,110 +42,834 @@ fn dedent(text: &str) -> String {
             } else if i == 0 {
                 // Keep first line as-is (function signature)
                 line.to_string()
-            } else if line.len() >= min_indent {
-                line[min_indent..].to_string()
             } else {
-                line.to_string()
+                strip_indent(line, min_indent).to_string()
             }
         })
-        .collect::<Vec<String>>()
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Remove the leading whitespace common to every line, including the first, for
+/// snippets cut from the middle of a file
+pub(crate) fn dedent_lines(text: &str) -> String {
+    let min_indent = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(indent_width)
+        .min()
+        .unwrap_or(0);
+    text.lines()
+        .map(|line| strip_indent(line, min_indent))
+        .collect::<Vec<_>>()
         .join("\n")
 }
 
+/// The number of whitespace characters a line starts with
+/// A tab counts as one character, the same as a space, so a file indented with tabs
+/// throughout dedents by whole tabs
+fn indent_width(line: &str) -> usize {
+    line.chars().take_while(|c| c.is_whitespace()).count()
+}
+
+/// Remove up to `width` leading whitespace characters from a line
+fn strip_indent(line: &str, width: usize) -> &str {
+    let start = line
+        .char_indices()
+        .take(width)
+        .take_while(|(_, c)| c.is_whitespace())
+        .last()
+        .map_or(0, |(i, c)| i + c.len_utf8());
+    &line[start..]
+}
+
 /// Format an item as a string
-pub fn format_item(item: &Item) -> String {
-    let source_text = item
-        .span()
-        .source_text()
-        .expect("Failed to get source text");
-    dedent(&source_text)
+pub fn format_item(item: &Item, config: &Config) -> anyhow::Result<String> {
+    match source_text(item) {
+        Ok(source) => Ok(dedent(&source)),
+        // Without its source text, the item can only be rendered from its syntax tree
+        Err(_) if config.render_fallback == RenderFallback::Pretty => Ok(format_item_pretty(item)),
+        Err(e) => Err(anyhow::anyhow!(
+            "{}, set render-fallback = \"pretty\" to render it from its syntax tree",
+            e
+        )),
+    }
 }
 
-/// Format a function body as a string
-/// It will always replace the function name with `main`
-/// It will always prefix the first and last lines with `# `
-/// If the body has the comments:
-/// * `// DISPLAY START` - This line and any before are prefixed with `# `
-/// * `// DISPLAY END` - This line and any after are prefixed with `# `
-pub(crate) fn format_function_body(fn_item: &Item) -> String {
-    if matches!(fn_item, Item::Fn { .. }) {
-        let source_text = fn_item
-            .span()
-            .source_text()
-            .expect("Failed to get source text");
-        let mut lines = source_text.split("\n").collect::<Vec<_>>();
-        if lines.len() == 1 {
-            return String::new();
-        }
-        lines[0] = "fn main() {\n";
-
-        // Process display markers
-        let mut result = String::new();
-        let mut display_started = false;
-        let mut display_ended = false;
-
-        // Check if display markers exist
-        let has_display_start = lines.iter().any(|line| line.trim() == "// DISPLAY START");
-        let has_display_end = lines.iter().any(|line| line.trim() == "// DISPLAY END");
-
-        // Skip the function signature and closing brace
-        for (i, line) in lines.iter().enumerate() {
-            // Skip the first and last line (fn main() and closing brace)
-            if i == 0 || i == lines.len() - 1 {
-                result.push_str(&format!("# {}\n", line.trim()));
-                continue;
+/// The source text of a syntax node, as written in its file
+/// Fails, rather than panicking, if the node's span can't be resolved to its source
+fn source_text(node: &impl Spanned) -> anyhow::Result<String> {
+    let span = node.span();
+    span.source_text().ok_or_else(|| {
+        anyhow::anyhow!(
+            "Failed to get the source text of the code at line {}, column {}",
+            span.start().line,
+            span.start().column + 1
+        )
+    })
+}
+
+/// Format an item for display, honouring the configured render mode
+/// Under `max-line-width`, items whose source has an over-long line are re-rendered
+/// with `prettyplease`, which reformats (and wraps) the whole item
+pub(crate) fn render_item(item: &Item, config: &Config) -> anyhow::Result<String> {
+    let source = format_item(item, config)?;
+    let too_wide = config
+        .max_line_width
+        .is_some_and(|width| source.lines().any(|line| line.chars().count() > width));
+    let rendered = if config.render == Render::Pretty || too_wide {
+        format_item_pretty(item)
+    } else {
+        source
+    };
+    Ok(format_with_rustfmt(rendered, config))
+}
+
+/// With the `rustfmt` option, format visible code with `rustfmt` so it reads the same however
+/// its source was formatted. The code is left as it is if rustfmt isn't installed or fails,
+/// as it does on a fragment that isn't valid on its own
+pub(crate) fn format_with_rustfmt(code: String, config: &Config) -> String {
+    if !config.rustfmt {
+        return code;
+    }
+    run_rustfmt(&code).unwrap_or(code)
+}
+
+fn run_rustfmt(code: &str) -> Option<String> {
+    let mut child = Command::new("rustfmt")
+        .args(["--emit", "stdout", "--edition", "2021"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    // rustfmt reads all of its input before writing any output, so this can't deadlock
+    child.stdin.take()?.write_all(code.as_bytes()).ok()?;
+    let output = child.wait_with_output().ok()?;
+    output.status.success().then(|| {
+        String::from_utf8_lossy(&output.stdout)
+            .trim_end()
+            .to_string()
+    })
+}
+
+/// Format an item by re-rendering its syntax tree with `prettyplease`
+/// Comments other than doc comments are not preserved
+pub(crate) fn format_item_pretty(item: &Item) -> String {
+    let file = syn::File {
+        shebang: None,
+        attrs: Vec::new(),
+        items: vec![item.clone()],
+    };
+    prettyplease::unparse(&file).trim_end().to_string()
+}
+
+/// Remove the doc comments, the other attributes, or both from an item and everything in it,
+/// such as its fields, variants and methods. Inner attributes like a module's `//!` docs are
+/// removed along with outer ones. Attributes inside function bodies are left alone
+pub(crate) fn strip_attributes(item: &Item, docs: bool, attrs: bool) -> Item {
+    let mut item = item.clone();
+    AttributeStripper { docs, attrs }.visit_item_mut(&mut item);
+    item
+}
+
+/// Rewrite a function's type parameters into `&dyn Trait` parameters, to show it with
+/// dynamic dispatch. Only a type parameter with a single trait bound, used once as the whole
+/// type of a parameter (`r: T`, `r: &T` or `r: &mut T`) and nowhere else, is rewritten; a
+/// by-value `mut r: T` becomes `r: &mut dyn Trait`. Returns the rewritten function and a note
+/// for each type parameter left generic
+pub(crate) fn dyn_ify(function: &ItemFn) -> (ItemFn, Vec<String>) {
+    let mut function = function.clone();
+    let mut notes = Vec::new();
+    let params = std::mem::take(&mut function.sig.generics.params);
+    for param in &params {
+        let GenericParam::Type(type_param) = param else {
+            function.sig.generics.params.push(param.clone());
+            continue;
+        };
+        // The other parameters' bounds may refer to this one too
+        let others = params
+            .iter()
+            .filter(|other| *other != param)
+            .map(|other| other.to_token_stream())
+            .collect();
+        match dyn_ify_param(&mut function, type_param, others) {
+            Ok(()) => {}
+            Err(reason) => {
+                notes.push(format!(
+                    "dyn_ify: `{}` is left generic, as {}",
+                    type_param.ident, reason
+                ));
+                function.sig.generics.params.push(param.clone());
             }
+        }
+    }
+    if function.sig.generics.params.is_empty() {
+        function.sig.generics.lt_token = None;
+        function.sig.generics.gt_token = None;
+    }
+    (function, notes)
+}
 
-            let trimmed_line = if line.len() >= 4 { &line[4..] } else { line };
+/// Replace the one parameter of type `T`, `&T` or `&mut T` with a trait object, or say why not
+fn dyn_ify_param(
+    function: &mut ItemFn,
+    type_param: &TypeParam,
+    other_params: proc_macro2::TokenStream,
+) -> Result<(), &'static str> {
+    let bound = match type_param.bounds.iter().collect::<Vec<_>>().as_slice() {
+        [] => return Err("it has no trait bound"),
+        [TypeParamBound::Trait(bound)]
+            if matches!(bound.modifier, TraitBoundModifier::None) && bound.lifetimes.is_none() =>
+        {
+            bound.clone()
+        }
+        [_] => return Err("its bound isn't a plain trait"),
+        _ => return Err("it has more than one bound"),
+    };
+    if type_param.default.is_some() {
+        return Err("it has a default");
+    }
+
+    let ident = &type_param.ident;
+    let sig = &mut function.sig;
+    let elsewhere = count_ident(other_params, ident)
+        + count_ident(sig.generics.where_clause.to_token_stream(), ident)
+        + count_ident(sig.output.to_token_stream(), ident)
+        + count_ident(function.block.to_token_stream(), ident);
+    if elsewhere > 0 {
+        return Err("it's used outside of the parameters");
+    }
+    let mut uses = sig.inputs.iter_mut().filter_map(|input| match input {
+        FnArg::Typed(pat_type) if count_ident(pat_type.ty.to_token_stream(), ident) > 0 => {
+            Some(pat_type)
+        }
+        _ => None,
+    });
+    let (Some(pat_type), None) = (uses.next(), uses.next()) else {
+        return Err("it's used by more than one parameter");
+    };
+    if count_ident(pat_type.ty.to_token_stream(), ident) > 1 {
+        return Err("it's used more than once in its parameter");
+    }
 
-            if trimmed_line.trim() == "// DISPLAY START" {
-                display_started = true;
-                continue; // Skip the DISPLAY START line itself
-            } else if trimmed_line.trim() == "// DISPLAY END" {
-                display_ended = true;
-                continue; // Skip the DISPLAY END line itself
+    let is_param = |ty: &Type| matches!(ty, Type::Path(path) if path.qself.is_none() && path.path.is_ident(ident));
+    let mutable = match &*pat_type.ty {
+        ty if is_param(ty) => match &mut *pat_type.pat {
+            // A parameter taken by value and mutated is borrowed mutably instead
+            Pat::Ident(pat_ident) if pat_ident.mutability.is_some() => {
+                pat_ident.mutability = None;
+                true
             }
+            _ => false,
+        },
+        Type::Reference(reference) if is_param(&reference.elem) => reference.mutability.is_some(),
+        _ => return Err("it's used inside another type"),
+    };
+    pat_type.ty = Box::new(if mutable {
+        parse_quote!(&mut dyn #bound)
+    } else {
+        parse_quote!(&dyn #bound)
+    });
+    Ok(())
+}
 
-            let should_hide =
-                (has_display_start && !display_started) || (has_display_end && display_ended);
+/// Count the occurrences of an identifier in some tokens, including inside groups
+fn count_ident(tokens: proc_macro2::TokenStream, ident: &syn::Ident) -> usize {
+    tokens
+        .into_iter()
+        .map(|token| match token {
+            proc_macro2::TokenTree::Ident(found) => usize::from(&found == ident),
+            proc_macro2::TokenTree::Group(group) => count_ident(group.stream(), ident),
+            _ => 0,
+        })
+        .sum()
+}
 
-            if should_hide {
-                // Add as hidden line
-                if trimmed_line.trim().is_empty() {
-                    result.push_str("# \n");
-                } else {
-                    result.push_str(&format!("# {}\n", trimmed_line));
-                }
+/// A visitor that removes doc comments and other attributes
+struct AttributeStripper {
+    docs: bool,
+    attrs: bool,
+}
+
+impl AttributeStripper {
+    fn strip(&self, attrs: &mut Vec<Attribute>) {
+        attrs.retain(|attr| {
+            if attr.path().is_ident("doc") {
+                !self.docs
             } else {
-                // Add as visible line
-                result.push_str(&format!("{}\n", trimmed_line));
+                !self.attrs
             }
-        }
+        });
+    }
+}
 
-        // Remove trailing newline if present
-        if result.ends_with('\n') {
-            result.pop();
-        }
+impl VisitMut for AttributeStripper {
+    fn visit_item_mut(&mut self, item: &mut Item) {
+        let attrs = match item {
+            Item::Const(item) => &mut item.attrs,
+            Item::Enum(item) => &mut item.attrs,
+            Item::Fn(item) => &mut item.attrs,
+            Item::Impl(item) => &mut item.attrs,
+            Item::Macro(item) => &mut item.attrs,
+            Item::Mod(item) => &mut item.attrs,
+            Item::Static(item) => &mut item.attrs,
+            Item::Struct(item) => &mut item.attrs,
+            Item::Trait(item) => &mut item.attrs,
+            Item::Type(item) => &mut item.attrs,
+            Item::Union(item) => &mut item.attrs,
+            Item::Use(item) => &mut item.attrs,
+            _ => return visit_mut::visit_item_mut(self, item),
+        };
+        self.strip(attrs);
+        visit_mut::visit_item_mut(self, item);
+    }
 
-        result
+    fn visit_field_mut(&mut self, field: &mut Field) {
+        self.strip(&mut field.attrs);
+        visit_mut::visit_field_mut(self, field);
+    }
+
+    fn visit_variant_mut(&mut self, variant: &mut Variant) {
+        self.strip(&mut variant.attrs);
+        visit_mut::visit_variant_mut(self, variant);
+    }
+
+    fn visit_impl_item_mut(&mut self, impl_item: &mut ImplItem) {
+        let attrs = match impl_item {
+            ImplItem::Const(item) => &mut item.attrs,
+            ImplItem::Fn(item) => &mut item.attrs,
+            ImplItem::Type(item) => &mut item.attrs,
+            ImplItem::Macro(item) => &mut item.attrs,
+            _ => return visit_mut::visit_impl_item_mut(self, impl_item),
+        };
+        self.strip(attrs);
+        visit_mut::visit_impl_item_mut(self, impl_item);
+    }
+
+    fn visit_trait_item_mut(&mut self, trait_item: &mut TraitItem) {
+        let attrs = match trait_item {
+            TraitItem::Const(item) => &mut item.attrs,
+            TraitItem::Fn(item) => &mut item.attrs,
+            TraitItem::Type(item) => &mut item.attrs,
+            TraitItem::Macro(item) => &mut item.attrs,
+            _ => return visit_mut::visit_trait_item_mut(self, trait_item),
+        };
+        self.strip(attrs);
+        visit_mut::visit_trait_item_mut(self, trait_item);
+    }
+
+    fn visit_block_mut(&mut self, _block: &mut Block) {
+        // Attributes in a body, like `#[cfg]` on a statement, change what the code does
+    }
+}
+
+/// Format the line-by-line difference between two versions of some code as a `diff` block
+/// Either side may be empty, in which case every line is an addition or removal
+pub(crate) fn format_diff(old: &str, new: &str) -> String {
+    format_fence("diff", &format_line_diff(old, new))
+}
+
+/// Format the line-by-line difference between two texts, with a `-`, `+` or space before each line
+pub(crate) fn format_line_diff(old: &str, new: &str) -> String {
+    let old = if old.is_empty() {
+        String::new()
     } else {
-        panic!("Expected Item::Fn, got {:?}", fn_item);
+        format!("{}\n", old)
+    };
+    let new = if new.is_empty() {
+        String::new()
+    } else {
+        format!("{}\n", new)
+    };
+
+    let mut result = String::new();
+    for change in TextDiff::from_lines(&old, &new).iter_all_changes() {
+        let sign = match change.tag() {
+            ChangeTag::Delete => "-",
+            ChangeTag::Insert => "+",
+            ChangeTag::Equal => " ",
+        };
+        result.push_str(&format!("{}{}", sign, change.value()));
     }
+    result.trim_end_matches('\n').to_string()
 }
 
-/// Format content with a # prefix for hidden code
-pub fn format_hidden(content: &str) -> String {
+/// Format the generic parameters and where clause of an item
+/// Returns `None` if the item has neither
+pub(crate) fn format_generics(generics: &Generics) -> Option<String> {
+    if generics.params.is_empty() && generics.where_clause.is_none() {
+        return None;
+    }
+
     let mut result = String::new();
-    for line in content.lines() {
-        if line.trim().is_empty() {
-            result.push_str("# \n");
+    if !generics.params.is_empty() {
+        let params = generics
+            .params
+            .iter()
+            .map(render_node)
+            .collect::<Vec<_>>()
+            .join(", ");
+        result.push_str(&format!("<{}>", params));
+    }
+    if let Some(where_clause) = &generics.where_clause {
+        if !result.is_empty() {
+            result.push('\n');
+        }
+        result.push_str("where");
+        for predicate in &where_clause.predicates {
+            result.push_str(&format!("\n    {},", render_node(predicate)));
+        }
+    }
+    Some(result)
+}
+
+/// A short hash of some code, stable across runs and platforms (a truncated 64-bit FNV-1a)
+pub(crate) fn fingerprint(code: &str) -> String {
+    let hash = code.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    });
+    format!("{:08x}", hash >> 32)
+}
+
+/// Wrap code in a fenced code block with the given info string
+/// The fence is longer than any run of backticks in the code, so a fence in it can't close it
+pub(crate) fn format_fence(info: &str, code: &str) -> String {
+    let longest = code
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or_default();
+    let fence = "`".repeat((longest + 1).max(3));
+    format!("{}{}\n{}\n{}", fence, info, code, fence)
+}
+
+/// Format a macro invocation and its expansion as two labeled code blocks
+pub(crate) fn format_macro_expansion(invocation: &str, expansion: &str, info: &str) -> String {
+    format!(
+        "*Invocation*\n\n{}\n\n*Expands to*\n\n{}",
+        format_fence(info, invocation),
+        format_fence(info, expansion)
+    )
+}
+
+/// Format rendered tabs as a group of `<details>` blocks for the HTML renderer, the first one open
+pub(crate) fn format_tabs(tabs: &[(String, String)]) -> String {
+    tabs.iter()
+        .enumerate()
+        .map(|(i, (label, content))| format_details(label, content, i == 0))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Format a collapsible `<details>` block for the HTML renderer
+/// The Markdown shown inside it is kept apart from the HTML by blank lines so mdbook still
+/// renders it
+pub(crate) fn format_details(summary: &str, content: &str, open: bool) -> String {
+    format!(
+        "<details{}>\n<summary>{}</summary>\n\n{}\n\n</details>",
+        if open { " open" } else { "" },
+        escape_html(summary),
+        content
+    )
+}
+
+/// Format code as an HTML code block for the HTML renderer, showing each run of hidden lines
+/// dimmed in a `<span class="include-rs-dim">` rather than hiding it
+/// The block isn't highlighted, as highlight.js would drop the spans
+pub(crate) fn format_focus(lang: &str, code: &str, hide_prefix: Option<&str>) -> String {
+    let mut body = String::new();
+    let mut dimmed = false;
+    for line in code.lines() {
+        let hidden = hidden_code(line, hide_prefix);
+        match (hidden, dimmed) {
+            (Some(_), false) => body.push_str("<span class=\"include-rs-dim\">"),
+            (None, true) => body.push_str("</span>"),
+            _ => {}
+        }
+        dimmed = hidden.is_some();
+        body.push_str(&escape_html(hidden.unwrap_or(line)));
+        body.push('\n');
+    }
+    format!(
+        "<pre><code class=\"nohighlight language-{}\">{}{}</code></pre>",
+        escape_html(lang),
+        body.trim_end_matches('\n'),
+        if dimmed { "</span>" } else { "" }
+    )
+}
+
+/// Escape text for use in HTML
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Translate a highlight spec like `3,5-7` into fence attributes, one `hl_lines=` per range
+///
+/// The spec counts only the visible lines of the snippet, as the reader sees it. The
+/// attributes give positions in the emitted snippet, where hidden `# ` lines still count.
+pub(crate) fn format_highlight_attrs(
+    snippet: &str,
+    spec: &str,
+    hide_prefix: Option<&str>,
+) -> anyhow::Result<String> {
+    let visible_lines = visible_line_numbers(snippet, hide_prefix);
+    let to_emitted = |n: usize| -> anyhow::Result<usize> {
+        n.checked_sub(1)
+            .and_then(|i| visible_lines.get(i).copied())
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Highlighted line {} is out of range, the snippet has {} visible lines",
+                    n,
+                    visible_lines.len()
+                )
+            })
+    };
+
+    let mut attrs = String::new();
+    for range in spec.split(',').map(str::trim).filter(|r| !r.is_empty()) {
+        let (start, end) = range.split_once('-').unwrap_or((range, range));
+        let parse = |n: &str| {
+            n.trim()
+                .parse::<usize>()
+                .map_err(|_| anyhow::anyhow!("Invalid highlight range '{}'", range))
+        };
+        let (start, end) = (parse(start)?, parse(end)?);
+        if start > end {
+            anyhow::bail!("Invalid highlight range '{}'", range);
+        }
+        let (start, end) = (to_emitted(start)?, to_emitted(end)?);
+        if start == end {
+            attrs.push_str(&format!(",hl_lines={}", start));
         } else {
-            result.push_str(&format!("# {}\n", line));
+            attrs.push_str(&format!(",hl_lines={}-{}", start, end));
         }
     }
+    Ok(attrs)
+}
+
+/// Append an error comment like `// error[E0382]: moved value` to lines of a snippet
+///
+/// The spec is a `;` separated list of `line: message` annotations, where the line counts
+/// only the visible lines of the snippet and the message may start with an error code.
+pub(crate) fn format_error_annotations(
+    snippet: &str,
+    spec: &str,
+    hide_prefix: Option<&str>,
+) -> anyhow::Result<String> {
+    let visible_lines = visible_line_numbers(snippet, hide_prefix);
+    let mut lines = snippet.lines().map(str::to_string).collect::<Vec<_>>();
+
+    for annotation in spec.split(';').map(str::trim).filter(|a| !a.is_empty()) {
+        let (line, message) = annotation
+            .split_once(':')
+            .and_then(|(line, message)| Some((line.trim().parse::<usize>().ok()?, message.trim())))
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Invalid error annotation '{}', expected \"line: message\"",
+                    annotation
+                )
+            })?;
+        let emitted = line
+            .checked_sub(1)
+            .and_then(|i| visible_lines.get(i).copied())
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Annotated line {} is out of range, the snippet has {} visible lines",
+                    line,
+                    visible_lines.len()
+                )
+            })?;
+
+        // A leading error code like `E0382` goes in brackets, as rustc prints it
+        let comment = match message.split_once(' ') {
+            Some((code, rest)) if is_error_code(code) => format!("// error[{}]: {}", code, rest),
+            _ if is_error_code(message) => format!("// error[{}]", message),
+            _ => format!("// error: {}", message),
+        };
+        let target = &mut lines[emitted - 1];
+        target.push_str("  ");
+        target.push_str(&comment);
+    }
+
+    Ok(lines.join("\n"))
+}
+
+/// Whether a word is a compiler error code like `E0382`
+fn is_error_code(word: &str) -> bool {
+    word.len() == 5 && word.starts_with('E') && word[1..].chars().all(|c| c.is_ascii_digit())
+}
+
+/// The emitted line number of each visible line of a snippet, skipping hidden lines
+fn visible_line_numbers(snippet: &str, hide_prefix: Option<&str>) -> Vec<usize> {
+    snippet
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| hidden_code(line, hide_prefix).is_none())
+        .map(|(i, _)| i + 1)
+        .collect()
+}
+
+/// The code of a line if it's hidden, starting with the hidden-line prefix or being just the
+/// prefix of a blank line, and `None` if it's visible or nothing is hidden
+pub(crate) fn hidden_code<'a>(line: &'a str, hide_prefix: Option<&str>) -> Option<&'a str> {
+    let prefix = hide_prefix?;
+    line.strip_prefix(prefix)
+        .or_else(|| (line == prefix.trim_end()).then_some(""))
+}
+
+/// Format a rendered trait implementation after an `// impl for Type` comment naming the type
+pub(crate) fn format_implementor(item_impl: &ItemImpl, rendered: &str) -> String {
+    format!(
+        "// impl for {}\n{}",
+        render_node(&*item_impl.self_ty),
+        rendered
+    )
+}
+
+/// Render a syntax node from its original source text, falling back to its tokens
+fn render_node<T: Spanned + ToTokens>(node: &T) -> String {
+    node.span()
+        .source_text()
+        .unwrap_or_else(|| node.to_token_stream().to_string())
+}
+
+/// Format a const whose value is an array (or slice) of tuples as a Markdown table
+/// Returns `None` if the initializer doesn't have a tabular shape
+pub(crate) fn format_const_table(item_const: &ItemConst) -> Option<String> {
+    // Look through a leading `&` so slice consts like `&[(..), (..)]` are handled too
+    let mut expr = &*item_const.expr;
+    if let Expr::Reference(reference) = expr {
+        expr = &reference.expr;
+    }
+    let Expr::Array(array) = expr else {
+        return None;
+    };
+
+    let rows = array
+        .elems
+        .iter()
+        .map(|elem| match elem {
+            Expr::Tuple(tuple) => Some(tuple.elems.iter().map(format_table_cell).collect()),
+            _ => None,
+        })
+        .collect::<Option<Vec<Vec<String>>>>()?;
+    let columns = rows.first()?.len();
+    if columns == 0 || rows.iter().any(|row| row.len() != columns) {
+        return None;
+    }
+
+    let mut result = String::new();
+    let headers = table_headers(&item_const.ty, columns);
+    result.push_str(&format!("| {} |\n", headers.join(" | ")));
+    result.push_str(&format!("|{}\n", " --- |".repeat(columns)));
+    for row in rows {
+        result.push_str(&format!("| {} |\n", row.join(" | ")));
+    }
+    Some(result)
+}
+
+/// Format a struct's fields as a Markdown table of each field's name, type and doc comment
+/// A doc comment spanning several lines is joined into one cell, and undocumented fields have
+/// an empty description
+pub(crate) fn format_struct_doc_table(item_struct: &ItemStruct) -> String {
+    if item_struct.fields.is_empty() {
+        return format!("`{}` has no fields.", item_struct.ident);
+    }
+
+    let mut result = String::from("| Field | Type | Description |\n| --- | --- | --- |\n");
+    for (index, field) in item_struct.fields.iter().enumerate() {
+        let name = match &field.ident {
+            Some(ident) => ident.to_string(),
+            None => index.to_string(),
+        };
+        let description = format_doc_comment(&field.attrs)
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        result.push_str(&format!(
+            "| `{}` | `{}` | {} |\n",
+            name,
+            render_node(&field.ty).replace('|', "\\|"),
+            description.replace('|', "\\|")
+        ));
+    }
     result
 }
 
+/// Column headers for a const table, taken from the tuple element types when available
+fn table_headers(ty: &Type, columns: usize) -> Vec<String> {
+    let mut ty = ty;
+    if let Type::Reference(reference) = ty {
+        ty = &reference.elem;
+    }
+    let elem = match ty {
+        Type::Array(array) => &*array.elem,
+        Type::Slice(slice) => &*slice.elem,
+        _ => ty,
+    };
+    match elem {
+        Type::Tuple(tuple) if tuple.elems.len() == columns => tuple
+            .elems
+            .iter()
+            .map(|ty| format!("`{}`", render_node(ty)))
+            .collect(),
+        _ => (1..=columns).map(|i| format!("Column {}", i)).collect(),
+    }
+}
+
+/// Format a single tuple element as a Markdown table cell
+fn format_table_cell(expr: &Expr) -> String {
+    let text = match expr {
+        Expr::Lit(lit) => match &lit.lit {
+            Lit::Str(s) => s.value(),
+            Lit::Char(c) => c.value().to_string(),
+            _ => render_node(expr),
+        },
+        _ => render_node(expr),
+    };
+    text.replace('|', "\\|")
+}
+
+/// The hidden `fn main` that an extracted body is wrapped in so it compiles on its own
+/// The body of an `async fn` is also wrapped in an `async` block run by an async runtime
+#[derive(Default)]
+pub(crate) struct MainWrapper {
+    /// The return type of `main`, if it isn't `()`
+    return_type: Option<String>,
+    /// The function that runs an `async` body to completion, if it isn't Tokio's
+    block_on: Option<String>,
+}
+
+/// Runs an `async` body on a new Tokio runtime, unless another runtime is chosen
+const TOKIO_BLOCK_ON: &str = "tokio::runtime::Runtime::new().unwrap().block_on";
+
+impl MainWrapper {
+    /// A wrapper returning the given type, which must be `()` or a `Result`
+    pub(crate) fn new(return_type: &str) -> anyhow::Result<Self> {
+        let ty = syn::parse_str::<Type>(return_type)
+            .map_err(|e| anyhow::anyhow!("Invalid wrapper_return '{}': {}", return_type, e))?;
+        match &ty {
+            Type::Tuple(tuple) if tuple.elems.is_empty() => Ok(Self::default()),
+            Type::Path(path)
+                if path
+                    .path
+                    .segments
+                    .last()
+                    .is_some_and(|seg| seg.ident == "Result") =>
+            {
+                Ok(Self {
+                    return_type: Some(return_type.trim().to_string()),
+                    ..Self::default()
+                })
+            }
+            _ => Err(anyhow::anyhow!(
+                "Invalid wrapper_return '{}', expected () or a Result type",
+                return_type
+            )),
+        }
+    }
+
+    /// Run `async` bodies with the given runtime: `tokio`, `futures`, `async-std`, or the
+    /// path of any function that takes a future and blocks on it
+    pub(crate) fn with_async_runtime(self, runtime: &str) -> anyhow::Result<Self> {
+        let block_on = match runtime.trim() {
+            "tokio" => TOKIO_BLOCK_ON.to_string(),
+            "futures" => String::from("futures::executor::block_on"),
+            "async-std" => String::from("async_std::task::block_on"),
+            path => {
+                syn::parse_str::<syn::Path>(path).map_err(|_| {
+                    anyhow::anyhow!(
+                        "Invalid async_runtime '{}', expected tokio, futures, async-std or the path of a block_on function",
+                        runtime
+                    )
+                })?;
+                path.to_string()
+            }
+        };
+        Ok(Self {
+            block_on: Some(block_on),
+            ..self
+        })
+    }
+
+    /// The hidden lines that open the wrapper, before the body
+    fn opening(&self, is_async: bool) -> String {
+        let mut opening = match &self.return_type {
+            Some(return_type) => format!("fn main() -> {} {{", return_type),
+            None => String::from("fn main() {"),
+        };
+        if is_async {
+            let block_on = self.block_on.as_deref().unwrap_or(TOKIO_BLOCK_ON);
+            opening.push_str(&format!("\n{}(async {{", block_on));
+        }
+        opening
+    }
+
+    /// The hidden lines that close the wrapper, after the body
+    fn closing(&self, is_async: bool) -> &'static str {
+        if is_async { "})\n}" } else { "}" }
+    }
+
+    /// The hidden line that ends the body before the closing brace, given its last line
+    /// A `Result` wrapper needs an `Ok(())`, unless the body already ends with one
+    fn trailer(&self, last_line: &str) -> &'static str {
+        match &self.return_type {
+            Some(_) if last_line.trim() != "Ok(())" => "Ok(())",
+            _ => "",
+        }
+    }
+}
+
+/// Format a function body as a string
+/// It will always replace the signature with the wrapper's `fn main`, which runs the body
+/// of an `async fn` in an async runtime so it can `.await`
+/// It will always prefix the first and last lines with `# `
+/// If the body has the comments:
+/// * `// DISPLAY START` - This line and any before are prefixed with `# `
+/// * `// DISPLAY END` - This line and any after are prefixed with `# `
+///
+/// With `trim_blanks`, blank lines at the start and end of the body are left out
+pub(crate) fn format_function_body(
+    fn_item: &Item,
+    wrapper: &MainWrapper,
+    config: &Config,
+) -> anyhow::Result<String> {
+    let Item::Fn(function) = fn_item else {
+        anyhow::bail!("Expected a function, got {}", fn_item.to_token_stream());
+    };
+    Ok(format_body(
+        &source_text(&function.block)?,
+        wrapper,
+        function.sig.asyncness.is_some(),
+        config,
+    ))
+}
+
+/// Format content as hidden code, prefixing each line with the hidden-line prefix, or leave
+/// it out when there's no prefix, as hidden lines can't be hidden in that fence
+pub fn format_hidden(content: &str, prefix: Option<&str>) -> String {
+    content
+        .lines()
+        .map(|line| hidden_line(line, prefix))
+        .collect()
+}
+
+/// Format a single line of hidden code, with its newline
+fn hidden_line(line: &str, prefix: Option<&str>) -> String {
+    match prefix {
+        None => String::new(),
+        // A blank line is just the prefix, as mdBook trims it to `#`
+        Some(prefix) if line.trim().is_empty() => format!("{}\n", prefix),
+        Some(prefix) => format!("{}{}\n", prefix, line),
+    }
+}
+
 /// Format content without a prefix for visible code
 pub fn format_visible(content: &str) -> String {
     let mut result = String::new();
@@ -143,25 +880,523 @@ pub fn format_visible(content: &str) -> String {
 }
 
 /// Format a method as a string
-pub fn format_method(method: &ImplItemFn) -> String {
-    let source_text = method
-        .span()
-        .source_text()
-        .expect("Failed to get source text");
-    dedent(&source_text)
+pub fn format_method(method: &ImplItemFn) -> anyhow::Result<String> {
+    Ok(dedent(&source_text(method)?))
+}
+
+/// Format an enum variant as written, with its doc comments and attributes
+pub(crate) fn format_variant(variant: &Variant) -> anyhow::Result<String> {
+    Ok(dedent(&source_text(variant)?))
+}
+
+/// Format a struct field as written, with its doc comments, attributes and visibility
+pub(crate) fn format_field(field: &Field) -> anyhow::Result<String> {
+    Ok(dedent(&source_text(field)?))
+}
+
+/// Format an associated const or type of an impl block as written
+pub(crate) fn format_impl_item(item: &ImplItem) -> anyhow::Result<String> {
+    Ok(dedent(&source_text(item)?))
+}
+
+/// Wrap code in declarations of the modules it's in, outermost first, indenting it to match
+pub(crate) fn format_in_modules(code: &str, modules: &[&ItemMod]) -> String {
+    modules.iter().rev().fold(code.to_string(), |code, module| {
+        let vis = render_node(&module.vis);
+        let vis = if vis.is_empty() {
+            String::new()
+        } else {
+            format!("{} ", vis)
+        };
+        let body = code
+            .lines()
+            .map(|line| {
+                if line.is_empty() {
+                    String::new()
+                } else {
+                    format!("    {}", line)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        format!("{}mod {} {{\n{}\n}}", vis, module.ident, body)
+    })
+}
+
+/// Format a function as literate Markdown: its doc comment as prose, followed by its body as
+/// code. Any `///` narration between the statements of the body becomes prose too, splitting
+/// the body into separate code blocks.
+pub(crate) fn format_literate(attrs: &[Attribute], block: &Block, info: &str) -> String {
+    let mut sections = Vec::new();
+    let docs = format_doc_comment(attrs);
+    if !docs.is_empty() {
+        sections.push(docs);
+    }
+
+    // The lines between the braces of the body
+    let source = render_node(block);
+    let lines = source.lines().collect::<Vec<_>>();
+    let body = match lines.as_slice() {
+        [line] => vec![
+            line.trim()
+                .trim_start_matches('{')
+                .trim_end_matches('}')
+                .trim(),
+        ],
+        [_, body @ .., _] => body.to_vec(),
+        _ => Vec::new(),
+    };
+    let indent = body
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| indent_width(line))
+        .min()
+        .unwrap_or(0);
+
+    let mut prose: Vec<&str> = Vec::new();
+    let mut code: Vec<&str> = Vec::new();
+    let flush_code = |code: &mut Vec<&str>, sections: &mut Vec<String>| {
+        let text = code.join("\n");
+        let text = text.trim_matches('\n');
+        if !text.trim().is_empty() {
+            sections.push(format_fence(info, text));
+        }
+        code.clear();
+    };
+    for line in body {
+        match line.trim().strip_prefix("///") {
+            Some(text) => {
+                flush_code(&mut code, &mut sections);
+                prose.push(text.strip_prefix(' ').unwrap_or(text));
+            }
+            None => {
+                if !prose.is_empty() {
+                    sections.push(prose.join("\n"));
+                    prose.clear();
+                }
+                code.push(strip_indent(line, indent));
+            }
+        }
+    }
+    flush_code(&mut code, &mut sections);
+    if !prose.is_empty() {
+        sections.push(prose.join("\n"));
+    }
+
+    sections.join("\n\n")
+}
+
+/// The text of the doc comments among some attributes, one line per comment line
+fn format_doc_comment(attrs: &[Attribute]) -> String {
+    attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(name_value) => match &name_value.value {
+                Expr::Lit(expr_lit) => match &expr_lit.lit {
+                    Lit::Str(text) => Some(text.value()),
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        })
+        .map(|line| line.strip_prefix(' ').map(str::to_string).unwrap_or(line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Format the associated types of an impl block, one `type Name = Type;` per line
+/// Returns `None` if the impl has no associated types
+pub(crate) fn format_assoc_types(item_impl: &ItemImpl) -> Option<String> {
+    let types = item_impl
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            ImplItem::Type(item_type) => Some(dedent(&render_node(item_type))),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    (!types.is_empty()).then(|| types.join("\n"))
+}
+
+/// Format `let` bindings as a summary, one `let pattern: Type` per line
+/// Bindings without a type annotation are shown with `_` as their type
+pub(crate) fn format_locals(locals: &[Local]) -> String {
+    locals
+        .iter()
+        .map(|local| match &local.pat {
+            Pat::Type(pat_type) => format!(
+                "let {}: {}",
+                render_node(&pat_type.pat),
+                render_node(&pat_type.ty)
+            ),
+            pat => format!("let {}: _", render_node(pat)),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Format how a function's `match` expressions cover an enum as a Markdown list, one entry per
+/// variant in declaration order
+pub(crate) fn format_match_coverage(
+    enum_name: &str,
+    variants: &[String],
+    coverage: &MatchCoverage,
+) -> String {
+    variants
+        .iter()
+        .map(|variant| {
+            let status = if coverage.handled.contains(variant) {
+                "handled"
+            } else if coverage.wildcard {
+                "caught by `_`"
+            } else {
+                "not handled"
+            };
+            format!("- `{}::{}`: {}", enum_name, variant, status)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Format a skeleton implementation of a trait for readers to fill in, with a `todo!()` body
+/// for each required method and a placeholder for each required associated type. Provided
+/// methods are listed commented out, for readers who want to override them
+pub(crate) fn format_trait_stub(item_trait: &ItemTrait) -> String {
+    let items = item_trait
+        .items
+        .iter()
+        .filter_map(|trait_item| match trait_item {
+            TraitItem::Fn(method) => {
+                let sig = &method.sig;
+                // Provided methods are marked, to be commented out once rendered
+                let marker = method.default.is_some().then(|| quote!(#[__provided]));
+                Some(quote! {
+                    #marker
+                    #sig {
+                        todo!()
+                    }
+                })
+            }
+            TraitItem::Const(item_const) if item_const.default.is_none() => {
+                let (ident, ty) = (&item_const.ident, &item_const.ty);
+                Some(quote!(const #ident: #ty = todo!();))
+            }
+            TraitItem::Type(item_type) if item_type.default.is_none() => {
+                let (ident, generics) = (&item_type.ident, &item_type.generics);
+                Some(quote!(type #ident #generics = __YourAssocType;))
+            }
+            _ => None,
+        });
+    let ident = &item_trait.ident;
+    let (impl_generics, ty_generics, where_clause) = item_trait.generics.split_for_impl();
+    let stub = quote! {
+        impl #impl_generics #ident #ty_generics for __YourType #where_clause {
+            #(#items)*
+        }
+    };
+    let rendered = match syn::parse2::<Item>(stub) {
+        Ok(item) => format_item_pretty(&item),
+        Err(_) => return format!("impl {} for /* YourType */ {{}}", ident),
+    };
+
+    let mut lines: Vec<String> = Vec::new();
+    let mut provided = false;
+    for line in rendered.lines() {
+        if line.trim() == "#[__provided]" {
+            provided = true;
+            continue;
+        }
+        // Methods are separated by a blank line, as they usually are in an impl
+        let after_method = lines
+            .last()
+            .is_some_and(|last| last == "    }" || last == "    // }");
+        if after_method && line != "}" {
+            lines.push(String::new());
+        }
+        match line.strip_prefix("    ") {
+            Some(rest) if provided => {
+                lines.push(format!("    // {}", rest));
+                // The method ends with the closing brace at its own indentation
+                provided = rest != "}";
+            }
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+        .join("\n")
+        .replace("__YourType", "/* YourType */")
+        .replace("__YourAssocType", "/* Type */")
+}
+
+/// Format an impl block with each method's body replaced by `;`, leaving its signatures,
+/// doc comments and attributes, along with associated consts and types, as written
+pub(crate) fn format_impl_summary(item_impl: &ItemImpl) -> anyhow::Result<String> {
+    let mut source = source_text(item_impl)?;
+    // Each body is found after its signature, so an identical body elsewhere isn't cut
+    let mut cursor = 0;
+    for impl_item in &item_impl.items {
+        let ImplItem::Fn(method) = impl_item else {
+            continue;
+        };
+        let (Some(sig), Some(block)) = (
+            method.sig.span().source_text(),
+            method.block.span().source_text(),
+        ) else {
+            continue;
+        };
+        let Some(sig_end) = source[cursor..]
+            .find(&sig)
+            .map(|start| cursor + start + sig.len())
+        else {
+            continue;
+        };
+        let Some(block_end) = source[sig_end..]
+            .find(&block)
+            .map(|start| sig_end + start + block.len())
+        else {
+            continue;
+        };
+        // A where clause's trailing comma would otherwise come right before the `;`
+        let sig_end = sig_end - usize::from(source[..sig_end].ends_with(','));
+        source.replace_range(sig_end..block_end, ";");
+        cursor = sig_end + 1;
+    }
+    Ok(dedent(&source))
+}
+
+/// Prefix each line of code with its line number in the original file, padded on the right
+/// to align, and a `|` separator. Hidden lines keep their prefix in front, so they stay hidden
+pub(crate) fn format_numbered(code: &str, numbers: &[usize], hide_prefix: Option<&str>) -> String {
+    let width = numbers.iter().max().unwrap_or(&0).to_string().len();
+    code.lines()
+        .zip(numbers)
+        .map(|(line, number)| {
+            let trimmed = line.trim_start();
+            let (hidden, line) = match (hidden_code(trimmed, hide_prefix), hide_prefix) {
+                (Some(rest), Some(prefix)) => (
+                    prefix,
+                    format!("{}{}", &line[..line.len() - trimmed.len()], rest),
+                ),
+                _ => ("", line.to_string()),
+            };
+            format!("{}{:<width$} | {}", hidden, number, line)
+                .trim_end()
+                .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Format a `#[test]` function that calls a function with the given arguments, so a reader
+/// can run it. A returned value is ignored, as the test only checks the call doesn't panic
+pub(crate) fn format_test_harness(function: &ItemFn, args: proc_macro2::TokenStream) -> String {
+    let ident = &function.sig.ident;
+    let test_ident = format_ident!("test_{}", ident);
+    let call = match function.sig.output {
+        ReturnType::Default => quote!(#ident(#args);),
+        ReturnType::Type(..) => quote!(let _ = #ident(#args);),
+    };
+    let harness: ItemFn = parse_quote! {
+        #[test]
+        fn #test_ident() {
+            #call
+        }
+    };
+    format_item_pretty(&Item::Fn(harness))
+}
+
+/// Format an impl block whose items have been filtered, keeping its header as written and
+/// each remaining item as written, separated by blank lines
+pub(crate) fn format_impl_subset(item_impl: &ItemImpl) -> anyhow::Result<String> {
+    // The impl's span still covers all of its original items, so the header is cut at the
+    // brace that opens the body
+    let source = source_text(item_impl)?;
+    let start = item_impl.span().start();
+    let open = item_impl.brace_token.span.open().start();
+    let header = source
+        .lines()
+        .take(open.line - start.line + 1)
+        .enumerate()
+        .map(|(index, line)| {
+            let column = if index == 0 { start.column } else { 0 };
+            if index == open.line - start.line {
+                line.chars().take(open.column - column + 1).collect()
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+    let items = item_impl
+        .items
+        .iter()
+        .map(|item| {
+            Ok(dedent(&source_text(item)?)
+                .lines()
+                .map(|line| {
+                    if line.is_empty() {
+                        String::new()
+                    } else {
+                        format!("    {}", line)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n"))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(format!("{}\n{}\n}}", dedent(&header), items.join("\n\n")))
+}
+
+/// Format the implementations of a type as a Markdown list of the traits it implements,
+/// preceded by a note if it also has inherent methods
+pub(crate) fn format_impls_list(type_name: &str, impls: &[ItemImpl]) -> String {
+    if impls.is_empty() {
+        return format!("`{}` has no implementations in this file.", type_name);
+    }
+
+    let mut lines = Vec::new();
+    if impls.iter().any(|item_impl| item_impl.trait_.is_none()) {
+        lines.push(format!("- Inherent methods (`impl {}`)", type_name));
+    }
+    for (bang, path, _) in impls
+        .iter()
+        .filter_map(|item_impl| item_impl.trait_.as_ref())
+    {
+        let bang = if bang.is_some() { "!" } else { "" };
+        lines.push(format!("- `{}{}`", bang, render_node(path)));
+    }
+    lines.join("\n")
+}
+
+/// Format the places a type is used as a Markdown list, each with its line and whether the
+/// type appears in its signature, its body or both
+pub(crate) fn format_usages(type_name: &str, usages: &[Usage]) -> String {
+    if usages.is_empty() {
+        return format!(
+            "`{}` isn't used by any function, method or impl in this file.",
+            type_name
+        );
+    }
+
+    usages
+        .iter()
+        .map(|usage| {
+            let places = match (usage.in_signature, usage.in_body) {
+                (true, true) => "signature and body",
+                (true, false) if usage.name.starts_with("impl ") => "header",
+                (true, false) => "signature",
+                _ => "body",
+            };
+            format!("- `{}` (line {}): {}", usage.name, usage.line, places)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Format a function signature as written, without the indentation of its later lines
+pub(crate) fn format_signature(sig: &Signature) -> String {
+    dedent(&render_node(sig))
+}
+
+/// Format a function signature with `prettyplease`, to compare signatures however they're
+/// formatted
+pub(crate) fn format_signature_pretty(sig: &Signature) -> String {
+    format_item_pretty(&Item::Fn(ItemFn {
+        attrs: Vec::new(),
+        vis: Visibility::Inherited,
+        sig: sig.clone(),
+        block: Box::new(Block {
+            brace_token: Default::default(),
+            stmts: Vec::new(),
+        }),
+    }))
+}
+
+/// Format a function's return type as written on a single line, `()` if it has none
+pub(crate) fn format_return_type(output: &ReturnType) -> String {
+    match output {
+        ReturnType::Default => "()".to_string(),
+        ReturnType::Type(_, ty) => render_node(ty)
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
+
+/// Format a method signature as a comment, one `// ` line per source line
+pub(crate) fn format_signature_comment(sig: &Signature) -> String {
+    format!("{};", format_signature(sig))
+        .lines()
+        .map(|line| format!("// {}", line).trim_end().to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
 /// Format a method body as a string, similar to format_function_body
-pub fn format_method_body(method: &ImplItemFn) -> String {
-    let source_text = method
-        .span()
-        .source_text()
-        .expect("Failed to get source text");
+pub fn format_method_body(
+    method: &ImplItemFn,
+    wrapper: &MainWrapper,
+    config: &Config,
+) -> anyhow::Result<String> {
+    Ok(format_body(
+        &source_text(&method.block)?,
+        wrapper,
+        method.sig.asyncness.is_some(),
+        config,
+    ))
+}
+
+/// Format the default body of a trait method as a string, similar to format_function_body
+/// Returns `None` if the method has no default body
+pub(crate) fn format_trait_method_body(
+    method: &TraitItemFn,
+    wrapper: &MainWrapper,
+    config: &Config,
+) -> anyhow::Result<Option<String>> {
+    let Some(block) = &method.default else {
+        return Ok(None);
+    };
+    Ok(Some(format_body(
+        &source_text(block)?,
+        wrapper,
+        method.sig.asyncness.is_some(),
+        config,
+    )))
+}
+
+/// Format the source of a function's block as its body wrapped in the wrapper's `fn main`,
+/// hiding the wrapper and anything outside the display markers. Taking the block rather than
+/// the whole function keeps doc comments and attributes out of the body
+/// With `trim-body-blanks`, blank lines just inside the braces are dropped, keeping those
+/// between statements
+fn format_body(
+    source_text: &str,
+    wrapper: &MainWrapper,
+    is_async: bool,
+    config: &Config,
+) -> String {
+    let hide_prefix = config.hide_prefix.as_deref();
     let mut lines = source_text.split("\n").collect::<Vec<_>>();
     if lines.len() == 1 {
         return String::new();
     }
-    lines[0] = "fn main() {\n";
+    if config.trim_body_blanks {
+        let is_blank = |line: &&&str| line.trim().is_empty();
+        let end = lines.len() - 1;
+        let start = 1 + lines[1..end].iter().take_while(is_blank).count();
+        let stop = end - lines[start..end].iter().rev().take_while(is_blank).count();
+        lines = [&lines[..1], &lines[start..stop], &lines[end..]].concat();
+    }
+
+    // The body lines keep their indentation in the file, so remove what they have in common
+    let indent = lines[1..lines.len() - 1]
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| indent_width(line))
+        .min()
+        .unwrap_or(0);
 
     // Process display markers
     let mut result = String::new();
@@ -174,13 +1409,18 @@ pub fn format_method_body(method: &ImplItemFn) -> String {
 
     // Skip the function signature and closing brace
     for (i, line) in lines.iter().enumerate() {
-        // Skip the first and last line (fn main() and closing brace)
-        if i == 0 || i == lines.len() - 1 {
-            result.push_str(&format!("# {}\n", line.trim()));
+        // Replace the first and last line with the hidden wrapper
+        if i == 0 {
+            result.push_str(&format_hidden(&wrapper.opening(is_async), hide_prefix));
+            continue;
+        }
+        if i == lines.len() - 1 {
+            result.push_str(&format_hidden(wrapper.trailer(lines[i - 1]), hide_prefix));
+            result.push_str(&format_hidden(wrapper.closing(is_async), hide_prefix));
             continue;
         }
 
-        let trimmed_line = if line.len() >= 4 { &line[4..] } else { line };
+        let trimmed_line = strip_indent(line, indent);
 
         if trimmed_line.trim() == "// DISPLAY START" {
             display_started = true;
@@ -195,11 +1435,7 @@ pub fn format_method_body(method: &ImplItemFn) -> String {
 
         if should_hide {
             // Add as hidden line
-            if trimmed_line.trim().is_empty() {
-                result.push_str("# \n");
-            } else {
-                result.push_str(&format!("# {}\n", trimmed_line));
-            }
+            result.push_str(&hidden_line(trimmed_line, hide_prefix));
         } else {
             // Add as visible line
             result.push_str(&format!("{}\n", trimmed_line));
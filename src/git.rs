@@ -0,0 +1,96 @@
+use anyhow::{Context, Result, bail};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::rc::Rc;
+
+use crate::parser::get_relative_path;
+
+/// An included file that git doesn't track, with `require-tracked` on
+/// Like an output mismatch, this fails the build rather than being inlined
+#[derive(Debug)]
+pub(crate) struct UntrackedFile {
+    pub(crate) path: String,
+}
+
+impl fmt::Display for UntrackedFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} is not tracked by git, so it may be missing from a fresh checkout",
+            self.path
+        )
+    }
+}
+
+impl std::error::Error for UntrackedFile {}
+
+/// The files tracked by each git repository queried so far, by the repository's root,
+/// so each repository is only listed once per run
+#[derive(Default)]
+pub(crate) struct TrackedFiles {
+    repositories: RefCell<BTreeMap<PathBuf, Rc<BTreeSet<PathBuf>>>>,
+}
+
+impl TrackedFiles {
+    /// Check that a file is tracked by the git repository it is in, so it won't be missing
+    /// from a fresh checkout. A file that doesn't exist is left to be reported when it's read
+    pub(crate) fn ensure_tracked(&self, path: &Path) -> Result<()> {
+        let Ok(path) = path.canonicalize() else {
+            return Ok(());
+        };
+        let root = path
+            .ancestors()
+            .find(|dir| dir.join(".git").exists())
+            .with_context(|| {
+                format!(
+                    "{} is not in a git repository, as require-tracked needs",
+                    get_relative_path(&path)
+                )
+            })?;
+
+        if !self.tracked_files(root)?.contains(&path) {
+            return Err(UntrackedFile {
+                path: get_relative_path(&path),
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// List the files tracked by the repository at `root`, or reuse the list from earlier
+    fn tracked_files(&self, root: &Path) -> Result<Rc<BTreeSet<PathBuf>>> {
+        if let Some(files) = self.repositories.borrow().get(root) {
+            return Ok(Rc::clone(files));
+        }
+
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(root)
+            .args(["ls-files", "-z"])
+            .output()
+            .context("Failed to run git")?;
+        if !output.status.success() {
+            bail!(
+                "Failed to list the files tracked in {}:\n{}",
+                root.display(),
+                String::from_utf8_lossy(&output.stderr).trim_end()
+            );
+        }
+
+        let files = Rc::new(
+            output
+                .stdout
+                .split(|&byte| byte == 0)
+                .filter(|file| !file.is_empty())
+                .map(|file| root.join(&*String::from_utf8_lossy(file)))
+                .collect::<BTreeSet<_>>(),
+        );
+        self.repositories
+            .borrow_mut()
+            .insert(root.to_path_buf(), Rc::clone(&files));
+        Ok(files)
+    }
+}
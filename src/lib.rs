@@ -1,8 +1,19 @@
+pub(crate) mod cache;
+pub(crate) mod config;
 pub(crate) mod directive;
+pub(crate) mod expander;
+pub mod extract;
 pub(crate) mod extractor;
 pub(crate) mod formatter;
+pub(crate) mod git;
+pub(crate) mod markdown;
+pub(crate) mod metrics;
 pub(crate) mod output;
 pub(crate) mod parser;
 pub(crate) mod preprocessor;
+pub(crate) mod runner;
 
+pub use config::{Config, HiddenPlacement, PathHeaderStyle, Render, RenderFallback};
+pub use extract::extract;
+pub use metrics::item_loc;
 pub use preprocessor::IncludeRsPreprocessor;
@@ -0,0 +1,55 @@
+use pulldown_cmark::{CodeBlockKind, Event, Parser, Tag, TagEnd};
+
+/// A fenced code block in a Markdown document
+pub(crate) struct CodeBlock {
+    /// The fence's info string, like `rust,no_run`
+    pub(crate) info: String,
+    pub(crate) code: String,
+}
+
+/// Attributes rustdoc accepts on a code block that is still Rust without saying so
+const RUSTDOC_ATTRIBUTES: &[&str] = &["ignore", "no_run", "should_panic", "compile_fail"];
+
+/// Find the fenced code blocks of a Markdown document that rustdoc would treat as Rust,
+/// in the order they appear: those marked `rust`, or only with rustdoc attributes
+pub(crate) fn find_rust_code_blocks(markdown: &str) -> Vec<CodeBlock> {
+    let mut blocks = Vec::new();
+    let mut current: Option<CodeBlock> = None;
+
+    for event in Parser::new(markdown) {
+        match event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => {
+                current = Some(CodeBlock {
+                    info: info.trim().to_string(),
+                    code: String::new(),
+                });
+            }
+            Event::Text(text) => {
+                if let Some(block) = &mut current {
+                    block.code.push_str(&text);
+                }
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                if let Some(block) = current.take() {
+                    if is_rust(&block.info) {
+                        blocks.push(block);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    blocks
+}
+
+/// Whether a fence info string marks a Rust code block
+fn is_rust(info: &str) -> bool {
+    let lang = info
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .find(|token| !token.is_empty());
+    match lang {
+        Some("rust") => true,
+        Some(attr) => RUSTDOC_ATTRIBUTES.contains(&attr) || attr.starts_with("edition"),
+        None => false,
+    }
+}
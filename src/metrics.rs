@@ -0,0 +1,35 @@
+use crate::config::Config;
+use crate::extractor::{ParsedFiles, find_item_span};
+use crate::parser::get_relative_path;
+use anyhow::{Context, Result};
+use std::path::Path;
+
+/// Count the lines of code spanned by an item in a Rust source file
+///
+/// `kind` selects the item the same way as the directive of that name: `function`
+/// (which also accepts methods as `Type::method`), `struct`, `enum`, `trait`, `impl`
+/// or `trait_impl` (with `name` given as `Trait for Type`). The count runs from the
+/// item's first line, including doc comments and attributes, to its last line.
+pub fn item_loc(path: &Path, kind: &str, name: &str) -> Result<usize> {
+    count_item_lines(path, kind, name, &Config::default(), &ParsedFiles::new())
+}
+
+/// Count the lines of code spanned by an item, honouring the book's configuration
+pub(crate) fn count_item_lines(
+    path: &Path,
+    kind: &str,
+    name: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<usize> {
+    let parsed_file = files.get(path, config)?;
+    let span = find_item_span(&parsed_file, kind, name).with_context(|| {
+        format!(
+            "{} '{}' not found in {}",
+            kind,
+            name,
+            get_relative_path(path)
+        )
+    })?;
+    Ok(span.end().line - span.start().line + 1)
+}
@@ -1,3 +1,4 @@
+use crate::config::{Config, HiddenPlacement};
 use crate::formatter::{format_hidden, format_visible};
 
 /// Represents a processed directive with hidden and visible code
@@ -22,19 +23,28 @@ impl Output {
         self.visible_content.push(content);
     }
 
-    pub(crate) fn format(&self) -> String {
-        let mut result = String::new();
+    pub(crate) fn format(&self, config: &Config) -> String {
+        // The preamble comes first wherever the dependencies go, as inner attributes must
+        let preamble = config
+            .hidden_preamble
+            .as_deref()
+            .map(|preamble| format_hidden(preamble, config.hide_prefix.as_deref()))
+            .unwrap_or_default();
 
-        // Add hidden dependencies
+        let mut hidden = String::new();
         for content in &self.hidden_content {
-            result.push_str(&format_hidden(content));
+            hidden.push_str(&format_hidden(content, config.hide_prefix.as_deref()));
         }
 
-        // Add visible content
+        let mut visible = String::new();
         for content in &self.visible_content {
-            result.push_str(&format_visible(content));
+            visible.push_str(&format_visible(content));
         }
 
-        result
+        // Hidden dependencies go before the visible content unless configured otherwise
+        match config.hidden_placement {
+            HiddenPlacement::Before => preamble + &hidden + &visible,
+            HiddenPlacement::After => preamble + &visible + &hidden,
+        }
     }
 }
@@ -1,27 +1,215 @@
-use crate::directive::parse_directive_args;
-use crate::extractor::enum_finder::find_enum;
-use crate::extractor::function_extractor::find_function;
-use crate::extractor::impl_finder::{find_struct_impl, find_trait_impl};
-use crate::extractor::method_extractor::find_method;
-use crate::extractor::read_and_parse_file;
-use crate::extractor::struct_finder::find_struct;
-use crate::extractor::trait_finder::find_trait;
-use crate::formatter::{format_function_body, format_item, format_method_body};
+use crate::cache::DirectiveCache;
+use crate::config::{Config, PathHeaderStyle, Render, hide_prefix};
+use crate::directive::{Directive, join_path, parse_directive_args, parse_tabs_args};
+use crate::extractor::anchor_finder::{find_anchor, find_anchor_lines};
+use crate::extractor::closure_finder::{MAX_CLOSURE_ITEMS, find_closure};
+use crate::extractor::const_finder::{find_const, find_consts, find_statics};
+use crate::extractor::enum_finder::{find_enum, find_enums, find_variant};
+use crate::extractor::function_extractor::{find_function, find_functions};
+use crate::extractor::impl_finder::{
+    find_implementors, find_impls_of, find_struct_impl, find_struct_impls, find_trait_impl,
+    find_trait_impls,
+};
+use crate::extractor::include_finder::find_in_included_files;
+use crate::extractor::local_finder::find_locals;
+use crate::extractor::macro_finder::{find_invoked_macros, find_macros};
+use crate::extractor::macro_item_finder::find_item_in_macros;
+use crate::extractor::match_finder::find_match_coverage;
+use crate::extractor::method_extractor::{
+    find_assoc_items, find_method, find_methods, find_trait_method,
+};
+use crate::extractor::module_finder::{find_enclosing_modules, find_module};
+use crate::extractor::pattern_finder::{PatternKind, find_items_matching};
+use crate::extractor::struct_finder::{find_field, find_struct, find_structs};
+use crate::extractor::trait_finder::{find_trait, find_traits};
+use crate::extractor::type_finder::find_type_aliases;
+use crate::extractor::type_ref_finder::find_referenced_types;
+use crate::extractor::usage_finder::find_usages;
+use crate::extractor::{ParsedFiles, SelectorMismatch, find_item_by_spec, item_attrs, select_item};
+use crate::formatter::{
+    MainWrapper, dedent, dedent_lines, dyn_ify, fingerprint, format_assoc_types,
+    format_const_table, format_details, format_diff, format_error_annotations, format_fence,
+    format_field, format_focus, format_function_body, format_generics, format_highlight_attrs,
+    format_impl_item, format_impl_subset, format_impl_summary, format_implementor,
+    format_impls_list, format_in_modules, format_item, format_item_pretty, format_line_diff,
+    format_literate, format_locals, format_macro_expansion, format_match_coverage, format_method,
+    format_method_body, format_numbered, format_return_type, format_signature,
+    format_signature_comment, format_signature_pretty, format_struct_doc_table, format_tabs,
+    format_test_harness, format_trait_method_body, format_trait_stub, format_usages,
+    format_variant, format_with_rustfmt, hidden_code, render_item, strip_attributes,
+};
+use crate::git::UntrackedFile;
+use crate::markdown::find_rust_code_blocks;
+use crate::metrics::count_item_lines;
 use crate::output::Output;
+use crate::runner::{OutputMismatch, run_example};
 use anyhow::{Context, Result};
+use quote::ToTokens;
 use regex::{Captures, Regex};
-use std::path::Path;
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
 use std::{env, fs};
-use syn::token::{Enum, Impl, Struct, Trait};
-use syn::{File, ImplItemFn, Item, ItemFn};
+use syn::spanned::Spanned;
+use syn::token::{Const, Enum, Impl, Macro, Mod, Static, Struct, Trait, Type};
+use syn::{Block, Fields, File, ImplItem, Item, ItemEnum, ItemFn, ItemImpl, ItemStruct, Signature};
+
+/// The directives written on a line of their own, or embedded in code with `/* include: */`
+const DIRECTIVES: &[&str] = &[
+    "source_file",
+    "function",
+    "struct",
+    "enum",
+    "trait",
+    "impl",
+    "trait_impl",
+    "function_body",
+    "const_table",
+    "functions",
+    "structs",
+    "enums",
+    "item_diff",
+    "generics",
+    "trait_method_impl",
+    "assoc_types",
+    "locals",
+    "tabs",
+    "assert_output",
+    "impls_of",
+    "literate",
+    "trait_method_body",
+    "readme_example",
+    "const",
+    "static",
+    "type",
+    "macro",
+    "mod",
+    "dossier",
+    "items",
+    "macro_expansion",
+    "impl_summary",
+    "match_coverage",
+    "struct_doc_table",
+    "assert_signature",
+    "expand",
+    "trait_stub",
+    "usages",
+    "assoc",
+    "variant",
+    "consts",
+    "field",
+    "implementors",
+];
+
+/// The directives written inline in prose
+const INLINE_DIRECTIVES: &[&str] = &["loc", "return_type"];
 
 /// Process the markdown content to find and replace include-rs directives
-pub fn process_markdown(base_dir: &Path, source_path: &Path, content: &mut String) -> Result<()> {
-    // This regex finds our directives anywhere in the content
-    let re = Regex::new(
-        r"(?ms)^#!\[((?:source_file|function|struct|enum|trait|impl|trait_impl|function_body)![\s\S]*?)\]$",
+pub fn process_markdown(
+    base_dir: &Path,
+    source_path: &Path,
+    content: &mut String,
+    config: &Config,
+    cache: &DirectiveCache,
+    files: &ParsedFiles,
+) -> Result<()> {
+    // Inline directives produce prose rather than code, so they may appear anywhere in a line
+    let inline_re = Regex::new(&format!(
+        r"#!\[((?:{})!\([^\n]*?\))\]",
+        INLINE_DIRECTIVES.join("|")
+    ))?;
+    *content = replace_directives(
+        content,
+        &inline_re,
+        source_path,
+        config.strict,
+        |directive, _| process_inline_directive(base_dir, directive, config, files),
+    )?;
+
+    // Embedded directives are written as a comment token within a code fence, and are
+    // replaced in place so the code around them is preserved
+    let embedded_re = Regex::new(r"/\*\s*include:\s*([a-z_]+!\([^\n]*?\))\s*\*/")?;
+    *content = replace_directives(
+        content,
+        &embedded_re,
+        source_path,
+        config.strict,
+        |directive, indent| {
+            let directive = &apply_chapter_options(directive, config)?;
+            let processed = process_cached_directive(base_dir, directive, config, cache, files)?;
+            let verified = verify_output(directive, &processed, config);
+            let processed = redact(directive, processed, config)?;
+            // Continuation lines are indented to match the line the token is on
+            let output = processed
+                .lines()
+                .enumerate()
+                .map(|(i, line)| {
+                    if i == 0 || line.is_empty() {
+                        line.to_string()
+                    } else {
+                        format!("{}{}", indent, line)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            keep_verified(directive, verified, output)
+        },
+    )?;
+
+    // This regex finds our directives anywhere in the content, on lines of their own
+    // Trailing spaces (or the `\r` of a CRLF line ending) after the directive are allowed
+    // The whole `kind!(...)` shape is required, so inlined error text is never re-matched
+    let re = Regex::new(&format!(
+        r"(?ms)^#!\[((?:{})!\s*\([\s\S]*?\))\][ \t\r]*$",
+        DIRECTIVES.join("|")
+    ))?;
+    *content = replace_directives(content, &re, source_path, config.strict, |directive, _| {
+        let directive = &apply_chapter_options(directive, config)?;
+        let processed = process_cached_directive(base_dir, directive, config, cache, files)?;
+        let verified = verify_output(directive, &processed, config);
+        let fenced = wrap_in_fence(directive, processed, config)?;
+        let linked = append_file_link(base_dir, directive, fenced, config, files)?;
+        keep_verified(directive, verified, redact(directive, linked, config)?)
+    })?;
+
+    // Anything left in the shape of a directive has a name that isn't one, like a misspelling
+    let unknown_re = Regex::new(r"(?ms)^#!\[([a-z_]+!\s*\([\s\S]*?\))\][ \t\r]*$")?;
+    *content = replace_directives(
+        content,
+        &unknown_re,
+        source_path,
+        config.strict,
+        |directive, _| {
+            let name = directive.split('!').next().unwrap_or_default();
+            if DIRECTIVES.contains(&name) {
+                // The code a directive included shows a directive, which is left alone
+                return Ok(format!("#![{}]", directive));
+            }
+            Err(UnknownDirective::new(name).into())
+        },
     )?;
 
+    // Included files may have CRLF line endings, which would otherwise end up mixed into the
+    // chapter, so every line ends in `\n` once the directives are replaced
+    if config.normalize_line_endings {
+        *content = content.replace('\r', "");
+    }
+
+    Ok(())
+}
+
+/// Replace every match of `re` in `content` with the result of processing its directive
+/// The processor is also given the leading whitespace of the line the directive is on.
+/// Errors are reported with the file, line and column of the directive and inlined in its place,
+/// except for an example's output not matching, which fails the build, as every error does
+/// when `strict` is set
+fn replace_directives(
+    content: &str,
+    re: &Regex,
+    source_path: &Path,
+    strict: bool,
+    process: impl Fn(&str, &str) -> Result<String>,
+) -> Result<String> {
     // Track the start position of each line to calculate line numbers
     let mut line_positions = Vec::new();
     let mut pos = 0;
@@ -30,8 +218,9 @@ pub fn process_markdown(base_dir: &Path, source_path: &Path, content: &mut Strin
         pos += line.len() + 1; // +1 for the newline character
     }
 
+    let mut fatal = None;
     let result = re.replace_all(content, |caps: &Captures| {
-        let include_doc_directive = caps.get(1).map_or("", |m| m.as_str());
+        let directive = caps.get(1).map_or("", |m| m.as_str());
 
         // Get match position information
         let match_start = caps.get(0).map_or(0, |m| m.start());
@@ -39,213 +228,2410 @@ pub fn process_markdown(base_dir: &Path, source_path: &Path, content: &mut Strin
         // Find line number and column based on position
         let (line_num, col_num) = find_line_and_col(&line_positions, match_start);
 
-        // Process the directive with include_doc_macro
-        match process_include_rs_directive(base_dir, include_doc_directive) {
+        // Find the indentation of the line containing the directive
+        let line_start = line_positions.get(line_num - 1).copied().unwrap_or(0);
+        let indent = content[line_start..]
+            .split(|c: char| !c.is_whitespace() || c == '\n')
+            .next()
+            .unwrap_or_default();
+
+        // Process the directive with the given processor
+        match process(directive, indent) {
             Ok(processed) => processed,
+            // Outside of strict mode, an unknown directive is only warned about and left as is
+            Err(e) if !strict && e.is::<UnknownDirective>() => {
+                eprintln!(
+                    "Warning: {}:{}:{}: {}",
+                    get_relative_path(source_path),
+                    line_num,
+                    col_num,
+                    e
+                );
+                caps[0].to_string()
+            }
+            // Likewise, code that doesn't parse is warned about and kept
+            Err(e) if !strict && e.is::<UnparsableOutput>() => {
+                eprintln!(
+                    "Warning: {}:{}:{}: {}",
+                    get_relative_path(source_path),
+                    line_num,
+                    col_num,
+                    e
+                );
+                e.downcast::<UnparsableOutput>()
+                    .expect("the error is an UnparsableOutput")
+                    .output
+            }
             Err(e) => {
                 let rel_path = get_relative_path(source_path);
                 eprintln!("{}:{}:{}: {}", rel_path, line_num, col_num, e);
-                format!("{}:{}:{}: {}", rel_path, line_num, col_num, e)
+                let error = format!("{}:{}:{}: {}", rel_path, line_num, col_num, e);
+                if (strict || fails_build(&e)) && fatal.is_none() {
+                    fatal = Some(error.clone());
+                }
+                error
             }
         }
     });
 
-    *content = result.to_string();
-    Ok(())
+    match fatal {
+        Some(error) => Err(anyhow::anyhow!(error)),
+        None => Ok(result.to_string()),
+    }
+}
+
+/// A directive whose name isn't one, with the closest known name if there's one near enough
+/// to be a misspelling of it
+#[derive(Debug)]
+pub(crate) struct UnknownDirective {
+    name: String,
+    suggestion: Option<&'static str>,
+}
+
+impl UnknownDirective {
+    fn new(name: &str) -> Self {
+        // Allow about one edit for every three characters of the name
+        let suggestion = DIRECTIVES
+            .iter()
+            .chain(INLINE_DIRECTIVES)
+            .map(|known| (edit_distance(name, known), *known))
+            .filter(|(distance, _)| *distance <= name.len().div_ceil(3))
+            .min()
+            .map(|(_, known)| known);
+        Self {
+            name: name.to_string(),
+            suggestion,
+        }
+    }
+}
+
+impl fmt::Display for UnknownDirective {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown directive '{}!'", self.name)?;
+        if let Some(suggestion) = self.suggestion {
+            write!(f, ", did you mean '{}!'?", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for UnknownDirective {}
+
+/// Code a directive emitted that doesn't parse as Rust, found with `verify-parse`, along with
+/// the output it's found in. The output is kept in the chapter with a warning, unless `strict`
+/// is set
+#[derive(Debug)]
+pub(crate) struct UnparsableOutput {
+    directive: String,
+    error: String,
+    output: String,
+}
+
+impl fmt::Display for UnparsableOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Emitted code of {} doesn't parse as Rust: {}",
+            self.directive, self.error
+        )
+    }
+}
+
+impl std::error::Error for UnparsableOutput {}
+
+/// The Levenshtein distance between two names: how many characters have to be inserted,
+/// removed or replaced to turn one into the other
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let replace = previous[j] + usize::from(a_char != *b_char);
+            current.push(replace.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Whether a directive error fails the build, rather than only being inlined in the chapter
+fn fails_build(error: &anyhow::Error) -> bool {
+    error.is::<OutputMismatch>() || error.is::<UntrackedFile>() || error.is::<SignatureMismatch>()
+}
+
+/// A function whose signature differs from the one the book documents
+/// Like an example's output not matching, this fails the build
+#[derive(Debug)]
+pub(crate) struct SignatureMismatch {
+    pub(crate) function: String,
+    pub(crate) expected: String,
+    pub(crate) actual: String,
+}
+
+impl fmt::Display for SignatureMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Signature of '{}' doesn't match the expected signature:\n  expected: {}\n  actual:   {}",
+            self.function, self.expected, self.actual
+        )
+    }
+}
+
+impl std::error::Error for SignatureMismatch {}
+
+/// Find line and column number from a position in the text
+fn find_line_and_col(line_positions: &[usize], position: usize) -> (usize, usize) {
+    let mut line_idx = 0;
+
+    // Find the line containing the position
+    for (idx, &start) in line_positions.iter().enumerate() {
+        if position >= start {
+            line_idx = idx;
+        } else {
+            break;
+        }
+    }
+
+    // Line numbers are 1-indexed
+    let line_num = line_idx + 1;
+    // Calculate column number (1-indexed)
+    let col_num = position - line_positions[line_idx] + 1;
+
+    (line_num, col_num)
+}
+
+/// Get the path relative to the current working directory
+pub(crate) fn get_relative_path(path: &Path) -> String {
+    if let Ok(current_dir) = env::current_dir() {
+        if let Ok(relative) = path.strip_prefix(&current_dir) {
+            return format!(
+                ".{}{}",
+                std::path::MAIN_SEPARATOR,
+                relative.to_string_lossy()
+            );
+        }
+    }
+
+    // Fall back to the original path if we can't get a relative path
+    format!(".{}{}", std::path::MAIN_SEPARATOR, path.to_string_lossy())
+}
+
+/// Process an include-rs directive, reusing its cached output while the files it read are
+/// unchanged. Directives that read files other than through `ParsedFiles` aren't cached
+fn process_cached_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    cache: &DirectiveCache,
+    files: &ParsedFiles,
+) -> Result<String> {
+    let directive_name = directive.split('!').next().unwrap_or_default();
+    if matches!(
+        directive_name,
+        "source_file" | "item_diff" | "tabs" | "assert_output" | "macro_expansion" | "expand"
+    ) {
+        return process_include_rs_directive(base_dir, directive, config, files);
+    }
+
+    let file_path = parse_directive_args(directive)?.resolve_path(base_dir, config, files)?;
+    cache.get_or_insert_with(base_dir, directive, config, &file_path, || {
+        let (output, read) = files
+            .tracking_reads(|| process_include_rs_directive(base_dir, directive, config, files));
+        Ok((output?, read))
+    })
+}
+
+/// Rewrite a directive with the configured `default-options` it doesn't set itself, and its
+/// file path's `@alias/` prefix replaced by the directory in `path-aliases`
+/// Directives with their own argument syntax are left as they are
+fn apply_chapter_options(directive: &str, config: &Config) -> Result<String> {
+    if config.default_options.is_empty() && config.path_aliases.is_empty() {
+        return Ok(directive.to_string());
+    }
+    let Ok(args) = parse_directive_args(directive) else {
+        return Ok(directive.to_string());
+    };
+
+    let mut directive = directive.to_string();
+    if let Some(aliased) = args.file_path.strip_prefix('@') {
+        let (alias, rest) = aliased.split_once('/').unwrap_or((aliased, ""));
+        let path = config
+            .path_aliases
+            .get(alias)
+            .with_context(|| format!("Unknown path alias '@{}'", alias))?;
+        let resolved = Path::new(path).join(rest);
+        directive = directive.replacen(
+            &format!("\"{}\"", args.file_path),
+            &format!("{:?}", resolved.to_string_lossy()),
+            1,
+        );
+    }
+
+    let defaults = config
+        .default_options
+        .iter()
+        .filter(|(key, _)| {
+            args.option(key).is_none() && !args.args.iter().skip(1).any(|arg| arg == *key)
+        })
+        .map(|(key, value)| format!("{} = {:?}", key, value))
+        .collect::<Vec<_>>();
+    match directive.rfind(')') {
+        Some(end) if !defaults.is_empty() => {
+            directive.insert_str(end, &format!(", {}", defaults.join(", ")));
+        }
+        _ => {}
+    }
+    Ok(directive)
+}
+
+/// The options that change an item's syntax tree, so it's rendered from that rather than its
+/// source text
+const AST_OPTIONS: &[&str] = &["strip_docs", "strip_attrs", "dyn_ify"];
+
+/// The configuration for a single directive, with its `render = "source" | "ast"` option
+/// overriding the book's render mode. An explicit `source` also ignores `max-line-width`
+/// Its `hide_prefix` option replaces the book's `hide-prefix` that hidden lines start with,
+/// and an empty one leaves hidden lines out, as a `# ` prefix does in a fence that isn't Rust
+fn directive_config(directive: &str, config: &Config) -> Result<Config> {
+    // Directives with their own argument syntax can't have options
+    let Ok(args) = parse_directive_args(directive) else {
+        return Ok(config.clone());
+    };
+    if args.option("render") == Some("source") {
+        for option in AST_OPTIONS {
+            if args.flag(option)? {
+                anyhow::bail!(
+                    "{} re-renders the item from its syntax tree, so it can't be combined with render = \"source\"",
+                    option
+                );
+            }
+        }
+    }
+    let mut config = match args.option("render") {
+        None => config.clone(),
+        Some("source") => Config {
+            render: Render::Source,
+            max_line_width: None,
+            ..config.clone()
+        },
+        Some("ast") => Config {
+            render: Render::Pretty,
+            ..config.clone()
+        },
+        Some(other) => {
+            return Err(anyhow::anyhow!(
+                "Invalid render option '{}', expected \"source\" or \"ast\"",
+                other
+            ));
+        }
+    };
+    let directive_name = directive.split('!').next().unwrap_or_default();
+    match args.option("hide_prefix") {
+        Some(prefix) => config.hide_prefix = hide_prefix(prefix),
+        // Doctest markers are wrong outside of Rust, unless the book asks for them
+        None if !is_rust(directive_name, directive)
+            && config.hide_prefix.as_deref() == Some("# ") =>
+        {
+            config.hide_prefix = None;
+        }
+        None => {}
+    }
+    Ok(config)
+}
+
+/// The hidden `fn main` for a body directive, from its `wrapper_return` and `async_runtime`
+fn main_wrapper(directive: &Directive) -> Result<MainWrapper> {
+    let wrapper = match directive.option("wrapper_return") {
+        Some(return_type) => MainWrapper::new(return_type)?,
+        None => MainWrapper::default(),
+    };
+    match directive.option("async_runtime") {
+        Some(runtime) => wrapper.with_async_runtime(runtime),
+        None => Ok(wrapper),
+    }
+}
+
+/// Process a single directive on its own, outside of any chapter, whether it's inline or not
+pub(crate) fn process_standalone_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    let directive_name = directive.split('!').next().unwrap_or_default().trim();
+    if INLINE_DIRECTIVES.contains(&directive_name) {
+        return process_inline_directive(base_dir, directive, config, files);
+    }
+    let processed = process_include_rs_directive(base_dir, directive, config, files)?;
+    let verified = verify_output(directive, &processed, config);
+    let output = redact(directive, processed, config)?;
+    match keep_verified(directive, verified, output) {
+        // Without a chapter, the warning names the directive alone
+        Err(e) if !config.strict && e.is::<UnparsableOutput>() => {
+            eprintln!("Warning: {}", e);
+            Ok(e.downcast::<UnparsableOutput>()
+                .expect("the error is an UnparsableOutput")
+                .output)
+        }
+        result => result,
+    }
+}
+
+/// Process an include-rs directive
+fn process_include_rs_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    // Parse the directive name
+    let directive_name = if let Some(pos) = directive.find('!') {
+        &directive[0..pos]
+    } else {
+        // Not a recognized directive format
+        return Ok(directive.to_string());
+    };
+    let config = &directive_config(directive, config)?;
+
+    // Process the directive based on its type
+    let result = match directive_name {
+        "source_file" => process_source_file_directive(base_dir, directive, config, files)?,
+        "const_table" => process_const_table_directive(base_dir, directive, config, files)?,
+        "item_diff" => process_item_diff_directive(base_dir, directive, config, files)?,
+        "generics" => process_generics_directive(base_dir, directive, config, files)?,
+        "trait_method_impl" => {
+            process_trait_method_impl_directive(base_dir, directive, config, files)?
+        }
+        "assoc_types" => process_assoc_types_directive(base_dir, directive, config, files)?,
+        "locals" => process_locals_directive(base_dir, directive, config, files)?,
+        "tabs" => process_tabs_directive(base_dir, directive, config, files)?,
+        "assert_output" => process_assert_output_directive(base_dir, directive, config, files)?,
+        "expand" => process_expand_directive(base_dir, directive, config, files)?,
+        "assert_signature" => {
+            process_assert_signature_directive(base_dir, directive, config, files)?
+        }
+        "impls_of" => process_impls_of_directive(base_dir, directive, config, files)?,
+        "implementors" => process_implementors_directive(base_dir, directive, config, files)?,
+        "usages" => process_usages_directive(base_dir, directive, config, files)?,
+        "assoc" => process_assoc_directive(base_dir, directive, config, files)?,
+        "variant" => process_variant_directive(base_dir, directive, config, files)?,
+        "field" => process_field_directive(base_dir, directive, config, files)?,
+        "impl_summary" => process_impl_summary_directive(base_dir, directive, config, files)?,
+        "trait_stub" => process_trait_stub_directive(base_dir, directive, config, files)?,
+        "match_coverage" => process_match_coverage_directive(base_dir, directive, config, files)?,
+        "struct_doc_table" => {
+            process_struct_doc_table_directive(base_dir, directive, config, files)?
+        }
+        "literate" => process_literate_directive(base_dir, directive, config, files)?,
+        "trait_method_body" => {
+            process_trait_method_body_directive(base_dir, directive, config, files)?
+        }
+        "readme_example" => process_readme_example_directive(base_dir, directive, config, files)?,
+        "macro_expansion" => process_macro_expansion_directive(base_dir, directive, config, files)?,
+        "functions" => {
+            process_pattern_directive(base_dir, directive, PatternKind::Function, config, files)?
+        }
+        "structs" => {
+            process_pattern_directive(base_dir, directive, PatternKind::Struct, config, files)?
+        }
+        "enums" => {
+            process_pattern_directive(base_dir, directive, PatternKind::Enum, config, files)?
+        }
+        "consts" => {
+            process_pattern_directive(base_dir, directive, PatternKind::Const, config, files)?
+        }
+        "function_body" => {
+            // The body is wrapped in a hidden `fn main`, returning `wrapper_return` if given
+            let wrapper = main_wrapper(&parse_directive_args(directive)?)?;
+            // Try to find as a regular function first
+            match process_directive::<ItemFn>(
+                base_dir,
+                directive,
+                |f, n| find_functions(f, n).into_iter().map(Item::Fn).collect(),
+                |item| format_function_body(item, &wrapper, config),
+                config,
+                files,
+            ) {
+                Ok(result) => result,
+                // Functions with the name were found, just not the one selected
+                Err(e) if e.is::<SelectorMismatch>() => return Err(e),
+                // If not found, try to find as a method
+                Err(_) => {
+                    process_method_body_directive(base_dir, directive, &wrapper, config, files)?
+                }
+            }
+        }
+        "struct" => process_directive::<Struct>(
+            base_dir,
+            directive,
+            |f, n| find_structs(f, n).into_iter().map(Item::Struct).collect(),
+            |item| render_item(item, config),
+            config,
+            files,
+        )
+        .or_else(|e| {
+            process_macro_item_directive(base_dir, directive, "struct", config, files)
+                .map_err(|_| e)
+        })?,
+        "enum" => process_directive::<Enum>(
+            base_dir,
+            directive,
+            |f, n| find_enums(f, n).into_iter().map(Item::Enum).collect(),
+            |item| render_item(item, config),
+            config,
+            files,
+        )
+        .or_else(|e| {
+            process_macro_item_directive(base_dir, directive, "enum", config, files).map_err(|_| e)
+        })?,
+        "trait" => process_directive::<Trait>(
+            base_dir,
+            directive,
+            |f, n| find_traits(f, n).into_iter().map(Item::Trait).collect(),
+            |item| render_item(item, config),
+            config,
+            files,
+        )
+        .or_else(|e| {
+            process_macro_item_directive(base_dir, directive, "trait", config, files).map_err(|_| e)
+        })?,
+        "const" => process_directive::<Const>(
+            base_dir,
+            directive,
+            |f, n| find_consts(f, n).into_iter().map(Item::Const).collect(),
+            |item| render_item(item, config),
+            config,
+            files,
+        )?,
+        "static" => process_directive::<Static>(
+            base_dir,
+            directive,
+            |f, n| find_statics(f, n).into_iter().map(Item::Static).collect(),
+            |item| render_item(item, config),
+            config,
+            files,
+        )?,
+        "type" => process_directive::<Type>(
+            base_dir,
+            directive,
+            |f, n| {
+                find_type_aliases(f, n)
+                    .into_iter()
+                    .map(Item::Type)
+                    .collect()
+            },
+            |item| render_item(item, config),
+            config,
+            files,
+        )?,
+        "macro" => process_directive::<Macro>(
+            base_dir,
+            directive,
+            |f, n| find_macros(f, n).into_iter().map(Item::Macro).collect(),
+            |item| render_item(item, config),
+            config,
+            files,
+        )?,
+        "mod" => process_mod_directive(base_dir, directive, config, files)?,
+        "dossier" => process_dossier_directive(base_dir, directive, config, files)?,
+        "items" => process_items_directive(base_dir, directive, config, files)?,
+        "impl" => {
+            // With `methods = [...]`, only the listed methods of the impl block are shown
+            let args = parse_directive_args(directive)?;
+            let methods = args.list_option("methods");
+            if args.flag("merge")? {
+                process_merged_impl_directive(base_dir, directive, config, files)?
+            } else {
+                process_directive::<Impl>(
+                    base_dir,
+                    directive,
+                    |f, n| {
+                        find_struct_impls(f, n)
+                            .into_iter()
+                            .map(Item::Impl)
+                            .collect()
+                    },
+                    |item| match (&methods, item) {
+                        (Some(methods), Item::Impl(item_impl)) => {
+                            let impls = select_methods(std::slice::from_ref(item_impl), methods)?;
+                            render_impl_subset(&impls[0], config)
+                        }
+                        _ => render_item(item, config),
+                    },
+                    config,
+                    files,
+                )?
+            }
+        }
+        "trait_impl" => process_directive::<Impl>(
+            base_dir,
+            directive,
+            |f, n| {
+                // For trait_impl, the item_name should have the format "TraitName for StructName"
+                let parts: Vec<&str> = n.split(" for ").collect();
+                if parts.len() != 2 {
+                    return Vec::new();
+                }
+
+                let trait_name = parts[0].trim();
+                let struct_name = parts[1].trim();
+
+                find_trait_impls(f, trait_name, struct_name)
+                    .into_iter()
+                    .map(Item::Impl)
+                    .collect()
+            },
+            |item| render_item(item, config),
+            config,
+            files,
+        )?,
+        "function" => {
+            // Invalid test arguments are reported as such, rather than as a missing function
+            test_args(&parse_directive_args(directive)?)?;
+            // Try to find as a regular function first
+            match process_directive::<ItemFn>(
+                base_dir,
+                directive,
+                |f, n| find_functions(f, n).into_iter().map(Item::Fn).collect(),
+                |item| render_item(item, config),
+                config,
+                files,
+            ) {
+                Ok(result) => result,
+                // Functions with the name were found, just not the one selected
+                Err(e) if e.is::<SelectorMismatch>() => return Err(e),
+                // If not found, try to find as a method, then inside macro invocations
+                Err(_) => {
+                    process_method_directive(base_dir, directive, config, files).or_else(|e| {
+                        process_macro_item_directive(base_dir, directive, "fn", config, files)
+                            .map_err(|_| e)
+                    })?
+                }
+            }
+        }
+        _ => return Err(UnknownDirective::new(directive_name).into()),
+    };
+
+    // Format the result as a Rust code block
+    let result = result.trim().to_string();
+    if emits_markdown(directive_name) {
+        return Ok(result);
+    }
+    // Other languages are passed through as they are, since the rest adds Rust comments
+    if !is_rust(directive_name, directive) {
+        return Ok(result);
+    }
+    let result = annotate_errors(directive, result, config)?;
+    let result = append_fingerprint(directive, result)?;
+    prepend_path_header(base_dir, directive, result, config, files)
+}
+
+/// With a `with_path_header` option, start a directive's code with a comment giving its
+/// file's path relative to the crate root, for tools that rebuild a crate from snippets
+/// The crate root is `crate-root` when it's configured and contains the file, and
+/// otherwise the nearest directory above the file with a `Cargo.toml`
+fn prepend_path_header(
+    base_dir: &Path,
+    directive: &str,
+    code: String,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    let Ok(args) = parse_directive_args(directive) else {
+        return Ok(code);
+    };
+    if !args.flag("with_path_header")? {
+        return Ok(code);
+    }
+
+    let absolute_path = args.resolve_path(base_dir, config, files)?;
+    let absolute_path = absolute_path
+        .canonicalize()
+        .with_context(|| format!("Failed to read file: {}", get_relative_path(&absolute_path)))?;
+    let crate_root = find_crate_root(&absolute_path, config).with_context(|| {
+        format!(
+            "with_path_header found no crate containing {}",
+            get_relative_path(&absolute_path)
+        )
+    })?;
+    let path = absolute_path
+        .strip_prefix(&crate_root)
+        .expect("the crate root contains the file")
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    let header = match config.path_header_style {
+        PathHeaderStyle::Comment => format!("// path: {}", path),
+        PathHeaderStyle::Fixture => format!("//- {}", path),
+    };
+    Ok(format!("{}\n{}", header, code))
+}
+
+/// The root of the crate a canonical file path is in: `crate-root` when it's configured and
+/// contains the file, and otherwise the nearest directory above the file with a `Cargo.toml`
+fn find_crate_root(absolute_path: &Path, config: &Config) -> Option<PathBuf> {
+    config
+        .crate_root
+        .as_ref()
+        .and_then(|root| root.canonicalize().ok())
+        .filter(|root| absolute_path.starts_with(root))
+        .or_else(|| {
+            absolute_path
+                .ancestors()
+                .skip(1)
+                .find(|dir| dir.join("Cargo.toml").is_file())
+                .map(Path::to_path_buf)
+        })
+}
+
+/// With an `errors = "3: E0382 moved value"` option, annotate lines of code that isn't
+/// meant to compile with the errors the compiler reports for them
+fn annotate_errors(directive: &str, code: String, config: &Config) -> Result<String> {
+    match parse_directive_args(directive)
+        .ok()
+        .and_then(|args| args.option("errors").map(str::to_string))
+    {
+        Some(spec) => format_error_annotations(&code, &spec, config.hide_prefix.as_deref()),
+        None => Ok(code),
+    }
+}
+
+/// With a `fingerprint` option, append a hash of a directive's code as a `// fp: ` comment
+/// `fingerprint = true` just records it, while `fingerprint = "<hash>"` also checks that the
+/// code still has that hash, so prose written about it can't silently drift out of date
+fn append_fingerprint(directive: &str, code: String) -> Result<String> {
+    let Some(expected) = parse_directive_args(directive)
+        .ok()
+        .and_then(|args| args.option("fingerprint").map(str::to_string))
+    else {
+        return Ok(code);
+    };
+    let actual = fingerprint(&code);
+    match expected.as_str() {
+        "false" => return Ok(code),
+        "true" => {}
+        expected if expected != actual => {
+            return Err(anyhow::anyhow!(
+                "Fingerprint changed from {} to {}, review the text around this code and update the fingerprint",
+                expected,
+                actual
+            ));
+        }
+        _ => {}
+    }
+    Ok(format!("{}\n// fp: {}", code, actual))
+}
+
+/// Wrap the output of a directive in a code fence when `wrap-fences` is enabled, or the
+/// directive sets the fence's language with `lang` or adds to its info string with `fence_attrs`
+/// Directives whose output is Markdown, or that already emit their own fence, are left alone
+/// With `focus` under the HTML renderer, the code block is written as HTML with its hidden
+/// lines shown dimmed instead
+fn wrap_in_fence(directive: &str, processed: String, config: &Config) -> Result<String> {
+    let directive_name = directive.split('!').next().unwrap_or_default();
+    if emits_markdown(directive_name) {
+        return Ok(processed);
+    }
+    let args = parse_directive_args(directive)?;
+    let focus = args.flag("focus")? && config.renderer == "html";
+    if focus && !emits_fence(&args, config) {
+        return Err(anyhow::anyhow!(
+            "focus needs wrap-fences or lang, so the code block can be written as HTML"
+        ));
+    }
+    if !emits_fence(&args, config) {
+        return Ok(processed);
+    }
+
+    let mut info = code_language(directive_name, &args)
+        .unwrap_or_else(|| config.fence_info(directive_name).to_string());
+    if focus {
+        let lang = info.split(',').next().unwrap_or_default();
+        return Ok(format_focus(
+            lang,
+            &processed,
+            config.hide_prefix.as_deref(),
+        ));
+    }
+    if let Some(fence_attrs) = args.option("fence_attrs") {
+        info.push(',');
+        info.push_str(fence_attrs);
+    }
+    // Annotated code doesn't compile, so it mustn't be tested as if it did
+    if args.option("errors").is_some() {
+        info.push_str(",compile_fail");
+    }
+    if let Some(spec) = args.option("highlight") {
+        info.push_str(&format_highlight_attrs(
+            &processed,
+            spec,
+            config.hide_prefix.as_deref(),
+        )?);
+    }
+    if let Some(spec) = args.option("highlight_item") {
+        info.push_str(&item_highlight_attrs(&processed, spec)?);
+    }
+    Ok(format_fence(&info, &processed))
+}
+
+/// With a `highlight_item` option, the `hl_lines=` attribute covering the lines of an item in
+/// the included code, such as `TestStruct` or `impl Display for TestStruct`
+/// The code is parsed as it's emitted, so the lines are those of the fence even when the
+/// code starts with a path header or is cut down to a line range
+fn item_highlight_attrs(code: &str, spec: &str) -> Result<String> {
+    let parsed_file = syn::parse_file(code)
+        .map_err(|e| anyhow::anyhow!("highlight_item needs code that parses as Rust: {}", e))?;
+    let item = find_item_by_spec(&parsed_file, spec)
+        .with_context(|| format!("highlight_item '{}' not found", spec))?;
+    let span = item.span();
+    Ok(format!(
+        ",hl_lines={}-{}",
+        span.start().line,
+        span.end().line
+    ))
+}
+
+/// The language of a directive's code if it's set: the `lang` option, or for a file included
+/// with `source_file!` that isn't a `.rs` file, its extension, which names the language for
+/// most highlighters. Otherwise the code is Rust
+fn code_language(directive_name: &str, args: &Directive) -> Option<String> {
+    if let Some(lang) = args.option("lang") {
+        return Some(lang.to_string());
+    }
+    if directive_name != "source_file" || args.file_path.is_empty() {
+        return None;
+    }
+    match Path::new(&args.file_path).extension() {
+        Some(extension) if extension == "rs" => None,
+        Some(extension) => Some(extension.to_string_lossy().to_lowercase()),
+        None => Some("text".to_string()),
+    }
+}
+
+/// Whether a directive's code is Rust, so Rust comments can be added to it and it can be parsed
+fn is_rust(directive_name: &str, directive: &str) -> bool {
+    parse_directive_args(directive)
+        .map(|args| code_language(directive_name, &args).is_none_or(|lang| lang == "rust"))
+        .unwrap_or(true)
+}
+
+/// Whether a directive writes its own code fence, as every one does with `wrap-fences`
+fn emits_fence(args: &Directive, config: &Config) -> bool {
+    config.wrap_fences || args.option("lang").is_some() || args.option("fence_attrs").is_some()
+}
+
+/// Replace the matches of the book's `redact` patterns, and of a directive's own `redact`
+/// option, with `***`, so secrets in the code being included don't end up in the book
+fn redact(directive: &str, output: String, config: &Config) -> Result<String> {
+    let option = parse_directive_args(directive)
+        .ok()
+        .and_then(|args| args.option("redact").map(str::to_string));
+    let mut output = output;
+    for pattern in config.redact.iter().chain(option.as_ref()) {
+        let re = Regex::new(pattern)
+            .map_err(|e| anyhow::anyhow!("Invalid redact pattern '{}': {}", pattern, e))?;
+        output = re.replace_all(&output, "***").into_owned();
+    }
+    Ok(output)
+}
+
+/// With a `with_file_link` option, follow a directive's output with its whole file in a
+/// collapsed `<details>` block, for readers who want more context than the extracted item
+/// The block is HTML, so other renderers get the output alone. It has to follow the code
+/// fence, so code needs `wrap-fences` for the directive to emit the fence itself
+fn append_file_link(
+    base_dir: &Path,
+    directive: &str,
+    processed: String,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    let Ok(args) = parse_directive_args(directive) else {
+        return Ok(processed);
+    };
+    if !args.flag("with_file_link")? || config.renderer != "html" {
+        return Ok(processed);
+    }
+    let directive_name = directive.split('!').next().unwrap_or_default();
+    if !emits_fence(&args, config) && !emits_markdown(directive_name) {
+        return Err(anyhow::anyhow!(
+            "with_file_link needs wrap-fences or lang, so the full source can follow the code fence"
+        ));
+    }
+
+    let absolute_path = args.resolve_path(base_dir, config, files)?;
+    let source = fs::read_to_string(&absolute_path)
+        .with_context(|| format!("Failed to read file: {}", get_relative_path(&absolute_path)))?;
+    let name = match args.option("mod") {
+        Some(module) => module,
+        None => args.file_path.as_str(),
+    };
+    Ok(format!(
+        "{}\n\n{}",
+        processed,
+        format_details(
+            &format!("Full source: {}", name),
+            &format_fence(config.fence_info("source_file"), source.trim_end()),
+            false,
+        )
+    ))
+}
+
+/// The output of a directive, or an `UnparsableOutput` holding it if `verify-parse` found that
+/// its code doesn't parse
+fn keep_verified(
+    directive: &str,
+    verified: std::result::Result<(), syn::Error>,
+    output: String,
+) -> Result<String> {
+    match verified {
+        Ok(()) => Ok(output),
+        Err(e) => Err(UnparsableOutput {
+            directive: directive.split('(').next().unwrap_or_default().to_string(),
+            error: e.to_string(),
+            output,
+        }
+        .into()),
+    }
+}
+
+/// With `verify-parse`, check that the visible lines of a directive's code parse as Rust,
+/// either on their own or as the body of a `fn main`, to catch bugs in the formatting
+fn verify_output(
+    directive: &str,
+    processed: &str,
+    config: &Config,
+) -> std::result::Result<(), syn::Error> {
+    let directive_name = directive.split('!').next().unwrap_or_default();
+    if !config.verify_parse || emits_markdown(directive_name) || !is_rust(directive_name, directive)
+    {
+        return Ok(());
+    }
+
+    let hide_prefix = config.hide_prefix.as_deref();
+    let visible = processed
+        .lines()
+        .filter(|line| hidden_code(line, hide_prefix).is_none())
+        .collect::<Vec<_>>()
+        .join("\n");
+    if syn::parse_file(&visible).is_ok() {
+        return Ok(());
+    }
+    syn::parse_file(&format!("fn main() {{\n{}\n}}", visible)).map(|_| ())
+}
+
+/// Whether a directive's output is Markdown, possibly with its own code fences, rather than code
+fn emits_markdown(directive_name: &str) -> bool {
+    matches!(
+        directive_name,
+        "const_table"
+            | "item_diff"
+            | "tabs"
+            | "assert_output"
+            | "expand"
+            | "impls_of"
+            | "literate"
+            | "readme_example"
+            | "macro_expansion"
+            | "match_coverage"
+            | "struct_doc_table"
+            | "usages"
+    )
+}
+
+/// Process an inline include-rs directive, whose output is prose rather than code
+fn process_inline_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    match directive.split('!').next() {
+        Some("loc") => process_loc_directive(base_dir, directive, config, files),
+        Some("return_type") => process_return_type_directive(base_dir, directive, config, files),
+        _ => Ok(directive.to_string()),
+    }
+}
+
+/// Process loc! directive
+/// The item may be prefixed with its kind (`struct Foo`, `impl Foo`, ...); functions need no prefix
+fn process_loc_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    let directive = parse_directive_args(directive)?;
+    if directive.item.is_none() {
+        return Err(anyhow::anyhow!("Item name is required"));
+    }
+    let item = directive.item.as_ref().expect("item name is required");
+    let (kind, name) = match item.split_once(' ') {
+        Some(("impl", rest)) if rest.contains(" for ") => ("trait_impl", rest.trim()),
+        Some((kind, rest)) => (kind, rest.trim()),
+        None => ("function", item.as_str()),
+    };
+    let absolute_path = directive.resolve_path(base_dir, config, files)?;
+    Ok(count_item_lines(&absolute_path, kind, name, config, files)?.to_string())
+}
+
+/// Process return_type! directive
+/// Emits the return type of a function or method (`Type::method`) for use in prose
+fn process_return_type_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    let directive = parse_directive_args(directive)?;
+    if directive.item.is_none() {
+        return Err(anyhow::anyhow!("Function name is required"));
+    }
+    let absolute_path = directive.resolve_path(base_dir, config, files)?;
+    let parsed_file = files.get(&absolute_path, config)?;
+    let name = directive.item.as_ref().expect("function name is required");
+
+    let sig = match find_function(&parsed_file, name) {
+        Some(function) => function.sig,
+        None => {
+            find_method(&parsed_file, name)
+                .with_context(|| format!("Function '{}' not found", name))?
+                .sig
+        }
+    };
+    Ok(format_return_type(&sig.output))
+}
+
+/// Process source_file! directive
+fn process_source_file_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    let directive = parse_directive_args(directive)?;
+    if let (Some(anchor), Some(_)) = (directive.option("anchor"), &directive.line_range) {
+        return Err(anyhow::anyhow!(
+            "A line range can't be combined with anchor = \"{}\"",
+            anchor
+        ));
+    }
+    let Some(paths) = directive.resolve_glob(base_dir, config, files)? else {
+        let absolute_path = directive.resolve_path(base_dir, config, files)?;
+        return read_source_file(&directive, &absolute_path, config);
+    };
+
+    // The files a glob matches are joined in order with the separator on a line between each,
+    // which by default leaves a blank line between them
+    let separator = directive
+        .option("separator")
+        .unwrap_or("")
+        .replace("\\n", "\n");
+    let sources = paths
+        .iter()
+        .map(|path| read_source_file(&directive, path, config))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(sources
+        .iter()
+        .map(|source| source.trim_end_matches('\n'))
+        .collect::<Vec<_>>()
+        .join(&format!("\n{}\n", separator)))
+}
+
+/// Read a file for `source_file!`, or the part of it given with an anchor or a line range
+/// With `numbered`, each line is prefixed with its line number in the file
+fn read_source_file(
+    directive: &Directive,
+    absolute_path: &Path,
+    config: &Config,
+) -> Result<String> {
+    let content = fs::read_to_string(absolute_path)
+        .with_context(|| format!("Failed to read file: {}", get_relative_path(absolute_path)))?;
+    // `source_file!` has no item, so a bare flag is its first argument
+    let numbered =
+        directive.flag("numbered")? || directive.args.iter().any(|arg| arg == "numbered");
+    if let Some(anchor) = directive.option("anchor") {
+        let code = read_anchor(&content, anchor, absolute_path)?;
+        if !numbered {
+            return Ok(code);
+        }
+        // Nested anchors' markers are left out, so the numbers of an anchor's lines can skip
+        let numbers = find_anchor_lines(&content, anchor)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(number, _)| number)
+            .collect::<Vec<_>>();
+        return Ok(format_numbered(
+            &code,
+            &numbers,
+            config.hide_prefix.as_deref(),
+        ));
+    }
+    let (code, first_line) = match &directive.line_range {
+        // A range cut from inside a block is shown without the block's indentation
+        Some(range) => (dedent_lines(&range.slice(&content)?), range.first_line()),
+        None => (content, 1),
+    };
+    if !numbered {
+        return Ok(code);
+    }
+    let numbers = (first_line..first_line + code.lines().count()).collect::<Vec<_>>();
+    Ok(format_numbered(
+        &code,
+        &numbers,
+        config.hide_prefix.as_deref(),
+    ))
+}
+
+/// Select the lines of an `ANCHOR` region of a file, without their common indentation
+fn read_anchor(content: &str, anchor: &str, path: &Path) -> Result<String> {
+    let lines = find_anchor(content, anchor).with_context(|| {
+        format!(
+            "Anchor '{}' not found in {}, expected a `// ANCHOR: {}` line",
+            anchor,
+            get_relative_path(path),
+            anchor
+        )
+    })?;
+    Ok(dedent_lines(&lines))
+}
+
+/// Process macro_expansion! directive
+/// Shows a macro invocation marked with `// ANCHOR: name` above a hand-written expansion
+/// marked with `// ANCHOR: name_expansion`, or the anchor given with `expansion = "..."`.
+/// Nothing is actually expanded, so the expansion is only as accurate as its author made it
+fn process_macro_expansion_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    let directive = parse_directive_args(directive)?;
+    let anchor = directive
+        .option("anchor")
+        .context("The invocation's anchor is required, with anchor = \"name\"")?;
+    let expansion_anchor = match directive.option("expansion") {
+        Some(expansion) => expansion.to_string(),
+        None => format!("{}_expansion", anchor),
+    };
+    let absolute_path = directive.resolve_path(base_dir, config, files)?;
+    let content = fs::read_to_string(&absolute_path)
+        .with_context(|| format!("Failed to read file: {}", get_relative_path(&absolute_path)))?;
+
+    let invocation = read_anchor(&content, anchor, &absolute_path)?;
+    let expansion = read_anchor(&content, &expansion_anchor, &absolute_path)?;
+    Ok(format_macro_expansion(
+        &invocation,
+        &expansion,
+        config.fence_info("macro_expansion"),
+    ))
+}
+
+/// Process readme_example! directive
+/// Emits the `n`th Rust code block of a Markdown file, such as a README, in a fence with the
+/// same info string, so `no_run` and `ignore` carry over
+fn process_readme_example_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    let directive = parse_directive_args(directive)?;
+    let index = match directive.option("n") {
+        Some(n) => n
+            .parse::<usize>()
+            .map_err(|_| anyhow::anyhow!("Invalid n option '{}', expected an index", n))?,
+        None => 0,
+    };
+    let absolute_path = directive.resolve_path(base_dir, config, files)?;
+    let markdown = fs::read_to_string(&absolute_path)
+        .with_context(|| format!("Failed to read file: {}", get_relative_path(&absolute_path)))?;
+
+    let blocks = find_rust_code_blocks(&markdown);
+    let block = blocks.get(index).with_context(|| {
+        format!(
+            "Code block {} not found, {} has {} Rust code blocks",
+            index,
+            get_relative_path(&absolute_path),
+            blocks.len()
+        )
+    })?;
+    Ok(format_fence(&block.info, block.code.trim_end()))
+}
+
+/// Process const_table! directive
+/// Falls back to a fenced code block if the const's value isn't tabular
+fn process_const_table_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    let directive = parse_directive_args(directive)?;
+    if directive.item.is_none() {
+        return Err(anyhow::anyhow!("Const name is required"));
+    }
+    let absolute_path = directive.resolve_path(base_dir, config, files)?;
+    let parsed_file = files.get(&absolute_path, config)?;
+    let const_name = directive.item.as_ref().expect("const name is required");
+    let const_item = find_const(&parsed_file, const_name)
+        .with_context(|| format!("Const '{}' not found", const_name))?;
+
+    match format_const_table(&const_item) {
+        Some(table) => Ok(table),
+        None => Ok(format_fence(
+            config.fence_info("const_table"),
+            &format_item(&Item::Const(const_item), config)?,
+        )),
+    }
+}
+
+/// Process struct_doc_table! directive
+/// Documents a struct's fields as a Markdown table rather than showing its code
+fn process_struct_doc_table_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    let directive = parse_directive_args(directive)?;
+    if directive.item.is_none() {
+        return Err(anyhow::anyhow!("Struct name is required"));
+    }
+    let absolute_path = directive.resolve_path(base_dir, config, files)?;
+    let parsed_file = files.get(&absolute_path, config)?;
+    let struct_name = directive.item.as_ref().expect("struct name is required");
+    let item_struct = find_struct(&parsed_file, struct_name)
+        .with_context(|| format!("Struct '{}' not found", struct_name))?;
+    Ok(format_struct_doc_table(&item_struct))
+}
+
+/// Process mod! directive
+/// Only inline modules have a body to show; a `mod name;` declaration is reported as such
+fn process_mod_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    let args = parse_directive_args(directive)?;
+    if let Some(name) = &args.item {
+        let absolute_path = args.resolve_path(base_dir, config, files)?;
+        let parsed_file = files.get(&absolute_path, config)?;
+        if find_module(&parsed_file, name).is_some_and(|module| module.content.is_none()) {
+            return Err(anyhow::anyhow!(
+                "Module '{}' is declared with `mod {};` and its body is in another file; only inline modules are supported, include its file with source_file! instead",
+                name,
+                name
+            ));
+        }
+    }
+
+    process_directive::<Mod>(
+        base_dir,
+        directive,
+        |f, n| {
+            find_module(f, n)
+                .filter(|m| m.content.is_some())
+                .map(Item::Mod)
+                .into_iter()
+                .collect()
+        },
+        |item| render_item(item, config),
+        config,
+        files,
+    )
+}
+
+/// Process a directive whose item is defined inside a macro invocation like `cfg_if!`
+/// Only consulted once the regular finders come up empty; see `find_item_in_macros`
+fn process_macro_item_directive(
+    base_dir: &Path,
+    directive: &str,
+    keyword: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    let directive = parse_directive_args(directive)?;
+    if directive.item.is_none() {
+        return Err(anyhow::anyhow!("Item name is required"));
+    }
+    let absolute_path = directive.resolve_path(base_dir, config, files)?;
+    let parsed_file = files.get(&absolute_path, config)?;
+    let item_name = directive.item.as_ref().expect("item name is required");
+    let (macro_name, source_text) = find_item_in_macros(&parsed_file, keyword, item_name)
+        .with_context(|| format!("{} '{}' not found", keyword, item_name))?;
+
+    eprintln!(
+        "Warning: {} '{}' was found inside a `{}!` invocation in {}; it was extracted textually without macro expansion",
+        keyword,
+        item_name,
+        macro_name,
+        get_relative_path(&absolute_path)
+    );
+    Ok(dedent(&source_text))
+}
+
+/// Process functions!, structs!, enums! and consts! directives
+/// Every item of the kind whose whole name matches the `pattern` regex, or starts with
+/// `prefix`, is shown, in source order
+fn process_pattern_directive(
+    base_dir: &Path,
+    directive: &str,
+    kind: PatternKind,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    let directive = parse_directive_args(directive)?;
+    let pattern = match (directive.option("pattern"), directive.option("prefix")) {
+        (Some(pattern), None) => pattern.to_string(),
+        (None, Some(prefix)) => format!("{}.*", regex::escape(prefix)),
+        (Some(_), Some(_)) => {
+            return Err(anyhow::anyhow!(
+                "Only one of `pattern` and `prefix` can be given"
+            ));
+        }
+        (None, None) => {
+            return Err(anyhow::anyhow!(
+                "A `pattern = \"...\"` or `prefix = \"...\"` option is required"
+            ));
+        }
+    };
+    let re = Regex::new(&format!("^(?:{})$", pattern))
+        .with_context(|| format!("Invalid pattern '{}'", pattern))?;
+    let absolute_path = directive.resolve_path(base_dir, config, files)?;
+    let parsed_file = files.get(&absolute_path, config)?;
+
+    let matched = find_items_matching(&parsed_file, kind, &re);
+    if matched.is_empty() {
+        return Err(anyhow::anyhow!("No items matching '{}' found", pattern));
+    }
+
+    let mut result = Output::new();
+    for item in &parsed_file.items {
+        if !matched.contains(item) {
+            result.add_hidden_content(format_item(item, config)?);
+        }
+    }
+    let visible = matched
+        .iter()
+        .map(|item| render_item(item, config))
+        .collect::<Result<Vec<_>>>()?;
+    // Consts are grouped into a single block, like the variants of an enum
+    let separator = if kind == PatternKind::Const {
+        "\n"
+    } else {
+        "\n\n"
+    };
+    result.add_visible_content(visible.join(separator));
+    Ok(result.format(config))
+}
+
+/// Process item_diff! directive
+/// Shows how an item changed between an old and a new file as a `diff` block
+fn process_item_diff_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    let directive = parse_directive_args(directive)?;
+    let [new_path, spec] = directive.args.as_slice() else {
+        return Err(anyhow::anyhow!(
+            "Expected item_diff!(\"old.rs\", \"new.rs\", item)"
+        ));
+    };
+    let old_file = files.get(&directive.resolve_path(base_dir, config, files)?, config)?;
+    let new_path = join_path(base_dir, new_path);
+    if config.require_tracked {
+        files.tracked().ensure_tracked(&new_path)?;
+    }
+    let new_file = files.get(&new_path, config)?;
+
+    let old_item = find_item_by_spec(&old_file, spec)
+        .map(|item| format_item(&item, config))
+        .transpose()?;
+    let new_item = find_item_by_spec(&new_file, spec)
+        .map(|item| format_item(&item, config))
+        .transpose()?;
+    if old_item.is_none() && new_item.is_none() {
+        return Err(anyhow::anyhow!("'{}' not found in either file", spec));
+    }
+
+    Ok(format_diff(
+        old_item.as_deref().unwrap_or_default(),
+        new_item.as_deref().unwrap_or_default(),
+    ))
+}
+
+/// Process generics! directive
+/// Emits just the generic parameters and where clause of an item given like `struct Cache`
+fn process_generics_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    let directive = parse_directive_args(directive)?;
+    if directive.item.is_none() {
+        return Err(anyhow::anyhow!("Item specification is required"));
+    }
+    let absolute_path = directive.resolve_path(base_dir, config, files)?;
+    let parsed_file = files.get(&absolute_path, config)?;
+    let spec = directive.item.as_ref().expect("item spec is required");
+    let item =
+        find_item_by_spec(&parsed_file, spec).with_context(|| format!("'{}' not found", spec))?;
+
+    let generics = match &item {
+        Item::Struct(item) => &item.generics,
+        Item::Enum(item) => &item.generics,
+        Item::Trait(item) => &item.generics,
+        Item::Fn(item) => &item.sig.generics,
+        Item::Impl(item) => &item.generics,
+        _ => return Err(anyhow::anyhow!("'{}' can't have generics", spec)),
+    };
+    match format_generics(generics) {
+        Some(generics) => Ok(generics),
+        None => {
+            eprintln!("Note: '{}' has no generic parameters", spec);
+            Ok(String::new())
+        }
+    }
+}
+
+/// Process trait_method_impl! directive
+/// Shows a trait method's declared signature as a comment above the implementation of it
+/// given as `TraitName for StructName::method_name`
+fn process_trait_method_impl_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    let directive = parse_directive_args(directive)?;
+    if directive.item.is_none() {
+        return Err(anyhow::anyhow!("Method specification is required"));
+    }
+    let method_spec = directive.item.as_ref().expect("method spec is required");
+    let (trait_name, method_name) = method_spec
+        .rsplit_once("::")
+        .and_then(|(impl_part, method_name)| {
+            let (trait_name, _) = impl_part.split_once(" for ")?;
+            Some((trait_name.trim(), method_name.trim()))
+        })
+        .with_context(|| {
+            format!(
+                "Expected 'TraitName for StructName::method_name', got '{}'",
+                method_spec
+            )
+        })?;
+    let absolute_path = directive.resolve_path(base_dir, config, files)?;
+    let parsed_file = files.get(&absolute_path, config)?;
+
+    let declaration =
+        find_trait_method(&parsed_file, trait_name, method_name).with_context(|| {
+            format!(
+                "Method '{}' not found in trait '{}'",
+                method_name, trait_name
+            )
+        })?;
+    let method = find_method(&parsed_file, method_spec)
+        .with_context(|| format!("Method '{}' not found", method_spec))?;
+
+    Ok(format!(
+        "// Declared by `{}` as:\n{}\n{}",
+        trait_name,
+        format_signature_comment(&declaration.sig),
+        format_method(&method)?
+    ))
+}
+
+/// Process assoc_types! directive
+/// Shows what the associated types of an `impl TraitName for StructName` resolve to
+fn process_assoc_types_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    let directive = parse_directive_args(directive)?;
+    if directive.item.is_none() {
+        return Err(anyhow::anyhow!(
+            "Trait implementation specification is required"
+        ));
+    }
+    let impl_spec = directive.item.as_ref().expect("impl spec is required");
+    let (trait_name, struct_name) = impl_spec
+        .split_once(" for ")
+        .map(|(trait_name, struct_name)| (trait_name.trim(), struct_name.trim()))
+        .with_context(|| format!("Expected 'TraitName for StructName', got '{}'", impl_spec))?;
+    let absolute_path = directive.resolve_path(base_dir, config, files)?;
+    let parsed_file = files.get(&absolute_path, config)?;
+
+    let item_impl = find_trait_impl(&parsed_file, trait_name, struct_name)
+        .with_context(|| format!("Implementation '{}' not found", impl_spec))?;
+    format_assoc_types(&item_impl)
+        .with_context(|| format!("'{}' has no associated types", impl_spec))
+}
+
+/// Process locals! directive
+/// Summarizes the `let` bindings of a function, or of a method given as `StructName::method_name`.
+/// Bindings in nested blocks and closures are only included with `nested = true`
+fn process_locals_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    let directive = parse_directive_args(directive)?;
+    if directive.item.is_none() {
+        return Err(anyhow::anyhow!("Function name is required"));
+    }
+    let nested = directive.flag("nested")?;
+    let absolute_path = directive.resolve_path(base_dir, config, files)?;
+    let parsed_file = files.get(&absolute_path, config)?;
+    let name = directive.item.as_ref().expect("function name is required");
+
+    let block = match find_function(&parsed_file, name) {
+        Some(function) => *function.block,
+        None => {
+            find_method(&parsed_file, name)
+                .with_context(|| format!("Function '{}' not found", name))?
+                .block
+        }
+    };
+    let locals = find_locals(&block, nested);
+    if locals.is_empty() {
+        eprintln!("Note: '{}' has no local bindings", name);
+    }
+    Ok(format_locals(&locals))
+}
+
+/// Process match_coverage! directive
+/// Lists the variants of an enum, marking each as handled by the `match` expressions of a
+/// function or caught by their wildcard arm
+fn process_match_coverage_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    let directive = parse_directive_args(directive)?;
+    let (Some(enum_name), Some(name)) = (directive.item.as_ref(), directive.args.get(1)) else {
+        return Err(anyhow::anyhow!("Enum and function names are required"));
+    };
+    let absolute_path = directive.resolve_path(base_dir, config, files)?;
+    let parsed_file = files.get(&absolute_path, config)?;
+
+    let item_enum = find_enum(&parsed_file, enum_name)
+        .with_context(|| format!("Enum '{}' not found", enum_name))?;
+    let variants = item_enum
+        .variants
+        .iter()
+        .map(|variant| variant.ident.to_string())
+        .collect::<Vec<_>>();
+    let block = match find_function(&parsed_file, name) {
+        Some(function) => *function.block,
+        None => {
+            find_method(&parsed_file, name)
+                .with_context(|| format!("Function '{}' not found", name))?
+                .block
+        }
+    };
+    // The enum may be named with its module path, but arms name it by its ident
+    let ident = item_enum.ident.to_string();
+    let coverage = find_match_coverage(&block, &ident, &variants);
+    if coverage.handled.is_empty() {
+        eprintln!("Note: '{}' has no match over '{}'", name, enum_name);
+    }
+    Ok(format_match_coverage(&ident, &variants, &coverage))
+}
+
+/// Process tabs! directive
+/// Runs each tab's directive and shows the results as tabs, each in its own code fence
+fn process_tabs_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    let tabs = parse_tabs_args(directive)?
+        .into_iter()
+        .map(|(label, directive)| {
+            let directive_name = directive.split('!').next().unwrap_or_default().trim();
+            if emits_markdown(directive_name) {
+                return Err(anyhow::anyhow!(
+                    "{}! can't be used inside tabs!",
+                    directive_name
+                ));
+            }
+            let code = process_include_rs_directive(base_dir, &directive, config, files)
+                .with_context(|| format!("In tab '{}'", label))?;
+            Ok((
+                label,
+                format_fence(config.fence_info(directive_name), &code),
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if tabs.is_empty() {
+        return Err(anyhow::anyhow!("tabs! needs at least one tab"));
+    }
+    Ok(format_tabs(&tabs))
+}
+
+/// Process assert_output! directive
+/// Runs an example and shows its source followed by its output, which must match `expected`.
+/// A mismatch fails the build. Requires `allow-run`, since it compiles and runs code
+fn process_assert_output_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    if !config.allow_run {
+        return Err(anyhow::anyhow!(
+            "assert_output! runs the example, so it must be enabled with allow-run = true"
+        ));
+    }
+    let directive = parse_directive_args(directive)?;
+    let expected = directive
+        .option("expected")
+        .context("Expected output is required")?
+        .replace("\\n", "\n");
+    let absolute_path = directive.resolve_path(base_dir, config, files)?;
+    let source = fs::read_to_string(&absolute_path)
+        .with_context(|| format!("Failed to read file: {}", get_relative_path(&absolute_path)))?;
+
+    let output = run_example(&absolute_path)?;
+    let (expected, output) = (expected.trim_end(), output.trim_end());
+    if expected != output {
+        return Err(OutputMismatch {
+            example: get_relative_path(&absolute_path),
+            diff: format_line_diff(expected, output),
+        }
+        .into());
+    }
+
+    Ok(format!(
+        "{}\n\n{}",
+        format_fence(config.fence_info("assert_output"), source.trim_end()),
+        format_fence("text", output)
+    ))
+}
+
+/// Process assert_signature! directive
+/// Shows a function's signature, failing the build if it differs from the `expected` one
+/// Both are compared as `prettyplease` renders them, so whitespace and trailing commas don't count
+/// With `warn = true`, a difference is only reported and the actual signature is shown anyway
+fn process_assert_signature_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    let directive = parse_directive_args(directive)?;
+    if directive.item.is_none() {
+        return Err(anyhow::anyhow!("Function name is required"));
+    }
+    let expected = directive
+        .option("expected")
+        .context("Expected signature is required")?;
+    let absolute_path = directive.resolve_path(base_dir, config, files)?;
+    let parsed_file = files.get(&absolute_path, config)?;
+    let name = directive.item.as_ref().expect("function name is required");
+
+    let sig = match find_function(&parsed_file, name) {
+        Some(function) => function.sig,
+        None => {
+            find_method(&parsed_file, name)
+                .with_context(|| format!("Function '{}' not found", name))?
+                .sig
+        }
+    };
+    let expected_sig = syn::parse_str::<Signature>(expected)
+        .map_err(|e| anyhow::anyhow!("Expected signature isn't a valid signature: {}", e))?;
+    if format_signature_pretty(&expected_sig) != format_signature_pretty(&sig) {
+        let mismatch = SignatureMismatch {
+            function: name.to_string(),
+            expected: expected.to_string(),
+            actual: format_signature(&sig)
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" "),
+        };
+        if !directive.flag("warn")? {
+            return Err(mismatch.into());
+        }
+        eprintln!("Warning: {}", mismatch);
+    }
+    Ok(format_signature(&sig))
+}
+
+/// Process expand! directive
+/// Shows an item as `cargo expand` expands the crate containing the file, followed by the
+/// impls that derive macros generated for it. Expanding builds the crate, so it has to be
+/// enabled with `allow-expand`, and each crate is only expanded once per run
+fn process_expand_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    if !config.allow_expand {
+        return Err(anyhow::anyhow!(
+            "expand! builds the crate, so it must be enabled with allow-expand = true"
+        ));
+    }
+    let directive = parse_directive_args(directive)?;
+    let Some(item_spec) = directive.item.as_ref() else {
+        return Err(anyhow::anyhow!("Item name is required"));
+    };
+    let absolute_path = directive.resolve_path(base_dir, config, files)?;
+    let absolute_path = absolute_path
+        .canonicalize()
+        .with_context(|| format!("Failed to read file: {}", get_relative_path(&absolute_path)))?;
+    let crate_root = find_crate_root(&absolute_path, config).with_context(|| {
+        format!(
+            "expand! found no crate containing {}",
+            get_relative_path(&absolute_path)
+        )
+    })?;
+
+    let expanded = files.get_expanded(&crate_root)?;
+    let item = find_item_by_spec(&expanded, item_spec)
+        .with_context(|| format!("'{}' not found in the expanded crate", item_spec))?;
+    let mut sections = vec![format_item_pretty(&item)];
+    if let Item::Struct(ItemStruct { ident, .. }) | Item::Enum(ItemEnum { ident, .. }) = &item {
+        sections.extend(
+            find_impls_of(&expanded, &ident.to_string())
+                .into_iter()
+                .filter(|item_impl| {
+                    item_impl
+                        .attrs
+                        .iter()
+                        .any(|attr| attr.path().is_ident("automatically_derived"))
+                })
+                .map(|item_impl| format_item_pretty(&Item::Impl(item_impl))),
+        );
+    }
+    Ok(format_fence(
+        config.fence_info("expand"),
+        &sections.join("\n"),
+    ))
+}
+
+/// Process impls_of! directive
+/// Lists the traits a type implements in the file, as prose rather than code
+fn process_impls_of_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    let directive = parse_directive_args(directive)?;
+    if directive.item.is_none() {
+        return Err(anyhow::anyhow!("Type name is required"));
+    }
+    let absolute_path = directive.resolve_path(base_dir, config, files)?;
+    let parsed_file = files.get(&absolute_path, config)?;
+    let type_name = directive.item.as_ref().expect("type name is required");
+    Ok(format_impls_list(
+        type_name,
+        &find_impls_of(&parsed_file, type_name),
+    ))
+}
+
+/// Process usages! directive
+/// Lists the functions, methods and impl blocks of a file that mention a type, with their lines
+fn process_usages_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    let directive = parse_directive_args(directive)?;
+    if directive.item.is_none() {
+        return Err(anyhow::anyhow!("Type name is required"));
+    }
+    let absolute_path = directive.resolve_path(base_dir, config, files)?;
+    let parsed_file = files.get(&absolute_path, config)?;
+    let type_name = directive.item.as_ref().expect("type name is required");
+    Ok(format_usages(
+        type_name,
+        &find_usages(&parsed_file, type_name),
+    ))
+}
+
+/// Process trait_stub! directive
+/// Shows a trait followed by a skeleton implementation of it, as a starting point for readers
+/// implementing it. The skeleton implements it for a `/* YourType */` placeholder, so it
+/// doesn't compile and nothing from the rest of the file is hidden in it
+fn process_trait_stub_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    let directive = parse_directive_args(directive)?;
+    if directive.item.is_none() {
+        return Err(anyhow::anyhow!("Trait name is required"));
+    }
+    let absolute_path = directive.resolve_path(base_dir, config, files)?;
+    let parsed_file = files.get(&absolute_path, config)?;
+    let trait_name = directive.item.as_ref().expect("trait name is required");
+    let item_trait = find_trait(&parsed_file, trait_name)
+        .with_context(|| format!("Trait '{}' not found", trait_name))?;
+    let stub = format_trait_stub(&item_trait);
+    Ok(format!(
+        "{}\n\n{}",
+        render_item(&Item::Trait(item_trait), config)?,
+        stub
+    ))
+}
+
+/// Process impl_summary! directive
+/// Shows a type's inherent impl, or a trait impl given as `Trait for Type`, with every method
+/// body elided, as a list of its methods
+/// Elided bodies don't compile, so the rest of the file isn't added as hidden lines
+fn process_impl_summary_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    let directive = parse_directive_args(directive)?;
+    if directive.item.is_none() {
+        return Err(anyhow::anyhow!("Type name is required"));
+    }
+    let absolute_path = directive.resolve_path(base_dir, config, files)?;
+    let parsed_file = files.get(&absolute_path, config)?;
+    let type_name = directive.item.as_ref().expect("type name is required");
+    let item_impl = match type_name.split_once(" for ") {
+        Some((trait_name, struct_name)) => {
+            find_trait_impl(&parsed_file, trait_name.trim(), struct_name.trim())
+        }
+        None => find_struct_impl(&parsed_file, type_name),
+    }
+    .with_context(|| format!("Impl '{}' not found", type_name))?;
+    format_impl_summary(&item_impl)
 }
 
-/// Find line and column number from a position in the text
-fn find_line_and_col(line_positions: &[usize], position: usize) -> (usize, usize) {
-    let mut line_idx = 0;
+/// Process dossier! directive
+/// Shows a struct followed by its inherent impls and then its trait impls, each group in
+/// source order and each item under a `// struct Name`, `// impl Name` or
+/// `// impl Trait for Name` comment. The rest of the file is hidden as usual
+fn process_dossier_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    let directive = parse_directive_args(directive)?;
+    if directive.item.is_none() {
+        return Err(anyhow::anyhow!("Struct name is required"));
+    }
+    let absolute_path = directive.resolve_path(base_dir, config, files)?;
+    let parsed_file = files.get(&absolute_path, config)?;
+    let name = directive.item.as_ref().expect("struct name is required");
+    let struct_item = Item::Struct(
+        find_struct(&parsed_file, name).with_context(|| format!("Struct '{}' not found", name))?,
+    );
+    let type_name = name.rsplit("::").next().unwrap_or(name).trim();
+    let (inherent_impls, trait_impls): (Vec<_>, Vec<_>) = find_impls_of(&parsed_file, type_name)
+        .into_iter()
+        .partition(|item_impl| item_impl.trait_.is_none());
 
-    // Find the line containing the position
-    for (idx, &start) in line_positions.iter().enumerate() {
-        if position >= start {
-            line_idx = idx;
-        } else {
-            break;
-        }
+    let mut sections = vec![(format!("struct {}", type_name), struct_item.clone())];
+    for item_impl in inherent_impls {
+        sections.push((format!("impl {}", type_name), Item::Impl(item_impl)));
+    }
+    for item_impl in trait_impls {
+        let trait_name = item_impl
+            .trait_
+            .as_ref()
+            .and_then(|(_, path, _)| path.segments.last())
+            .map(|segment| segment.ident.to_string())
+            .unwrap_or_default();
+        sections.push((
+            format!("impl {} for {}", trait_name, type_name),
+            Item::Impl(item_impl),
+        ));
     }
 
-    // Line numbers are 1-indexed
-    let line_num = line_idx + 1;
-    // Calculate column number (1-indexed)
-    let col_num = position - line_positions[line_idx] + 1;
+    let (mut hidden_deps, _) = process_extra(&parsed_file, &struct_item, &[]);
+    hidden_deps.retain(|dep| !sections.iter().any(|(_, item)| item == dep));
 
-    (line_num, col_num)
+    let mut result = Output::new();
+    for dep in hidden_deps {
+        result.add_hidden_content(format_item(&dep, config)?);
+    }
+    for (i, (label, item)) in sections.iter().enumerate() {
+        let separator = if i == 0 { "" } else { "\n" };
+        result.add_visible_content(format!(
+            "{}// {}\n{}",
+            separator,
+            label,
+            render_item(item, config)?
+        ));
+    }
+    Ok(result.format(config))
 }
 
-/// Get the path relative to the current working directory
-pub(crate) fn get_relative_path(path: &Path) -> String {
-    if let Ok(current_dir) = env::current_dir() {
-        if let Ok(relative) = path.strip_prefix(&current_dir) {
-            return format!(
-                ".{}{}",
-                std::path::MAIN_SEPARATOR,
-                relative.to_string_lossy()
-            );
-        }
+/// Process items! directive
+/// Shows each item of a list like `[struct Foo, impl Foo]` in the order given, separated by
+/// blank lines. None of them is primary, so the rest of the file isn't added as hidden lines
+fn process_items_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    let directive = parse_directive_args(directive)?;
+    if directive.extra_items.is_empty() {
+        return Err(anyhow::anyhow!(
+            "A list of items is required, like [struct Foo, impl Foo]"
+        ));
     }
+    let absolute_path = directive.resolve_path(base_dir, config, files)?;
+    let parsed_file = files.get(&absolute_path, config)?;
 
-    // Fall back to the original path if we can't get a relative path
-    format!(".{}{}", std::path::MAIN_SEPARATOR, path.to_string_lossy())
+    let items = directive
+        .extra_items
+        .iter()
+        .map(|spec| {
+            find_item_by_spec(&parsed_file, spec)
+                .with_context(|| format!("Item '{}' not found", spec))
+                .and_then(|item| render_item(&item, config))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(items.join("\n\n"))
 }
 
-/// Process an include-rs directive
-fn process_include_rs_directive(base_dir: &Path, directive: &str) -> Result<String> {
-    // Parse the directive name
-    let directive_name = if let Some(pos) = directive.find('!') {
-        &directive[0..pos]
-    } else {
-        // Not a recognized directive format
-        return Ok(directive.to_string());
-    };
-
-    // Process the directive based on its type
-    let result = match directive_name {
-        "source_file" => process_source_file_directive(base_dir, directive)?,
-        "function_body" => {
-            // Try to find as a regular function first
-            if let Ok(result) = process_directive::<ItemFn>(
-                base_dir,
-                directive,
-                |f, n| Some(Item::Fn(find_function(f, n)?)),
-                format_function_body,
-            ) {
-                result
-            } else {
-                // If not found, try to find as a method
-                process_method_body_directive(base_dir, directive)?
-            }
-        }
-        "struct" => process_directive::<Struct>(
-            base_dir,
-            directive,
-            |f, n| Some(Item::Struct(find_struct(f, n)?)),
-            format_item,
-        )?,
-        "enum" => process_directive::<Enum>(
-            base_dir,
-            directive,
-            |f, n| Some(Item::Enum(find_enum(f, n)?)),
-            format_item,
-        )?,
-        "trait" => process_directive::<Trait>(
-            base_dir,
-            directive,
-            |f, n| Some(Item::Trait(find_trait(f, n)?)),
-            format_item,
-        )?,
-        "impl" => process_directive::<Impl>(
-            base_dir,
-            directive,
-            |f, n| Some(Item::Impl(find_struct_impl(f, n)?)),
-            format_item,
-        )?,
-        "trait_impl" => process_directive::<Impl>(
-            base_dir,
-            directive,
-            |f, n| {
-                // For trait_impl, the item_name should have the format "TraitName for StructName"
-                let parts: Vec<&str> = n.split(" for ").collect();
-                if parts.len() != 2 {
-                    return None;
-                }
-
-                let trait_name = parts[0].trim();
-                let struct_name = parts[1].trim();
+/// Process literate! directive
+/// Shows a function, or a method given as `StructName::method_name`, as its doc comment in
+/// prose followed by its body in code, with `///` narration in the body interleaved as prose
+fn process_literate_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    let directive = parse_directive_args(directive)?;
+    if directive.item.is_none() {
+        return Err(anyhow::anyhow!("Function name is required"));
+    }
+    let absolute_path = directive.resolve_path(base_dir, config, files)?;
+    let parsed_file = files.get(&absolute_path, config)?;
+    let name = directive.item.as_ref().expect("function name is required");
 
-                Some(Item::Impl(find_trait_impl(f, trait_name, struct_name)?))
-            },
-            format_item,
-        )?,
-        "function" => {
-            // Try to find as a regular function first
-            if let Ok(result) = process_directive::<ItemFn>(
-                base_dir,
-                directive,
-                |f, n| Some(Item::Fn(find_function(f, n)?)),
-                format_item,
-            ) {
-                result
-            } else {
-                // If not found, try to find as a method
-                process_method_directive(base_dir, directive)?
-            }
-        }
-        _ => {
-            // Not a recognized directive
-            return Ok(directive.to_string());
+    let (attrs, block) = match find_function(&parsed_file, name) {
+        Some(function) => (function.attrs, *function.block),
+        None => {
+            let method = find_method(&parsed_file, name)
+                .with_context(|| format!("Function '{}' not found", name))?;
+            (method.attrs, method.block)
         }
     };
-
-    // Format the result as a Rust code block
-    Ok(result.trim().to_string())
+    Ok(format_literate(
+        &attrs,
+        &block,
+        config.fence_info("literate"),
+    ))
 }
 
-/// Process source_file! directive
-fn process_source_file_directive(base_dir: &Path, directive: &str) -> Result<String> {
+/// Process method_body directive for methods in impl blocks
+fn process_method_body_directive(
+    base_dir: &Path,
+    directive: &str,
+    wrapper: &MainWrapper,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
     let directive = parse_directive_args(directive)?;
-    let absolute_path = base_dir.join(directive.file_path);
-    let content = fs::read_to_string(&absolute_path)
-        .with_context(|| format!("Failed to read file: {}", get_relative_path(&absolute_path)))?;
-    Ok(content)
+    if directive.item.is_none() {
+        return Err(anyhow::anyhow!("Method specification is required"));
+    }
+    let absolute_path = directive.resolve_path(base_dir, config, files)?;
+    let parsed_file = files.get(&absolute_path, config)?;
+    let method_spec = directive.item.as_ref().expect("method spec is required");
+    let methods = find_methods(&parsed_file, method_spec);
+    let method = select_item(
+        methods,
+        directive.selector.as_ref(),
+        directive.cfg.as_ref(),
+        |method| &method.attrs,
+    )?
+    .with_context(|| format!("Method '{}' not found", method_spec))?;
+
+    // Process extra dependencies if provided
+    let (mut hidden_deps, visible_deps) =
+        process_extra_for_method(&parsed_file, &directive.extra_items);
+    hidden_deps.extend(imports(&directive, &parsed_file)?);
+    hidden_deps.extend(auto_deps(
+        &directive,
+        &parsed_file,
+        &method.sig,
+        Some(&method.block),
+        &visible_deps,
+    )?);
+    hidden_deps.extend(invoked_macros(&directive, &parsed_file, &method)?);
+    let mut result = Output::new();
+    for dep in hidden_deps {
+        result.add_hidden_content(format_item(&dep, config)?);
+    }
+    for dep in visible_deps {
+        result.add_visible_content(format_item(&dep, config)?);
+    }
+
+    result.add_visible_content(format_method_body(&method, wrapper, config)?);
+    Ok(result.format(config))
 }
 
-/// Process method_body directive for methods in impl blocks
-fn process_method_body_directive(base_dir: &Path, directive: &str) -> Result<String> {
+/// Process trait_method_body! directive
+/// Shows the default body of a trait method given as `TraitName::method_name`, wrapped in
+/// `fn main` like function_body!. The body can't refer to `self` and still compile on its own
+fn process_trait_method_body_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
     let directive = parse_directive_args(directive)?;
     if directive.item.is_none() {
         return Err(anyhow::anyhow!("Method specification is required"));
     }
-    let absolute_path = base_dir.join(directive.file_path);
-    let parsed_file = read_and_parse_file(&absolute_path)?;
     let method_spec = directive.item.as_ref().expect("method spec is required");
-    let method = find_method(&parsed_file, method_spec)
-        .with_context(|| format!("Method '{}' not found", method_spec))?;
+    let (trait_name, method_name) = method_spec
+        .rsplit_once("::")
+        .map(|(trait_name, method_name)| (trait_name.trim(), method_name.trim()))
+        .with_context(|| format!("Expected 'TraitName::method_name', got '{}'", method_spec))?;
+    let wrapper = main_wrapper(&directive)?;
+    let absolute_path = directive.resolve_path(base_dir, config, files)?;
+    let parsed_file = files.get(&absolute_path, config)?;
 
-    // Process extra dependencies if provided
-    let (hidden_deps, visible_deps) =
-        process_extra_for_method(&parsed_file, &method, &directive.extra_items);
+    let method = find_trait_method(&parsed_file, trait_name, method_name).with_context(|| {
+        format!(
+            "Method '{}' not found in trait '{}'",
+            method_name, trait_name
+        )
+    })?;
+    let body = format_trait_method_body(&method, &wrapper, config)?
+        .with_context(|| format!("'{}' has no default body", method_spec))?;
+
+    let (mut hidden_deps, visible_deps) =
+        process_extra_for_method(&parsed_file, &directive.extra_items);
+    hidden_deps.extend(imports(&directive, &parsed_file)?);
+    hidden_deps.extend(auto_deps(
+        &directive,
+        &parsed_file,
+        &method.sig,
+        method.default.as_ref(),
+        &visible_deps,
+    )?);
+    hidden_deps.extend(invoked_macros(&directive, &parsed_file, &method)?);
     let mut result = Output::new();
     for dep in hidden_deps {
-        result.add_hidden_content(format_item(&dep));
+        result.add_hidden_content(format_item(&dep, config)?);
     }
     for dep in visible_deps {
-        result.add_visible_content(format_item(&dep));
+        result.add_visible_content(format_item(&dep, config)?);
     }
 
-    result.add_visible_content(format_method_body(&method));
-    Ok(result.format())
+    result.add_visible_content(body);
+    Ok(result.format(config))
 }
 
 /// Process method directive for methods in impl blocks (complete method including signature)
-fn process_method_directive(base_dir: &Path, directive: &str) -> Result<String> {
+fn process_method_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
     let directive = parse_directive_args(directive)?;
     if directive.item.is_none() {
         return Err(anyhow::anyhow!("Method specification is required"));
     }
-    let absolute_path = base_dir.join(directive.file_path);
-    let parsed_file = read_and_parse_file(&absolute_path)?;
+    let absolute_path = directive.resolve_path(base_dir, config, files)?;
+    let parsed_file = files.get(&absolute_path, config)?;
     let method_spec = directive.item.as_ref().expect("method spec is required");
-    let method = find_method(&parsed_file, method_spec)
-        .with_context(|| format!("Method '{}' not found", method_spec))?;
+    let methods = find_methods(&parsed_file, method_spec);
+    let method = select_item(
+        methods,
+        directive.selector.as_ref(),
+        directive.cfg.as_ref(),
+        |method| &method.attrs,
+    )?
+    .with_context(|| format!("Method '{}' not found", method_spec))?;
 
     // Process extra dependencies if provided
-    let (hidden_deps, visible_deps) =
-        process_extra_for_method(&parsed_file, &method, &directive.extra_items);
+    let (mut hidden_deps, visible_deps) =
+        process_extra_for_method(&parsed_file, &directive.extra_items);
+    hidden_deps.extend(imports(&directive, &parsed_file)?);
+    hidden_deps.extend(auto_deps(
+        &directive,
+        &parsed_file,
+        &method.sig,
+        Some(&method.block),
+        &visible_deps,
+    )?);
+    hidden_deps.extend(invoked_macros(&directive, &parsed_file, &method)?);
     let mut result = Output::new();
     for dep in hidden_deps {
-        result.add_hidden_content(format_item(&dep));
+        result.add_hidden_content(format_item(&dep, config)?);
     }
     for dep in visible_deps {
-        result.add_visible_content(format_item(&dep));
+        result.add_visible_content(format_item(&dep, config)?);
     }
 
     // Use the method formatter to show the complete method signature and body
-    use crate::formatter::format_method;
-    result.add_visible_content(format_method(&method));
-    Ok(result.format())
+    result.add_visible_content(format_with_rustfmt(format_method(&method)?, config));
+    Ok(result.format(config))
+}
+
+/// Process variant! directive
+/// Shows a single variant of an enum, named as `EnumName::Variant`
+fn process_variant_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    let directive = parse_directive_args(directive)?;
+    let variant_spec = directive
+        .item
+        .as_ref()
+        .context("Variant is required, as EnumName::Variant")?;
+    let (enum_name, variant_name) = variant_spec
+        .rsplit_once("::")
+        .context("Variant must be written as EnumName::Variant")?;
+    let absolute_path = directive.resolve_path(base_dir, config, files)?;
+    let parsed_file = files.get(&absolute_path, config)?;
+    let item_enum = select_item(
+        find_enums(&parsed_file, enum_name),
+        directive.selector.as_ref(),
+        directive.cfg.as_ref(),
+        |item_enum| &item_enum.attrs,
+    )?
+    .with_context(|| format!("Enum '{}' not found", enum_name))?;
+    let variant = find_variant(&item_enum, variant_name).with_context(|| {
+        let variants = item_enum
+            .variants
+            .iter()
+            .map(|variant| variant.ident.to_string())
+            .collect::<Vec<_>>();
+        format!(
+            "Variant '{}' not found in enum '{}', which has {}",
+            variant_name,
+            enum_name,
+            variants.join(", ")
+        )
+    })?;
+
+    let mut result = Output::new();
+    result.add_visible_content(format_variant(&variant)?);
+    Ok(result.format(config))
+}
+
+/// Keep only the listed methods of impl blocks of the same type, in the order they appear,
+/// along with their associated consts and types. Impl blocks with none of the methods are
+/// left out, and it's an error for a method to be in none of them or for the list to be empty
+fn select_methods(impls: &[ItemImpl], methods: &[String]) -> Result<Vec<ItemImpl>> {
+    if methods.is_empty() {
+        anyhow::bail!("methods needs at least one name");
+    }
+    let is_listed = |item: &ImplItem| {
+        matches!(
+            item,
+            ImplItem::Fn(method) if methods.iter().any(|name| method.sig.ident == name)
+        )
+    };
+    let has_method = |name: &String| {
+        impls
+            .iter()
+            .flat_map(|item_impl| &item_impl.items)
+            .any(|item| matches!(item, ImplItem::Fn(method) if method.sig.ident == name))
+    };
+    let missing = methods
+        .iter()
+        .filter(|name| !has_method(name))
+        .map(|name| format!("'{}'", name))
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        let self_ty = impls
+            .first()
+            .and_then(|item_impl| item_impl.self_ty.span().source_text())
+            .unwrap_or_default();
+        return Err(anyhow::anyhow!(
+            "Method{} {} not found in impl {}",
+            if missing.len() == 1 { "" } else { "s" },
+            missing.join(", "),
+            self_ty
+        ));
+    }
+
+    Ok(impls
+        .iter()
+        .filter(|item_impl| item_impl.items.iter().any(is_listed))
+        .map(|item_impl| {
+            let mut item_impl = item_impl.clone();
+            item_impl
+                .items
+                .retain(|item| is_listed(item) || !matches!(item, ImplItem::Fn(_)));
+            item_impl
+        })
+        .collect())
+}
+
+/// Render an impl block left with only some of its items, keeping what's left as written
+/// unless rendering with `prettyplease`
+fn render_impl_subset(item_impl: &ItemImpl, config: &Config) -> Result<String> {
+    if config.render == Render::Pretty {
+        render_item(&Item::Impl(item_impl.clone()), config)
+    } else {
+        Ok(format_with_rustfmt(format_impl_subset(item_impl)?, config))
+    }
+}
+
+/// Process impl! directive with `merge`
+/// Shows every inherent impl block of a type in source order, as they would read if they
+/// were one. Trait implementations aren't included
+fn process_merged_impl_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    let directive = parse_directive_args(directive)?;
+    let type_name = directive.item.as_ref().context("Type name is required")?;
+    let absolute_path = directive.resolve_path(base_dir, config, files)?;
+    let parsed_file = files.get(&absolute_path, config)?;
+    let impls = find_struct_impls(&parsed_file, type_name)
+        .into_iter()
+        .filter(|item_impl| {
+            directive
+                .cfg
+                .as_ref()
+                .is_none_or(|cfg| cfg.enables(&item_impl.attrs))
+        })
+        .collect::<Vec<_>>();
+    if impls.is_empty() {
+        return Err(anyhow::anyhow!("No impl blocks found for '{}'", type_name));
+    }
+
+    let mut result = Output::new();
+    for item in &parsed_file.items {
+        if !matches!(item, Item::Impl(item_impl) if impls.contains(item_impl)) {
+            result.add_hidden_content(format_item(item, config)?);
+        }
+    }
+    let visible = match directive.list_option("methods") {
+        Some(methods) => select_methods(&impls, &methods)?
+            .iter()
+            .map(|item_impl| render_impl_subset(item_impl, config))
+            .collect::<Result<Vec<_>>>()?,
+        None => impls
+            .into_iter()
+            .map(|item_impl| render_item(&Item::Impl(item_impl), config))
+            .collect::<Result<Vec<_>>>()?,
+    };
+    result.add_visible_content(visible.join("\n\n"));
+    Ok(result.format(config))
+}
+
+/// Process implementors! directive
+/// Shows every implementation of a trait in a file, for any type, in source order, each
+/// after an `// impl for Type` comment. The rest of the file, with the trait, is hidden
+fn process_implementors_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    let directive = parse_directive_args(directive)?;
+    let trait_name = directive.item.as_ref().context("Trait name is required")?;
+    let absolute_path = directive.resolve_path(base_dir, config, files)?;
+    let parsed_file = files.get(&absolute_path, config)?;
+    let impls = find_implementors(&parsed_file, trait_name)
+        .into_iter()
+        .filter(|item_impl| {
+            directive
+                .cfg
+                .as_ref()
+                .is_none_or(|cfg| cfg.enables(&item_impl.attrs))
+        })
+        .collect::<Vec<_>>();
+    if impls.is_empty() {
+        return Err(anyhow::anyhow!(
+            "No implementations of '{}' found",
+            trait_name
+        ));
+    }
+
+    let mut result = Output::new();
+    for item in &parsed_file.items {
+        if !matches!(item, Item::Impl(item_impl) if impls.contains(item_impl)) {
+            result.add_hidden_content(format_item(item, config)?);
+        }
+    }
+    let visible = impls
+        .iter()
+        .map(|item_impl| {
+            let rendered = render_item(&Item::Impl(item_impl.clone()), config)?;
+            Ok(format_implementor(item_impl, &rendered))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    result.add_visible_content(visible.join("\n\n"));
+    Ok(result.format(config))
+}
+
+/// Process field! directive
+/// Shows a single named field of a struct, written as `StructName::field`
+fn process_field_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    let directive = parse_directive_args(directive)?;
+    let field_spec = directive
+        .item
+        .as_ref()
+        .context("Field is required, as StructName::field")?;
+    let (struct_name, field_name) = field_spec
+        .rsplit_once("::")
+        .context("Field must be written as StructName::field")?;
+    let absolute_path = directive.resolve_path(base_dir, config, files)?;
+    let parsed_file = files.get(&absolute_path, config)?;
+    let item_struct = select_item(
+        find_structs(&parsed_file, struct_name),
+        directive.selector.as_ref(),
+        directive.cfg.as_ref(),
+        |item_struct| &item_struct.attrs,
+    )?
+    .with_context(|| format!("Struct '{}' not found", struct_name))?;
+    let field = match &item_struct.fields {
+        Fields::Named(fields) => find_field(&item_struct, field_name).with_context(|| {
+            let names = fields
+                .named
+                .iter()
+                .filter_map(|field| field.ident.as_ref().map(ToString::to_string))
+                .collect::<Vec<_>>();
+            format!(
+                "Field '{}' not found in struct '{}', which has {}",
+                field_name,
+                struct_name,
+                names.join(", ")
+            )
+        })?,
+        Fields::Unnamed(_) => {
+            return Err(anyhow::anyhow!(
+                "Struct '{}' is a tuple struct, whose fields have no names",
+                struct_name
+            ));
+        }
+        Fields::Unit => {
+            return Err(anyhow::anyhow!("Struct '{}' has no fields", struct_name));
+        }
+    };
+
+    let mut result = Output::new();
+    result.add_visible_content(format_field(&field)?);
+    Ok(result.format(config))
+}
+
+/// Process assoc! directive
+/// Shows an associated const or type of an impl block, named like a method as
+/// `StructName::NAME` or `TraitName for StructName::NAME`
+fn process_assoc_directive(
+    base_dir: &Path,
+    directive: &str,
+    config: &Config,
+    files: &ParsedFiles,
+) -> Result<String> {
+    let directive = parse_directive_args(directive)?;
+    if directive.item.is_none() {
+        return Err(anyhow::anyhow!("Associated item specification is required"));
+    }
+    let absolute_path = directive.resolve_path(base_dir, config, files)?;
+    let parsed_file = files.get(&absolute_path, config)?;
+    let item_spec = directive.item.as_ref().expect("item spec is required");
+    let item = select_item(
+        find_assoc_items(&parsed_file, item_spec),
+        directive.selector.as_ref(),
+        directive.cfg.as_ref(),
+        |item| match item {
+            ImplItem::Const(item_const) => &item_const.attrs,
+            ImplItem::Type(item_type) => &item_type.attrs,
+            _ => &[],
+        },
+    )?
+    .with_context(|| format!("Associated const or type '{}' not found", item_spec))?;
+
+    let (mut hidden_deps, visible_deps) =
+        process_extra_for_method(&parsed_file, &directive.extra_items);
+    hidden_deps.extend(imports(&directive, &parsed_file)?);
+    let mut result = Output::new();
+    for dep in hidden_deps {
+        result.add_hidden_content(format_item(&dep, config)?);
+    }
+    for dep in visible_deps {
+        result.add_visible_content(format_item(&dep, config)?);
+    }
+    result.add_visible_content(format_impl_item(&item)?);
+    Ok(result.format(config))
+}
+
+/// With `auto_deps`, the structs, enums, traits and type aliases of the file that a method
+/// refers to, and those they refer to, which are added as hidden lines unless they're shown
+fn auto_deps(
+    directive: &Directive,
+    parsed_file: &File,
+    sig: &Signature,
+    block: Option<&Block>,
+    visible_deps: &[Item],
+) -> Result<Vec<Item>> {
+    if !directive.flag("auto_deps")? {
+        return Ok(Vec::new());
+    }
+    let mut types = find_referenced_types(parsed_file, sig, block);
+    types.retain(|item| !visible_deps.contains(item));
+    Ok(types)
+}
+
+/// With `with_imports`, the file's top-level `use` items, each written once, which are added
+/// as hidden lines so a snippet that only shows a method still has its imports
+fn imports(directive: &Directive, parsed_file: &File) -> Result<Vec<Item>> {
+    if !directive.flag("with_imports")? {
+        return Ok(Vec::new());
+    }
+    let mut seen = HashSet::new();
+    Ok(parsed_file
+        .items
+        .iter()
+        .filter(|item| matches!(item, Item::Use(_)))
+        .filter(|item| seen.insert(item.to_token_stream().to_string()))
+        .cloned()
+        .collect())
+}
+
+/// With `with_macros`, the local `macro_rules!` definitions that `node` invokes, which are
+/// added as hidden lines so a snippet that only shows a method still compiles
+fn invoked_macros(
+    directive: &Directive,
+    parsed_file: &File,
+    node: &impl ToTokens,
+) -> Result<Vec<Item>> {
+    if !directive.flag("with_macros")? {
+        return Ok(Vec::new());
+    }
+    Ok(find_invoked_macros(parsed_file, node.to_token_stream())
+        .into_iter()
+        .map(Item::Macro)
+        .collect())
 }
 
 /// Helper function to process extra items
@@ -315,11 +2701,7 @@ fn process_extra(
 }
 
 /// Helper function to process extra items for methods - simplified version
-fn process_extra_for_method(
-    parsed_file: &File,
-    _method: &ImplItemFn,
-    extra_items: &[String],
-) -> (Vec<Item>, Vec<Item>) {
+fn process_extra_for_method(parsed_file: &File, extra_items: &[String]) -> (Vec<Item>, Vec<Item>) {
     let hidden = Vec::new();
     let mut visible = Vec::new();
 
@@ -377,8 +2759,10 @@ fn process_extra_for_method(
 fn process_directive<T>(
     base_dir: &Path,
     directive: &str,
-    finder: impl Fn(&File, &str) -> Option<Item>,
-    formatter: impl Fn(&Item) -> String,
+    finder: impl Fn(&File, &str) -> Vec<Item>,
+    formatter: impl Fn(&Item) -> Result<String>,
+    config: &Config,
+    files: &ParsedFiles,
 ) -> Result<String> {
     let directive = parse_directive_args(directive)?;
     if directive.item.is_none() {
@@ -387,20 +2771,124 @@ fn process_directive<T>(
             std::any::type_name::<T>()
         ));
     }
-    let absolute_path = base_dir.join(directive.file_path);
-    let parsed_file = read_and_parse_file(&absolute_path)?;
+    let absolute_path = directive.resolve_path(base_dir, config, files)?;
+    let parsed_file = files.get(&absolute_path, config)?;
     let item_name = directive.item.as_ref().expect("item name is required");
-    let item = finder(&parsed_file, item_name)
-        .with_context(|| format!("{} '{}' not found", std::any::type_name::<T>(), item_name))?;
-    let (hidden_deps, visible_deps) = process_extra(&parsed_file, &item, &directive.extra_items);
+    // An item missing from the file may come from a file it brings in with `include!`,
+    // in which case its dependencies are taken from that file too
+    let selector = directive.selector.as_ref();
+    let cfg = directive.cfg.as_ref();
+    let (parsed_file, item) =
+        match select_item(finder(&parsed_file, item_name), selector, cfg, item_attrs)? {
+            Some(item) => (parsed_file, item),
+            None => find_in_included_files(&parsed_file, &absolute_path, config, files, &|file| {
+                select_item(finder(file, item_name), selector, cfg, item_attrs)
+                    .ok()
+                    .flatten()
+            })
+            .with_context(|| format!("{} '{}' not found", std::any::type_name::<T>(), item_name))?,
+        };
+    let (mut hidden_deps, visible_deps) =
+        process_extra(&parsed_file, &item, &directive.extra_items);
+    // With `closure`, only the items the snippet refers to, directly or through each other,
+    // are hidden along with the file's imports, rather than the whole file
+    if directive.flag("closure")? {
+        let roots = std::iter::once(item.clone())
+            .chain(visible_deps.iter().cloned())
+            .collect::<Vec<_>>();
+        let (closure, truncated) = find_closure(&parsed_file, &roots);
+        if truncated {
+            eprintln!(
+                "Warning: '{}' refers to more than {} items, the rest are left out of its closure",
+                item_name, MAX_CLOSURE_ITEMS
+            );
+        }
+        hidden_deps.retain(|dep| matches!(dep, Item::Use(_)) || closure.contains(dep));
+    }
+    // With `auto_deps`, a function's hidden lines are only the types it refers to, with the
+    // file's imports, rather than the whole file
+    if directive.flag("auto_deps")? {
+        let Item::Fn(function) = &item else {
+            return Err(anyhow::anyhow!("auto_deps only applies to functions"));
+        };
+        let types = find_referenced_types(&parsed_file, &function.sig, Some(&function.block));
+        hidden_deps.retain(|dep| matches!(dep, Item::Use(_)) || types.contains(dep));
+    }
+    // With `with_imports`, the file's imports come first, each written once
+    if directive.flag("with_imports")? {
+        hidden_deps.retain(|dep| !matches!(dep, Item::Use(_)));
+        hidden_deps.splice(0..0, imports(&directive, &parsed_file)?);
+    }
+    // With `with_module`, the item is shown inside its modules, which would otherwise be
+    // declared a second time by the hidden dependencies
+    let modules = if directive.flag("with_module")? {
+        find_enclosing_modules(&parsed_file, item.span())
+    } else {
+        Vec::new()
+    };
+    if let Some(outermost) = modules.first() {
+        hidden_deps.retain(|dep| !matches!(dep, Item::Mod(module) if module == *outermost));
+    }
+
     let mut result = Output::new();
     for dep in hidden_deps {
-        result.add_hidden_content(format_item(&dep));
+        result.add_hidden_content(format_item(&dep, config)?);
     }
     for dep in visible_deps {
-        result.add_visible_content(format_item(&dep));
+        result.add_visible_content(format_item(&dep, config)?);
+    }
+
+    // With `strip_docs` or `strip_attrs`, the item is re-rendered without them, since its
+    // source text has them, and likewise with its generics rewritten by `dyn_ify`
+    let strip_docs = directive.flag("strip_docs")?;
+    let strip_attrs = directive.flag("strip_attrs")?;
+    let code = if directive.flag("dyn_ify")? {
+        let Item::Fn(function) = strip_attributes(&item, strip_docs, strip_attrs) else {
+            return Err(anyhow::anyhow!("dyn_ify only applies to functions"));
+        };
+        let (function, notes) = dyn_ify(&function);
+        notes
+            .iter()
+            .map(|note| format!("// {}\n", note))
+            .chain([format_item_pretty(&Item::Fn(function))])
+            .collect()
+    } else if strip_docs || strip_attrs {
+        format_item_pretty(&strip_attributes(&item, strip_docs, strip_attrs))
+    } else {
+        formatter(&item)?
+    };
+    result.add_visible_content(format_in_modules(&code, &modules));
+    // With `usage_anchor`, an example of using the item, marked with anchors in the same
+    // file, follows it after a blank line
+    if let Some(anchor) = directive.option("usage_anchor") {
+        let content = fs::read_to_string(&absolute_path).with_context(|| {
+            format!("Failed to read file: {}", get_relative_path(&absolute_path))
+        })?;
+        let usage = read_anchor(&content, anchor, &absolute_path)?;
+        result.add_visible_content(format!("\n{}", usage));
+    }
+    // With `with_test_harness`, a function is followed by a test calling it with `test_args`
+    if directive.flag("with_test_harness")? {
+        let Item::Fn(function) = &item else {
+            return Err(anyhow::anyhow!(
+                "with_test_harness only applies to functions"
+            ));
+        };
+        if function.sig.asyncness.is_some() {
+            return Err(anyhow::anyhow!(
+                "with_test_harness doesn't support async functions"
+            ));
+        }
+        let harness = format_test_harness(function, test_args(&directive)?);
+        result.add_visible_content(format!("\n{}", harness));
     }
+    Ok(result.format(config))
+}
 
-    result.add_visible_content(formatter(&item));
-    Ok(result.format())
+/// Parse the `test_args = "..."` of a directive, the arguments a generated test harness calls
+/// the function with, as written. There are none if it isn't given
+fn test_args(directive: &Directive) -> Result<proc_macro2::TokenStream> {
+    let args = directive.option("test_args").unwrap_or_default();
+    args.parse::<proc_macro2::TokenStream>()
+        .map_err(|e| anyhow::anyhow!("Invalid test_args '{}': {}", args, e))
 }
@@ -1,38 +1,73 @@
 use anyhow::Result;
 use mdbook::book::{Book, BookItem};
 use mdbook::preprocess::{Preprocessor, PreprocessorContext};
-use toml::Value;
+use std::path::PathBuf;
 
+use crate::cache::DirectiveCache;
+use crate::config::{Config, PREPROCESSOR_NAME};
+use crate::extractor::ParsedFiles;
 use crate::parser::process_markdown;
 
+/// A chapter starting with this comment is left unprocessed, so it can show directives verbatim
+const SKIP_MARKER: &str = "<!-- include-rs: skip -->";
+
+/// Appended to a chapter's file name to name its sidecar file of options
+const SIDECAR_EXTENSION: &str = ".include.toml";
+
 /// Preprocessor that handles include-rs code blocks
 pub struct IncludeRsPreprocessor;
 
 impl Preprocessor for IncludeRsPreprocessor {
     fn name(&self) -> &str {
-        "include-rs"
+        PREPROCESSOR_NAME
     }
 
     fn run(&self, ctx: &PreprocessorContext, mut book: Book) -> Result<Book> {
-        let config_section = ctx.config.get_preprocessor(self.name());
-        // Get global base_dir from config if provided, otherwise set to None
-        let global_base_dir = if let Some(config) = config_section {
-            if let Some(Value::String(dir)) = config.get("base-dir") {
-                Some(ctx.root.join(dir))
-            } else {
-                None
-            }
-        } else {
-            None
-        };
+        let config = Config::from_env_and_toml(ctx)?;
 
         let src_dir = ctx.root.join("src");
+        let cache = match &config.cache_file {
+            Some(file) => DirectiveCache::load(ctx.root.join(file)),
+            None => DirectiveCache::disabled(),
+        };
+        // Files are parsed once per run, however many directives include them
+        let files = ParsedFiles::new();
 
+        // The first error that should fail the build, reported once every chapter is processed
+        let mut failure = None;
         book.for_each_mut(|item| {
             if let BookItem::Chapter(chapter) = item {
+                if chapter.content.trim_start().starts_with(SKIP_MARKER) {
+                    return;
+                }
+
+                let source_path = src_dir.join(
+                    chapter
+                        .source_path
+                        .clone()
+                        .unwrap_or_else(|| "SUMMARY.md".into()),
+                );
+
+                // A sidecar file next to the chapter overrides options for its directives
+                let mut sidecar = source_path.clone().into_os_string();
+                sidecar.push(SIDECAR_EXTENSION);
+                let sidecar = PathBuf::from(sidecar);
+                let config = if chapter.source_path.is_some() && sidecar.is_file() {
+                    match config.with_sidecar(&sidecar, &ctx.root) {
+                        Ok(config) => config,
+                        Err(e) => {
+                            eprintln!("Error processing chapter '{}': {:#}", chapter.name, e);
+                            failure.get_or_insert(e);
+                            return;
+                        }
+                    }
+                } else {
+                    config.clone()
+                };
+
                 // Get the directory of the chapter markdown file to use as the base if no global base_dir
-                let base_dir = if let Some(ref global_dir) = global_base_dir {
-                    global_dir.clone()
+                let base_dir = if let Some(ref global_dir) = config.base_dir {
+                    ctx.root.join(global_dir)
                 } else if let Some(ref source_path) = chapter.source_path {
                     // The SUMMARY.md file is always in src
                     // Use the directory containing the markdown file as base
@@ -46,20 +81,28 @@ impl Preprocessor for IncludeRsPreprocessor {
                     src_dir.clone()
                 };
 
-                let source_path = src_dir.join(
-                    chapter
-                        .source_path
-                        .clone()
-                        .unwrap_or_else(|| "SUMMARY.md".into()),
-                );
-
-                if let Err(e) = process_markdown(&base_dir, &source_path, &mut chapter.content) {
+                if let Err(e) = process_markdown(
+                    &base_dir,
+                    &source_path,
+                    &mut chapter.content,
+                    &config,
+                    &cache,
+                    &files,
+                ) {
                     eprintln!("Error processing chapter '{}': {}", chapter.name, e);
+                    failure.get_or_insert(e);
                 }
             }
         });
 
-        Ok(book)
+        if let Err(e) = cache.save() {
+            eprintln!("Warning: {}", e);
+        }
+
+        match failure {
+            Some(e) => Err(e),
+            None => Ok(book),
+        }
     }
 
     fn supports_renderer(&self, _renderer: &str) -> bool {
@@ -0,0 +1,73 @@
+use anyhow::{Context, Result, bail};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::parser::get_relative_path;
+
+/// An included example whose output differs from what the book says it prints
+/// Unlike other directive errors, this fails the build rather than being inlined
+#[derive(Debug)]
+pub(crate) struct OutputMismatch {
+    pub(crate) example: String,
+    pub(crate) diff: String,
+}
+
+impl fmt::Display for OutputMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Output of {} doesn't match the expected output:\n{}",
+            self.example, self.diff
+        )
+    }
+}
+
+impl std::error::Error for OutputMismatch {}
+
+/// Counts the examples built by this process, so each gets its own binary
+static BUILD_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Compile a single-file example with `rustc` and return what it prints to stdout
+/// The example may only use the standard library
+pub(crate) fn run_example(path: &Path) -> Result<String> {
+    let rel_path = get_relative_path(path);
+    let stem = path
+        .file_stem()
+        .with_context(|| format!("Not a file: {}", rel_path))?
+        .to_string_lossy();
+
+    let build_dir = std::env::temp_dir().join(format!("mdbook-include-rs-{}", std::process::id()));
+    fs::create_dir_all(&build_dir)
+        .with_context(|| format!("Failed to create {}", build_dir.display()))?;
+    let binary = build_dir.join(format!(
+        "{}-{}{}",
+        stem,
+        BUILD_COUNT.fetch_add(1, Ordering::Relaxed),
+        std::env::consts::EXE_SUFFIX
+    ));
+
+    let compiled = Command::new("rustc")
+        .args(["--edition", "2021", "--crate-name", "example", "-o"])
+        .arg(&binary)
+        .arg(path)
+        .output()
+        .context("Failed to run rustc")?;
+    if !compiled.status.success() {
+        bail!(
+            "Failed to compile {}:\n{}",
+            rel_path,
+            String::from_utf8_lossy(&compiled.stderr).trim_end()
+        );
+    }
+
+    let ran = Command::new(&binary)
+        .output()
+        .with_context(|| format!("Failed to run {}", rel_path))?;
+    if !ran.status.success() {
+        bail!("{} exited with {}", rel_path, ran.status);
+    }
+    Ok(String::from_utf8_lossy(&ran.stdout).into_owned())
+}
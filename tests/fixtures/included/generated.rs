@@ -0,0 +1,7 @@
+// Generated by build.rs, do not edit
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+include!("nested.rs");
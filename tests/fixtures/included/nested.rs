@@ -0,0 +1,4 @@
+enum Axis {
+    X,
+    Y,
+}
@@ -0,0 +1,5 @@
+/// Where to connect to
+pub struct Endpoint {
+    pub host: String,
+    pub port: u16,
+}
@@ -0,0 +1,5 @@
+pub mod config;
+
+pub fn connect(endpoint: &config::Endpoint) -> String {
+    format!("{}:{}", endpoint.host, endpoint.port)
+}
@@ -0,0 +1,4 @@
+fn main() {
+    let origin = Point::origin();
+    println!("({}, {})", origin.x, origin.y);
+}
@@ -0,0 +1,5 @@
+impl Point {
+    pub fn origin() -> Self {
+        Point { x: 0.0, y: 0.0 }
+    }
+}
@@ -0,0 +1,4 @@
+/// Shared between the chapters of part A
+pub fn shared_helper() -> u32 {
+    42
+}
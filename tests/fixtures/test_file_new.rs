@@ -0,0 +1,11 @@
+struct Config {
+    name: String,
+    timeout: std::time::Duration,
+    verbose: bool,
+    retries: u8,
+}
+
+enum Mode {
+    Fast,
+    Safe,
+}
@@ -0,0 +1,9 @@
+struct Config {
+    name: String,
+    timeout: u32,
+    verbose: bool,
+}
+
+struct Legacy {
+    enabled: bool,
+}
@@ -0,0 +1,6 @@
+pub struct Point { pub x: i32,
+    pub y: i32 }
+
+impl Point {
+    pub fn   manhattan(&self)->i32 { self.x.abs()+self.y.abs() }
+}
@@ -0,0 +1,24 @@
+// ANCHOR: all
+use std::collections::HashMap;
+
+// ANCHOR: setup
+fn setup() -> HashMap<String, u32> {
+    let mut scores = HashMap::new();
+    // ANCHOR: insert
+    scores.insert("alice".to_string(), 3);
+    scores.insert("bob".to_string(), 5);
+    // ANCHOR_END: insert
+    scores
+}
+// ANCHOR_END: setup
+
+fn total(scores: &HashMap<String, u32>) -> u32 {
+    // ANCHOR: sum
+    let total = scores.values().sum();
+    // ANCHOR: report
+    println!("total: {}", total);
+    // ANCHOR_END: sum
+    total
+    // ANCHOR_END: report
+}
+// ANCHOR_END: all
@@ -0,0 +1,21 @@
+use std::ops::Add;
+
+#[derive(Clone, Copy)]
+pub struct Meters(f64);
+
+impl Meters {
+    /// The length of a marathon
+    pub const MARATHON: Meters = Meters(42_195.0);
+
+    pub fn new(value: f64) -> Self {
+        Meters(value)
+    }
+}
+
+impl Add for Meters {
+    type Output = Meters;
+
+    fn add(self, other: Meters) -> Meters {
+        Meters(self.0 + other.0)
+    }
+}
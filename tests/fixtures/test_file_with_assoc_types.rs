@@ -0,0 +1,31 @@
+use std::ops::Add;
+
+struct Counter {
+    count: u32,
+}
+
+impl Iterator for Counter {
+    /// Counts are never negative
+    type Item = u32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.count += 1;
+        (self.count < 6).then_some(self.count)
+    }
+}
+
+impl Add for Counter {
+    type Output = Counter;
+
+    fn add(self, other: Counter) -> Counter {
+        Counter {
+            count: self.count + other.count,
+        }
+    }
+}
+
+impl Default for Counter {
+    fn default() -> Self {
+        Counter { count: 0 }
+    }
+}
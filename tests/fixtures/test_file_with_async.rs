@@ -0,0 +1,18 @@
+async fn fetch_greeting(name: &str) -> String {
+    format!("Hello, {}!", name)
+}
+
+async fn greet_all() {
+    let first = fetch_greeting("Ferris").await;
+    let second = fetch_greeting("Corro").await;
+    println!("{}\n{}", first, second);
+}
+
+async fn greet_checked(name: &str) -> Result<(), String> {
+    let greeting = fetch_greeting(name).await;
+    if greeting.is_empty() {
+        return Err(String::from("empty greeting"));
+    }
+    println!("{}", greeting);
+    Ok(())
+}
@@ -0,0 +1,23 @@
+/// A user of the service
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct User {
+    /// The user's unique id
+    pub id: u64,
+    #[allow(dead_code)]
+    name: String,
+}
+
+/// Helpers for users
+pub mod users {
+    //! Everything needed to look users up
+    #![allow(unused)]
+
+    /// Find a user by id
+    #[inline]
+    pub fn find(id: u64) -> Option<u64> {
+        #[cfg(debug_assertions)]
+        let id = id + 0;
+        Some(id)
+    }
+}
@@ -0,0 +1,7 @@
+fn padded_body() {
+
+    let greeting = "hello";
+
+    println!("{}", greeting);
+
+}
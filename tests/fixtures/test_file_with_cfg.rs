@@ -0,0 +1,36 @@
+#[cfg(unix)]
+pub fn data_dir() -> &'static str {
+    "/var/lib/app"
+}
+
+#[cfg(windows)]
+pub fn data_dir() -> &'static str {
+    "C:\\ProgramData\\app"
+}
+
+#[cfg(feature = "json")]
+pub struct Codec {
+    pretty: bool,
+}
+
+#[cfg(all(not(feature = "json"), feature = "binary"))]
+pub struct Codec {
+    compressed: bool,
+}
+
+#[cfg(not(any(feature = "json", feature = "binary")))]
+pub struct Codec;
+
+pub struct Server;
+
+impl Server {
+    #[cfg(unix)]
+    pub fn bind(&self) -> &'static str {
+        "unix socket"
+    }
+
+    #[cfg(not(unix))]
+    pub fn bind(&self) -> &'static str {
+        "tcp socket"
+    }
+}
@@ -0,0 +1,15 @@
+const STATUS_CODES: [(&str, u32); 3] = [
+    ("OK", 200),
+    ("Not Found", 404),
+    ("Internal Server Error", 500),
+];
+
+const MIME_TYPES: &[(&str, &str, bool)] = &[
+    ("html", "text/html", true),
+    ("png", "image/png", false),
+];
+
+const RETRY_DELAYS: [u64; 3] = [100, 200, 400];
+
+/// How many requests have been served
+static REQUEST_COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
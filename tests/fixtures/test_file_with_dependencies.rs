@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+
+/// Not referred to by anything in the catalog
+pub struct Unrelated;
+
+impl Unrelated {
+    pub fn noise() {}
+}
+
+pub trait Describe {
+    fn describe(&self) -> String;
+}
+
+pub enum Kind {
+    Book,
+    Film,
+}
+
+macro_rules! label {
+    ($text:expr) => {
+        $text.to_string()
+    };
+}
+
+fn format_kind(kind: &Kind) -> String {
+    match kind {
+        Kind::Book => label!("book"),
+        Kind::Film => label!("film"),
+    }
+}
+
+pub struct Entry {
+    name: String,
+    kind: Kind,
+}
+
+impl Describe for Entry {
+    fn describe(&self) -> String {
+        format!("{} ({})", self.name, format_kind(&self.kind))
+    }
+}
+
+pub struct Catalog {
+    entries: Vec<Entry>,
+    index: HashMap<String, usize>,
+}
+
+impl Catalog {
+    pub fn describe_all(&self) -> Vec<String> {
+        self.entries.iter().map(Describe::describe).collect()
+    }
+}
+
+pub struct Tree {
+    root: Node,
+}
+
+pub struct Node {
+    children: Vec<Node>,
+    tree: Option<Box<Tree>>,
+}
@@ -0,0 +1,27 @@
+// Test file with two functions of the same name behind cfg
+
+#[cfg(unix)]
+pub fn run() -> &'static str {
+    "unix"
+}
+
+#[cfg(not(unix))]
+pub fn run() -> &'static str {
+    "other"
+}
+
+pub struct Task;
+
+impl Task {
+    #[cfg(unix)]
+    pub fn spawn(&self) -> u32 {
+        1
+    }
+}
+
+impl Task {
+    #[cfg(not(unix))]
+    pub fn spawn(&self) -> u32 {
+        2
+    }
+}
@@ -0,0 +1,12 @@
+use std::num::ParseIntError;
+
+fn parse_port(text: &str) -> Result<u16, ParseIntError> {
+    let port = text.trim().parse::<u16>()?;
+    println!("Listening on {}", port);
+    Ok(port)
+}
+
+fn report(port: u16) {
+    let port = parse_port("8080")?;
+    println!("Using port {}", port);
+}
@@ -0,0 +1,23 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Settings for a build
+#[derive(Debug, Default)]
+pub struct Config {
+    /// The directory the book is written to
+    pub output_dir: PathBuf,
+    /// How many pages are rendered at once.
+    /// Defaults to the number of CPUs
+    pub jobs: usize,
+    pub verbose: bool,
+    /// Extra variables, as `name | value` pairs
+    pub variables: HashMap<String, String>,
+}
+
+/// A pair of coordinates
+pub struct Point(
+    /// The horizontal coordinate
+    pub f64,
+    /// The vertical coordinate
+    pub f64,
+);
@@ -0,0 +1,16 @@
+/// The maximum length of a path
+pub const MAX_PATH: usize = 4096;
+
+/// Open for reading
+pub const FLAG_READ: u8 = 1;
+/// Open for writing
+pub const FLAG_WRITE: u8 = 2;
+/// Open for executing
+pub const FLAG_EXEC: u8 = 4;
+
+pub fn open(path: &str, flags: u8) -> bool {
+    const FLAG_DEFAULT: u8 = FLAG_READ;
+    !path.is_empty() && path.len() <= MAX_PATH && flags & FLAG_DEFAULT != 0
+}
+
+pub const FLAG_ALL: u8 = FLAG_READ | FLAG_WRITE | FLAG_EXEC;
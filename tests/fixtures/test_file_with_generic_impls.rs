@@ -0,0 +1,29 @@
+pub struct Wrapper<T> {
+    value: T,
+}
+
+impl<T> Wrapper<T> {
+    pub fn new(value: T) -> Self {
+        Self { value }
+    }
+}
+
+impl Wrapper<u8> {
+    pub fn double(&self) -> u8 {
+        self.value * 2
+    }
+}
+
+impl Wrapper<String> {
+    pub fn shout(&self) -> String {
+        self.value.to_uppercase()
+    }
+}
+
+mod other {
+    pub struct Wrapper;
+
+    impl Wrapper {
+        pub fn unrelated() {}
+    }
+}
@@ -0,0 +1,18 @@
+use std::fmt::Display;
+use std::io::{Read, Write};
+
+/// Count the bytes a reader yields
+pub fn count_bytes<R: Read>(mut reader: R) -> usize {
+    let mut buffer = Vec::new();
+    reader.read_to_end(&mut buffer).unwrap_or(0)
+}
+
+/// Write a greeting and a value to a writer
+pub fn greet<W: Write, T: Display>(writer: &mut W, value: &T) {
+    writeln!(writer, "Hello, {}!", value).ok();
+}
+
+/// Show every item of a list, which needs `T` to stay generic
+pub fn show_all<T: Display + Clone>(items: &[T]) -> Vec<String> {
+    items.iter().map(|item| item.to_string()).collect()
+}
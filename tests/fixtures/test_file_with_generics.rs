@@ -0,0 +1,21 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+struct Cache<K: Hash + Eq, V: Clone = String>
+where
+    V: Default,
+{
+    entries: HashMap<K, V>,
+}
+
+fn lookup<'a, K, V>(cache: &'a Cache<K, V>, key: &K) -> Option<&'a V>
+where
+    K: Hash + Eq,
+    V: Clone + Default,
+{
+    cache.entries.get(key)
+}
+
+struct Plain {
+    value: u32,
+}
@@ -0,0 +1,27 @@
+struct Request {
+    path: String,
+}
+
+struct RequestHeaders;
+
+enum Response {
+    Ok(String),
+    NotFound,
+}
+
+fn handle_index(request: &Request) -> Response {
+    Response::Ok(format!("index of {}", request.path))
+}
+
+fn route(request: &Request) -> Response {
+    match request.path.as_str() {
+        "/" => handle_index(request),
+        _ => handle_missing(request),
+    }
+}
+
+fn handle_missing(_request: &Request) -> Response {
+    Response::NotFound
+}
+
+fn unhandled_request() {}
@@ -0,0 +1,7 @@
+# use std::collections::HashMap;
+#
+fn main() {
+    let mut map = HashMap::new();
+    # map.reserve(10);
+    map.insert(1, 2);
+}
@@ -0,0 +1,44 @@
+use std::fmt;
+
+pub struct Buffer {
+    bytes: Vec<u8>,
+}
+
+impl Buffer {
+    /// The most bytes a buffer holds
+    pub const CAPACITY: usize = 4096;
+
+    /// An empty buffer
+    pub fn new() -> Self {
+        Self { bytes: Vec::new() }
+    }
+
+    #[inline]
+    pub fn extend<I>(&mut self, bytes: I) -> usize
+    where
+        I: IntoIterator<Item = u8>,
+    {
+        let before = self.bytes.len();
+        self.bytes.extend(bytes);
+        self.bytes.len() - before
+    }
+
+    // Identical to `new`'s body, which mustn't confuse the summary
+    pub fn empty() -> Self {
+        Self { bytes: Vec::new() }
+    }
+}
+
+impl Iterator for Buffer {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        self.bytes.pop()
+    }
+}
+
+impl fmt::Display for Buffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} bytes", self.bytes.len())
+    }
+}
@@ -0,0 +1,18 @@
+use std::collections::HashMap;
+
+/// A count of each word in a text
+pub struct WordCount {
+    counts: HashMap<String, usize>,
+}
+
+use std::fmt::Write;
+
+impl WordCount {
+    pub fn summary(&self) -> String {
+        let mut out = String::new();
+        for (word, count) in &self.counts {
+            writeln!(out, "{}: {}", word, count).unwrap();
+        }
+        out
+    }
+}
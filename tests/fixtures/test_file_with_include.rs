@@ -0,0 +1,5 @@
+include!("included/generated.rs");
+
+fn describe(point: &Point) -> String {
+    format!("({}, {})", point.x, point.y)
+}
@@ -0,0 +1,14 @@
+mod generated;
+
+/// Small examples of the API
+mod examples {
+    use super::add;
+
+    pub fn sum() -> i32 {
+        add(1, 2)
+    }
+}
+
+fn add(a: i32, b: i32) -> i32 {
+    a + b
+}
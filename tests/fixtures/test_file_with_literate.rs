@@ -0,0 +1,15 @@
+/// Counts the words in some text, keeping track of how often each one appears.
+///
+/// The result is sorted by count, most frequent first.
+fn word_counts(text: &str) -> Vec<(String, usize)> {
+    /// First, tally each word in a map, ignoring case.
+    let mut counts = std::collections::HashMap::new();
+    for word in text.split_whitespace() {
+        *counts.entry(word.to_lowercase()).or_insert(0) += 1;
+    }
+
+    /// Then sort the tallies, breaking ties alphabetically so the order is stable.
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    counts
+}
@@ -0,0 +1,35 @@
+// Test file with methods that invoke local macros
+
+macro_rules! square {
+    ($x:expr) => {
+        $x * $x
+    };
+}
+
+macro_rules! sum_of_squares {
+    () => {
+        0
+    };
+    ($x:expr $(, $rest:expr)*) => {
+        square!($x) + sum_of_squares!($($rest),*)
+    };
+}
+
+macro_rules! unused {
+    () => {};
+}
+
+pub struct Point {
+    pub x: i64,
+    pub y: i64,
+}
+
+impl Point {
+    pub fn norm_squared(&self) -> i64 {
+        sum_of_squares!(self.x, self.y)
+    }
+
+    pub fn is_origin(&self) -> bool {
+        self.x == 0 && self.y != 0
+    }
+}
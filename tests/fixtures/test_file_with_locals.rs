@@ -0,0 +1,19 @@
+use std::collections::HashMap;
+
+fn run(input: &str) -> usize {
+    let words: Vec<&str> = input.split_whitespace().collect();
+    let mut counts = HashMap::new();
+    let (mut longest, mut total): (usize, usize) = (0, 0);
+    for word in &words {
+        let len = word.len();
+        *counts.entry(len).or_insert(0) += 1;
+        longest = longest.max(len);
+        total += len;
+    }
+    let average = |count: usize| {
+        let count = count.max(1);
+        total / count
+    };
+    let _ = average(words.len());
+    longest
+}
@@ -0,0 +1,7 @@
+fn configure_connection(hostname: &str, port: u16, timeout_ms: u64, retries: u32, use_tls: bool) -> bool {
+    !hostname.is_empty() && port > 0 && timeout_ms > 0 && use_tls
+}
+
+fn short_function(a: u32) -> u32 {
+    a + 1
+}
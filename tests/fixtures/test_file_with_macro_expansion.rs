@@ -0,0 +1,28 @@
+macro_rules! newtype {
+    ($name:ident, $inner:ty) => {
+        struct $name($inner);
+
+        impl $name {
+            fn get(&self) -> &$inner {
+                &self.0
+            }
+        }
+    };
+}
+
+// ANCHOR: newtype_demo
+newtype!(UserId, u64);
+// ANCHOR_END: newtype_demo
+
+#[cfg(any())]
+mod expanded {
+    // ANCHOR: newtype_demo_expansion
+    struct UserId(u64);
+
+    impl UserId {
+        fn get(&self) -> &u64 {
+            &self.0
+        }
+    }
+    // ANCHOR_END: newtype_demo_expansion
+}
@@ -0,0 +1,19 @@
+cfg_if::cfg_if! {
+    if #[cfg(unix)] {
+        /// Platform-specific configuration
+        #[derive(Debug)]
+        pub struct PlatformConfig {
+            pub socket_path: String,
+        }
+
+        fn platform_name() -> &'static str {
+            "unix"
+        }
+    } else {
+        pub struct PlatformConfig;
+
+        fn platform_name() -> &'static str {
+            "other"
+        }
+    }
+}
@@ -0,0 +1,38 @@
+/// The opcodes of the wire protocol
+pub enum Opcode {
+    Ping,
+    Pong,
+    Data(Vec<u8>),
+    Close { code: u16 },
+    Reset,
+    Upgrade,
+}
+
+pub struct Connection {
+    open: bool,
+}
+
+pub fn handle(opcode: &Opcode, connection: &mut Connection) -> Option<Opcode> {
+    match opcode {
+        Opcode::Ping => Some(Opcode::Pong),
+        Opcode::Data(bytes) if bytes.is_empty() => None,
+        Opcode::Data(_) | Opcode::Pong => None,
+        Opcode::Close { .. } => {
+            connection.open = false;
+            None
+        }
+        _ => Some(Opcode::Reset),
+    }
+}
+
+impl Connection {
+    pub fn respond(&self, opcode: Opcode) -> &'static str {
+        use Opcode::*;
+        match opcode {
+            Ping => "pong",
+            Reset | Upgrade => "bye",
+            Close { .. } => "closed",
+            Pong | Data(_) => "ok",
+        }
+    }
+}
@@ -0,0 +1,24 @@
+mod network {
+    /// A packet sent over the network
+    pub struct Packet {
+        pub payload: Vec<u8>,
+    }
+
+    pub enum Protocol {
+        Tcp,
+        Udp,
+    }
+
+    pub mod tcp {
+        pub trait Transport {
+            fn send(&self, packet: &super::Packet);
+        }
+    }
+}
+
+mod storage {
+    /// A packet written to disk
+    pub struct Packet {
+        pub offset: u64,
+    }
+}
@@ -0,0 +1,12 @@
+pub mod net {
+    pub mod tcp {
+        /// A TCP connection
+        pub struct Connection {
+            pub port: u16,
+        }
+    }
+
+    pub fn connect(port: u16) -> tcp::Connection {
+        tcp::Connection { port }
+    }
+}
@@ -0,0 +1,13 @@
+struct Buffer {
+    data: Vec<u8>,
+}
+
+fn consume(buffer: Buffer) -> usize {
+    buffer.data.len()
+}
+
+fn use_after_move() {
+    let buffer = Buffer { data: vec![1, 2, 3] };
+    let size = consume(buffer);
+    println!("{} bytes, first is {}", size, buffer.data[0]);
+}
@@ -0,0 +1,5 @@
+/// The endpoint the client talks to
+pub const API_URL: &str = "https://internal.example.corp/api";
+
+/// The token used when none is configured
+pub const DEFAULT_TOKEN: &str = "sk_live_4f9a2c7e1b";
@@ -0,0 +1,17 @@
+use std::io;
+use std::net::{SocketAddr, TcpStream};
+
+pub struct Stream(TcpStream);
+
+pub fn connect(addr: SocketAddr) -> io::Result<Stream> {
+    TcpStream::connect(addr).map(Stream)
+}
+
+impl Stream {
+    pub fn send(
+        &mut self,
+        bytes: &[u8],
+    ) -> io::Result<usize> {
+        io::Write::write(&mut self.0, bytes)
+    }
+}
@@ -0,0 +1,25 @@
+// Test file indented with tabs, with non-ASCII text in its comments
+
+mod units {
+	/// Größe in Metern — always non-negative
+	pub struct Length {
+		// Maß: the length in meters
+		pub meters: f64,
+	}
+
+	impl Length {
+		pub fn doubled(&self) -> Length {
+			// ×2, keeping the unit
+			Length {
+				meters: self.meters * 2.0,
+			}
+		}
+	}
+}
+
+// ANCHOR: conversion
+	fn to_feet(meters: f64) -> f64 {
+		// 1 ft ≈ 0.3048 m
+		meters / 0.3048
+	}
+// ANCHOR_END: conversion
@@ -0,0 +1,12 @@
+/// Parse a comma separated list of numbers
+pub fn parse_list(input: &str, separator: char) -> Vec<u32> {
+    input
+        .split(separator)
+        .filter_map(|part| part.trim().parse().ok())
+        .collect()
+}
+
+/// Print a greeting
+pub fn greet(name: &str) {
+    println!("Hello, {}!", name);
+}
@@ -0,0 +1,22 @@
+/// A store of values by key
+pub trait Store<K>
+where
+    K: Ord,
+{
+    type Value;
+    const NAME: &'static str;
+    const CAPACITY: usize = 16;
+
+    /// Look a value up
+    fn get(&self, key: &K) -> Option<&Self::Value>;
+
+    fn insert(
+        &mut self,
+        key: K,
+        value: Self::Value,
+    ) -> Option<Self::Value>;
+
+    fn contains(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+}
@@ -0,0 +1,21 @@
+// Test file indented with two spaces
+
+pub fn two_space_body() {
+  let total = 1 + 2;
+  if total > 2 {
+    println!("{}", total);
+  }
+}
+
+pub struct Counter {
+  count: u32,
+}
+
+impl Counter {
+  pub fn bump(&mut self) {
+    self.count += 1;
+    if self.count > 9 {
+      self.count = 0;
+    }
+  }
+}
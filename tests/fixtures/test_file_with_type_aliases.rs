@@ -0,0 +1,13 @@
+use std::collections::HashMap;
+
+#[derive(Debug)]
+struct MyError;
+
+/// A result whose error is always `MyError`
+type Result<T> = std::result::Result<T, MyError>;
+
+type Lookup<'a, K, V = String> = HashMap<&'a K, V>;
+
+fn parse(input: &str) -> Result<u32> {
+    input.parse().map_err(|_| MyError)
+}
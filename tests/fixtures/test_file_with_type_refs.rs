@@ -0,0 +1,36 @@
+use std::collections::HashMap;
+
+/// A node in a tree, which refers to itself
+pub struct Node {
+    pub label: Label,
+    pub children: Vec<Node>,
+}
+
+pub enum Label {
+    Name(String),
+    Id(u32),
+}
+
+/// Not referred to by anything
+pub struct Unused {
+    pub values: HashMap<String, u32>,
+}
+
+pub struct Counter {
+    pub count: usize,
+}
+
+pub fn count_nodes(root: &Node) -> usize {
+    let mut counter = Counter { count: 0 };
+    counter.count += 1 + root.children.len();
+    counter.count
+}
+
+impl Node {
+    pub fn leaf(id: u32) -> Self {
+        Node {
+            label: Label::Id(id),
+            children: Vec::new(),
+        }
+    }
+}
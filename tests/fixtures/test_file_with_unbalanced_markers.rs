@@ -0,0 +1,7 @@
+fn print_each() {
+    for i in 0..3 {
+        // DISPLAY START
+        println!("{}", i);
+    }
+    // DISPLAY END
+}
@@ -0,0 +1,38 @@
+// Test file with a builder struct and an anchored example of using it
+
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub host: String,
+    pub port: u16,
+}
+
+#[derive(Default)]
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl ConfigBuilder {
+    pub fn host(mut self, host: &str) -> Self {
+        self.config.host = host.to_string();
+        self
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.config.port = port;
+        self
+    }
+
+    pub fn build(self) -> Config {
+        self.config
+    }
+}
+
+fn example() -> Config {
+    // ANCHOR: config-usage
+    let config = ConfigBuilder::default()
+        .host("localhost")
+        .port(8080)
+        .build();
+    // ANCHOR_END: config-usage
+    config
+}
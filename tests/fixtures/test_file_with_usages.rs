@@ -0,0 +1,43 @@
+use std::fmt;
+
+pub struct Account {
+    pub owner: String,
+    pub balance: i64,
+}
+
+impl Account {
+    pub fn open(owner: &str) -> Self {
+        Self {
+            owner: owner.to_string(),
+            balance: 0,
+        }
+    }
+
+    pub fn deposit(&mut self, amount: i64) {
+        self.balance += amount;
+    }
+}
+
+impl fmt::Display for Account {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.owner, self.balance)
+    }
+}
+
+pub trait Ledger {
+    fn record(&mut self, account: &Account);
+}
+
+pub fn total(accounts: &[Account]) -> i64 {
+    accounts.iter().map(|account| account.balance).sum()
+}
+
+pub fn demo() {
+    let mut account = Account::open("Ferris");
+    account.deposit(10);
+    println!("{}", account);
+}
+
+pub fn unrelated() -> i64 {
+    42
+}
@@ -0,0 +1,4 @@
+#!/bin/sh
+set -e
+# Build the book
+mdbook build
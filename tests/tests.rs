@@ -2,7 +2,10 @@ use insta::assert_snapshot;
 use mdbook::Config;
 use mdbook::book::{Book, BookItem, Chapter};
 use mdbook::preprocess::{Preprocessor, PreprocessorContext};
-use mdbook_include_rs::IncludeRsPreprocessor;
+use mdbook_include_rs::extract::{Directive, find_function, find_method, find_struct, render_item};
+use mdbook_include_rs::{
+    HiddenPlacement, IncludeRsPreprocessor, Render, RenderFallback, extract, item_loc,
+};
 use std::path::PathBuf;
 
 #[test]
@@ -27,7 +30,7 @@ fn test_empty() {
     let preprocessor = IncludeRsPreprocessor;
     let processed_book = preprocessor.run(&ctx, book).unwrap();
 
-    // Since there are no include-doc snippets, the book should remain unchanged
+    // Since there are no include-rs directives, the book should remain unchanged
     let mut chapter_found = false;
     for item in processed_book.iter() {
         if let BookItem::Chapter(chapter) = item {
@@ -40,6 +43,13 @@ fn test_empty() {
     assert!(chapter_found, "Chapter not found in processed book");
 }
 
+#[test]
+fn test_preprocessor_name() {
+    // The name is the `[preprocessor.include-rs]` table, and the binary mdBook runs for it
+    assert_eq!(IncludeRsPreprocessor.name(), "include-rs");
+    assert!(IncludeRsPreprocessor.supports_renderer("html"));
+}
+
 /// Create a test book with a single chapter containing the given content
 fn create_test_book(chapter_name: &str, content: &str, chapter_path: &str) -> Book {
     let mut book = Book::new();
@@ -58,9 +68,15 @@ fn create_test_book(chapter_name: &str, content: &str, chapter_path: &str) -> Bo
 
 /// Run the preprocessor on a book and return the processed content of the specified chapter
 fn run_and_extract_content(book: Book, chapter_name: &str) -> String {
-    // Create a preprocessor context
-    let ctx = create_test_context();
+    run_and_extract_content_with_context(book, chapter_name, create_test_context())
+}
 
+/// Run the preprocessor with the given context and return the processed content of the specified chapter
+fn run_and_extract_content_with_context(
+    book: Book,
+    chapter_name: &str,
+    ctx: PreprocessorContext,
+) -> String {
     // Run the preprocessor
     let preprocessor = IncludeRsPreprocessor;
     let processed_book = preprocessor.run(&ctx, book).unwrap();
@@ -282,24 +298,2574 @@ fn test_method_extraction() {
     );
 }
 
-// Create a mock PreprocessorContext for testing
-fn create_test_context() -> PreprocessorContext {
-    let mut config = Config::default();
-    config.set("book.title", "Test Book").unwrap();
+#[test]
+fn test_const_table() {
+    let content = "\
+Status codes:
 
-    let manifest_dir = env!("CARGO_MANIFEST_DIR");
-    let fixtures_dir = PathBuf::from(manifest_dir).join("tests").join("fixtures");
-    // Use a test-specific approach since PreprocessorContext has private fields
-    let ctx_json = format!(
-        r#"{{
-            "root": "{root}",
-            "config": {config},
-            "renderer": "html",
-            "mdbook_version": "0.4.47"
-        }}"#,
-        root = fixtures_dir.display(),
-        config = serde_json::to_string(&config).unwrap()
+#![const_table!(\"../test_file_with_consts.rs\", STATUS_CODES)]
+
+MIME types:
+
+#![const_table!(\"../test_file_with_consts.rs\", MIME_TYPES)]
+
+Retry delays:
+
+#![const_table!(\"../test_file_with_consts.rs\", RETRY_DELAYS)]
+";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!("const_table", processed_content);
+}
+
+#[test]
+fn test_struct_inside_macro() {
+    test_directive(
+        "struct_inside_macro",
+        "#![struct!(\"../test_file_with_macros.rs\", PlatformConfig)]\n#![function!(\"../test_file_with_macros.rs\", platform_name)]",
+        "Chapter 1",
+        "cfg_if preamble",
     );
+}
 
-    serde_json::from_str(&ctx_json).unwrap()
+#[test]
+fn test_max_line_width() {
+    let content = "\
+```rust
+#![function!(\"../test_file_with_long_lines.rs\", configure_connection)]
+```
+
+```rust
+#![function!(\"../test_file_with_long_lines.rs\", short_function)]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let ctx = create_test_context_with_config(|config| {
+        config
+            .set("preprocessor.include-rs.max-line-width", 80)
+            .unwrap();
+    });
+    let processed_content = run_and_extract_content_with_context(book, "Chapter 1", ctx);
+
+    // Hidden lines aren't displayed, so only visible lines need to fit
+    assert!(
+        processed_content
+            .lines()
+            .filter(|line| !line.starts_with("# "))
+            .all(|line| line.chars().count() <= 80),
+        "Over-long lines were not wrapped:\n{}",
+        processed_content
+    );
+    assert_snapshot!("max_line_width", processed_content);
+}
+
+#[test]
+fn test_loc() {
+    let content = "\
+`free_function` is only #![loc!(\"../test_file.rs\", free_function)] lines long.
+`TestStruct::new` takes #![loc!(\"../test_file.rs\", TestStruct::new)] lines and `TestStruct` itself #![loc!(\"../test_file.rs\", struct TestStruct)].";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_eq!(
+        processed_content,
+        "\
+`free_function` is only 3 lines long.
+`TestStruct::new` takes 6 lines and `TestStruct` itself 4."
+    );
+}
+
+#[test]
+fn test_return_type() {
+    let content = "\
+`connect` returns `#![return_type!(\"../test_file_with_signatures.rs\", connect)]` and \
+`Stream::send` returns `#![return_type!(\"../test_file_with_signatures.rs\", Stream::send)]`.
+`free_function` returns `#![return_type!(\"../test_file.rs\", free_function)]`.";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_eq!(
+        processed_content,
+        "\
+`connect` returns `io::Result<Stream>` and `Stream::send` returns `io::Result<usize>`.
+`free_function` returns `()`."
+    );
+}
+
+#[test]
+fn test_item_loc() {
+    let test_file = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/test_file.rs");
+    assert_eq!(
+        item_loc(&test_file, "function", "free_function").unwrap(),
+        3
+    );
+    assert_eq!(item_loc(&test_file, "impl", "TestStruct").unwrap(), 12);
+    assert_eq!(
+        item_loc(&test_file, "trait_impl", "TestTrait for TestStruct").unwrap(),
+        5
+    );
+    assert!(item_loc(&test_file, "function", "missing_function").is_err());
+}
+
+#[test]
+fn test_extract() {
+    let fixtures = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    // The rest of the file is hidden, with the body visible inside a hidden `fn main`
+    let snippet = extract(&fixtures, "function_body!(\"test_file.rs\", free_function)").unwrap();
+    assert!(snippet.starts_with("# use std::fmt;\n"), "{}", snippet);
+    assert!(
+        snippet.ends_with("# fn main() {\nprintln!(\"Hello, world! \\\\{\");\n# }"),
+        "{}",
+        snippet
+    );
+    // The `#![...]` around a directive is optional, and inline directives work too
+    assert_eq!(
+        extract(&fixtures, "#![loc!(\"test_file.rs\", free_function)]").unwrap(),
+        "3"
+    );
+    let error = extract(&fixtures, "struct!(\"test_file.rs\", Missing)").unwrap_err();
+    assert!(
+        error.to_string().contains("'Missing' not found"),
+        "{}",
+        error
+    );
+
+    let directive =
+        Directive::parse("struct!(\"test_file.rs\", TestStruct, anchor = \"x\")").unwrap();
+    assert_eq!(directive.file_path(), "test_file.rs");
+    assert_eq!(directive.item(), Some("TestStruct"));
+    assert_eq!(directive.option("anchor"), Some("x"));
+
+    let source = std::fs::read_to_string(fixtures.join("test_file.rs")).unwrap();
+    let file = syn::parse_file(&source).unwrap();
+    assert_eq!(find_struct(&file, "TestStruct").unwrap().fields.len(), 2);
+    assert!(find_method(&file, "TestStruct::print").is_some());
+    assert!(find_function(&file, "missing_function").is_none());
+}
+
+#[test]
+fn test_functions_matching_pattern() {
+    test_directive(
+        "functions_matching_pattern",
+        "#![functions!(\"../test_file_with_handlers.rs\", pattern = \"handle_.*\")]",
+        "Chapter 1",
+        "handlers preamble",
+    );
+}
+
+#[test]
+fn test_structs_matching_pattern() {
+    test_directive(
+        "structs_matching_pattern",
+        "#![structs!(\"../test_file_with_handlers.rs\", pattern = \"Request.*\")]",
+        "Chapter 1",
+        "structs preamble",
+    );
+}
+
+#[test]
+fn test_consts_with_prefix() {
+    test_directive(
+        "consts_with_prefix",
+        "#![consts!(\"../test_file_with_flags.rs\", prefix = \"FLAG_\")]",
+        "Chapter 1",
+        "flags preamble",
+    );
+}
+
+#[test]
+fn test_consts_matching_pattern() {
+    let content = "#![consts!(\"../test_file_with_flags.rs\", pattern = \"FLAG_(READ|WRITE)\")]\n";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert!(
+        processed_content.contains(
+            "/// Open for reading\npub const FLAG_READ: u8 = 1;\n/// Open for writing\npub const FLAG_WRITE: u8 = 2;\n"
+        ),
+        "Unexpected output: {}",
+        processed_content
+    );
+    assert!(processed_content.contains("# pub const FLAG_EXEC: u8 = 4;"));
+}
+
+#[test]
+fn test_pattern_without_matches() {
+    let content = "#![enums!(\"../test_file_with_handlers.rs\", pattern = \"Missing.*\")]\n";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert!(
+        processed_content.contains("No items matching 'Missing.*' found"),
+        "Unexpected output: {}",
+        processed_content
+    );
+}
+
+#[test]
+fn test_item_diff() {
+    let content = "\
+Changed:
+
+#![item_diff!(\"../test_file_old.rs\", \"../test_file_new.rs\", struct Config)]
+
+Added:
+
+#![item_diff!(\"../test_file_old.rs\", \"../test_file_new.rs\", enum Mode)]
+
+Removed:
+
+#![item_diff!(\"../test_file_old.rs\", \"../test_file_new.rs\", struct Legacy)]
+";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!("item_diff", processed_content);
+}
+
+#[test]
+fn test_embedded_directive() {
+    let content = "\
+```rust
+// The type we are working with:
+/* include: struct!(\"../test_file.rs\", TestStruct) */
+
+impl TestStruct {
+    // The constructor looks like this:
+    /* include: function!(\"../test_file.rs\", TestStruct::new) */
+}
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!("embedded_directive", processed_content);
+}
+
+#[test]
+fn test_max_parse_size() {
+    // Write a source file of exactly `size` bytes, padded with a comment
+    let write_file = |name: &str, size: usize| {
+        let path = std::env::temp_dir().join(name);
+        let mut content = String::from("struct Large;\n//");
+        content.push_str(&"x".repeat(size - content.len()));
+        std::fs::write(&path, content).unwrap();
+        path
+    };
+    let at_limit = write_file("mdbook-include-rs-at-limit.rs", 1024);
+    let over_limit = write_file("mdbook-include-rs-over-limit.rs", 1025);
+
+    let content = format!(
+        "#![struct!(\"{}\", Large)]\n\n#![struct!(\"{}\", Large)]\n",
+        at_limit.display(),
+        over_limit.display()
+    );
+    let book = create_test_book("Chapter 1", &content, "chapter_1.md");
+    let ctx = create_test_context_with_config(|config| {
+        config
+            .set("preprocessor.include-rs.max-parse-size", 1024)
+            .unwrap();
+    });
+    let processed_content = run_and_extract_content_with_context(book, "Chapter 1", ctx);
+
+    let (first, second) = processed_content.split_once("\n\n").unwrap();
+    assert_eq!(first, "struct Large;");
+    assert!(
+        second.contains("File too large for AST extraction")
+            && second.contains("1025 bytes, above the max-parse-size of 1024 bytes"),
+        "Unexpected output: {}",
+        second
+    );
+}
+
+#[test]
+fn test_generics() {
+    let content = "\
+```rust
+#![generics!(\"../test_file_with_generics.rs\", \"struct Cache\")]
+```
+
+```rust
+#![generics!(\"../test_file_with_generics.rs\", \"fn lookup\")]
+```
+
+```rust
+#![generics!(\"../test_file_with_generics.rs\", \"struct Plain\")]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!("generics", processed_content);
+}
+
+#[test]
+fn test_hidden_placement_before() {
+    test_hidden_placement("before");
+}
+
+#[test]
+fn test_hidden_placement_after() {
+    test_hidden_placement("after");
+}
+
+/// Snapshot a directive with both hidden dependencies and visible code under a hidden-placement
+fn test_hidden_placement(placement: &str) {
+    let content = "\
+```rust
+#![function_body!(\"../test_file.rs\", free_function, [struct TestStruct])]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let ctx = create_test_context_with_config(|config| {
+        config
+            .set("preprocessor.include-rs.hidden-placement", placement)
+            .unwrap();
+    });
+    let processed_content = run_and_extract_content_with_context(book, "Chapter 1", ctx);
+    assert_snapshot!(format!("hidden_placement_{}", placement), processed_content);
+}
+
+#[test]
+fn test_trait_method_impl() {
+    test_directive(
+        "trait_method_impl",
+        "#![trait_method_impl!(\"../test_file.rs\", TestTrait for TestStruct::test_method)]",
+        "Chapter 1",
+        "trait method impl preamble",
+    );
+}
+
+#[test]
+fn test_chapter_with_only_a_directive() {
+    let content = "#![function!(\"../test_file.rs\", TestStruct::print)]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_eq!(
+        processed_content,
+        "fn print(&self) {\n    println!(\"Name: {}, Value: {}\", self.name, self.value);\n}"
+    );
+}
+
+#[test]
+fn test_directives_at_chapter_boundaries() {
+    let content = "\
+#![function!(\"../test_file.rs\", TestStruct::print)]
+Between the directives
+#![function!(\"../test_file.rs\", TestStruct::print)]  ";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    let method =
+        "fn print(&self) {\n    println!(\"Name: {}, Value: {}\", self.name, self.value);\n}";
+    assert_eq!(
+        processed_content,
+        format!("{}\nBetween the directives\n{}", method, method)
+    );
+}
+
+#[test]
+fn test_error_in_chapter_with_only_a_directive() {
+    let content = "#![function!(\"../test_file.rs\", missing_function)]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert!(
+        processed_content.contains("chapter_1.md:1:1: "),
+        "Unexpected output: {}",
+        processed_content
+    );
+    assert!(!processed_content.contains('\n'));
+}
+
+#[test]
+fn test_wrap_fences_with_highlight() {
+    let content = "\
+#![function_body!(\"../test_file.rs\", free_function, [struct TestStruct], highlight = \"1,2-3\")]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let ctx = create_test_context_with_config(|config| {
+        config
+            .set("preprocessor.include-rs.wrap-fences", true)
+            .unwrap();
+    });
+    let processed_content = run_and_extract_content_with_context(book, "Chapter 1", ctx);
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_highlight_out_of_range() {
+    let content = "#![function!(\"../test_file.rs\", free_function, highlight = \"40\")]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let ctx = create_test_context_with_config(|config| {
+        config
+            .set("preprocessor.include-rs.wrap-fences", true)
+            .unwrap();
+    });
+    let processed_content = run_and_extract_content_with_context(book, "Chapter 1", ctx);
+    assert!(
+        processed_content.contains("Highlighted line 40 is out of range"),
+        "Unexpected output: {}",
+        processed_content
+    );
+}
+
+#[test]
+fn test_assoc_types() {
+    test_directive(
+        "assoc_types",
+        "#![assoc_types!(\"../test_file_with_assoc_types.rs\", \"Iterator for Counter\")]",
+        "Chapter 1",
+        "assoc types preamble",
+    );
+}
+
+#[test]
+fn test_assoc_types_missing() {
+    let content = "#![assoc_types!(\"../test_file_with_assoc_types.rs\", \"Default for Counter\")]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert!(
+        processed_content.contains("'Default for Counter' has no associated types"),
+        "Unexpected output: {}",
+        processed_content
+    );
+}
+
+#[test]
+fn test_fence_by_kind() {
+    let content = "\
+#![function_body!(\"../test_file.rs\", free_function)]
+
+#![struct!(\"../test_file.rs\", TestStruct)]
+
+#![enum!(\"../test_file.rs\", TestEnum)]
+
+#![const_table!(\"../test_file_with_consts.rs\", RETRY_DELAYS)]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let ctx = create_test_context_with_config(|config| {
+        config
+            .set("preprocessor.include-rs.wrap-fences", true)
+            .unwrap();
+        config
+            .set(
+                "preprocessor.include-rs.fence-by-kind.const_table",
+                "rust,ignore",
+            )
+            .unwrap();
+        config
+            .set(
+                "preprocessor.include-rs.fence-by-kind.function_body",
+                "rust,no_run",
+            )
+            .unwrap();
+        config
+            .set(
+                "preprocessor.include-rs.fence-by-kind.struct",
+                "rust,ignore",
+            )
+            .unwrap();
+    });
+    let processed_content = run_and_extract_content_with_context(book, "Chapter 1", ctx);
+    let fences = processed_content
+        .lines()
+        .filter(|line| line.starts_with("```") && *line != "```")
+        .collect::<Vec<_>>();
+    assert_eq!(
+        fences,
+        [
+            "```rust,no_run",
+            "```rust,ignore",
+            "```rust",
+            "```rust,ignore"
+        ]
+    );
+}
+
+#[test]
+fn test_locals() {
+    let content = "\
+```rust
+#![locals!(\"../test_file_with_locals.rs\", run)]
+```
+
+```rust
+#![locals!(\"../test_file_with_locals.rs\", run, nested = true)]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_tabs() {
+    let content = "\
+Before the tabs
+#![tabs!([
+    (\"Before\", struct!(\"../test_file_old.rs\", Config)),
+    (\"After\", struct!(\"../test_file_new.rs\", Config)),
+])]
+After the tabs";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_assert_output() {
+    let content = "\
+#![assert_output!(\"../examples/greet.rs\", expected = \"Hello, Ferris!\\nHello, Corro!\")]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let ctx = create_test_context_with_config(|config| {
+        config
+            .set("preprocessor.include-rs.allow-run", true)
+            .unwrap();
+    });
+    let processed_content = run_and_extract_content_with_context(book, "Chapter 1", ctx);
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_assert_output_mismatch() {
+    let content = "\
+#![assert_output!(\"../examples/greet.rs\", expected = \"Hello, Ferris!\\nHello, world!\")]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let ctx = create_test_context_with_config(|config| {
+        config
+            .set("preprocessor.include-rs.allow-run", true)
+            .unwrap();
+    });
+    let error = IncludeRsPreprocessor
+        .run(&ctx, book)
+        .unwrap_err()
+        .to_string();
+    assert!(
+        error.contains("doesn't match the expected output")
+            && error.contains("-Hello, world!\n+Hello, Corro!"),
+        "Unexpected error: {}",
+        error
+    );
+}
+
+#[test]
+fn test_assert_output_requires_allow_run() {
+    let content = "#![assert_output!(\"../examples/greet.rs\", expected = \"Hello, Ferris!\")]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert!(
+        processed_content.contains("must be enabled with allow-run = true"),
+        "Unexpected output: {}",
+        processed_content
+    );
+}
+
+#[test]
+fn test_function_body_wrapper_return() {
+    let content = "\
+```rust
+#![function_body!(\"../test_file_with_errors.rs\", report, wrapper_return = \"Result<(), ParseIntError>\")]
+```
+
+```rust
+#![function_body!(\"../test_file_with_errors.rs\", report, wrapper_return = \"()\")]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_impls_of() {
+    let content = "\
+`TestStruct` implements:
+
+#![impls_of!(\"../test_file.rs\", TestStruct)]
+
+#![impls_of!(\"../test_file.rs\", TestEnum)]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_cache_file() {
+    let dir = std::env::temp_dir().join(format!("include-rs-cache-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("cached.rs");
+    let cache_file = dir.join("cache.json");
+    std::fs::write(&source, "struct Cached {\n    value: i32,\n}\n").unwrap();
+    let content = format!("#![struct!(\"{}\", Cached)]", source.display());
+    let run = || {
+        let book = create_test_book("Chapter 1", &content, "chapter_1.md");
+        let ctx = create_test_context_with_config(|config| {
+            config
+                .set(
+                    "preprocessor.include-rs.cache-file",
+                    cache_file.display().to_string(),
+                )
+                .unwrap();
+        });
+        run_and_extract_content_with_context(book, "Chapter 1", ctx)
+    };
+
+    assert_eq!(run(), "struct Cached {\n    value: i32,\n}");
+    let cached = std::fs::read_to_string(&cache_file).unwrap();
+
+    // While the file is unchanged, the entry is served and the cache file left as it is
+    assert_eq!(run(), "struct Cached {\n    value: i32,\n}");
+    assert_eq!(std::fs::read_to_string(&cache_file).unwrap(), cached);
+
+    // Once the file changes, the stale entry is replaced
+    std::fs::write(&source, "struct Cached {\n    value: String,\n}\n").unwrap();
+    assert_eq!(run(), "struct Cached {\n    value: String,\n}");
+    assert!(
+        std::fs::read_to_string(&cache_file)
+            .unwrap()
+            .contains("value: String")
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_cache_file_with_included_file() {
+    let dir = std::env::temp_dir().join(format!(
+        "include-rs-cache-include-test-{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("main.rs");
+    let included = dir.join("generated.rs");
+    let cache_file = dir.join("cache.json");
+    std::fs::write(&source, "include!(\"generated.rs\");\n").unwrap();
+    std::fs::write(&included, "struct Generated {\n    value: i32,\n}\n").unwrap();
+    let content = format!("#![struct!(\"{}\", Generated)]", source.display());
+    let run = || {
+        let book = create_test_book("Chapter 1", &content, "chapter_1.md");
+        let ctx = create_test_context_with_config(|config| {
+            config
+                .set(
+                    "preprocessor.include-rs.cache-file",
+                    cache_file.display().to_string(),
+                )
+                .unwrap();
+        });
+        run_and_extract_content_with_context(book, "Chapter 1", ctx)
+    };
+
+    assert_eq!(run(), "struct Generated {\n    value: i32,\n}");
+
+    // The item comes from the included file, so changing it replaces the cached entry
+    std::fs::write(&included, "struct Generated {\n    value: u64,\n}\n").unwrap();
+    assert_eq!(run(), "struct Generated {\n    value: u64,\n}");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_config_from_context() {
+    let ctx = create_test_context_with_config(|config| {
+        for (key, value) in [
+            ("base-dir", toml::Value::from("src")),
+            ("render", toml::Value::from("pretty")),
+            ("max-line-width", toml::Value::from(80)),
+            ("max-parse-size", toml::Value::from(4096)),
+            ("hidden-placement", toml::Value::from("after")),
+            ("wrap-fences", toml::Value::from(true)),
+            ("fence-by-kind.trait", toml::Value::from("rust,ignore")),
+            ("allow-run", toml::Value::from(true)),
+            ("cache-file", toml::Value::from("target/cache.json")),
+            ("verify-parse", toml::Value::from(true)),
+            ("crate-root", toml::Value::from("..")),
+            ("require-tracked", toml::Value::from(true)),
+            ("trim-body-blanks", toml::Value::from(false)),
+        ] {
+            config
+                .set(format!("preprocessor.include-rs.{}", key), value)
+                .unwrap();
+        }
+    });
+
+    let config = mdbook_include_rs::Config::from_env_and_toml(&ctx).unwrap();
+    assert_eq!(config.base_dir.as_deref(), Some("src"));
+    assert_eq!(config.render, Render::Pretty);
+    assert_eq!(config.max_line_width, Some(80));
+    assert_eq!(config.max_parse_size, 4096);
+    assert_eq!(config.hidden_placement, HiddenPlacement::After);
+    assert!(config.wrap_fences);
+    assert_eq!(
+        config.fence_by_kind.get("trait").map(String::as_str),
+        Some("rust,ignore")
+    );
+    assert!(config.allow_run);
+    assert_eq!(config.cache_file.as_deref(), Some("target/cache.json"));
+    assert!(config.verify_parse);
+    assert_eq!(config.crate_root, Some(ctx.root.join("..")));
+    assert!(config.require_tracked);
+    assert!(!config.trim_body_blanks);
+}
+
+#[test]
+fn test_config_from_env() {
+    use std::io::Write;
+    use std::process::{Command, Output, Stdio};
+
+    // Environment variables are shared by every test in the process, so they're only set
+    // for a child process running the preprocessor
+    let run = |name: &str, value: &str| -> Output {
+        let book = create_test_book(
+            "Chapter 1",
+            "#![struct!(\"../test_file.rs\", TestStruct)]",
+            "chapter_1.md",
+        );
+        let ctx = create_test_context_with_config(|config| {
+            config
+                .set("preprocessor.include-rs.wrap-fences", false)
+                .unwrap();
+        });
+        let input = serde_json::to_vec(&(ctx, book)).unwrap();
+        let mut child = Command::new(env!("CARGO_BIN_EXE_mdbook-include-rs"))
+            .env(name, value)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(&input).unwrap();
+        child.wait_with_output().unwrap()
+    };
+
+    // The variable overrides the book.toml value, parsed as a TOML value
+    let output = run("MDBOOK_INCLUDE_RS_WRAP_FENCES", "true");
+    assert!(output.status.success());
+    let book: Book = serde_json::from_slice(&output.stdout).unwrap();
+    let Some(BookItem::Chapter(chapter)) = book.iter().next() else {
+        panic!("Expected a chapter");
+    };
+    assert!(
+        chapter.content.starts_with("```rust\n"),
+        "Unexpected content: {}",
+        chapter.content
+    );
+
+    // A malformed value is reported rather than ignored
+    let output = run("MDBOOK_INCLUDE_RS_MAX_LINE_WIDTH", "wide");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("Invalid max-line-width"),
+        "Unexpected stderr: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_with_module() {
+    let content = "\
+```rust
+#![struct!(\"../test_file_with_modules.rs\", Connection, with_module = true)]
+```
+
+```rust
+#![function!(\"../test_file_with_modules.rs\", connect, with_module = true)]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_directive_render_option() {
+    let content = "\
+```rust
+#![function!(\"../test_file_with_long_lines.rs\", configure_connection, render = \"source\")]
+```
+
+```rust
+#![function!(\"../test_file_with_long_lines.rs\", configure_connection, render = \"ast\")]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let ctx = create_test_context_with_config(|config| {
+        config
+            .set("preprocessor.include-rs.max-line-width", 80)
+            .unwrap();
+    });
+    let processed_content = run_and_extract_content_with_context(book, "Chapter 1", ctx);
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_invalid_directive_render_option() {
+    let content = "#![struct!(\"../test_file.rs\", TestStruct, render = \"fancy\")]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert!(
+        processed_content.contains("Invalid render option 'fancy'"),
+        "Unexpected output: {}",
+        processed_content
+    );
+}
+
+#[test]
+fn test_render_source_with_ast_option() {
+    // Stripping attributes needs the syntax tree, which `render = "source"` rules out
+    let content = "\
+#![struct!(\"../test_file_with_attributes.rs\", User, strip_attrs, render = \"source\")]
+
+#![function!(\"../test_file.rs\", free_function, dyn_ify = true, render = \"source\")]
+
+#![struct!(\"../test_file_with_attributes.rs\", User, strip_attrs, render = \"ast\")]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    let errors = processed_content
+        .lines()
+        .filter(|line| line.contains("can't be combined with render = \"source\""))
+        .collect::<Vec<_>>();
+    assert_eq!(errors.len(), 2, "Unexpected output: {}", processed_content);
+    assert!(errors[0].ends_with(
+        ": strip_attrs re-renders the item from its syntax tree, so it can't be combined with render = \"source\""
+    ));
+    assert!(errors[1].contains(": dyn_ify re-renders"));
+}
+
+#[test]
+fn test_literate() {
+    let content = "#![literate!(\"../test_file_with_literate.rs\", word_counts)]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_verify_parse() {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    let content = "\
+```rust
+#![function_body!(\"../test_file_with_display_comments.rs\", function_with_display_markers)]
+```
+
+```rust
+#![function_body!(\"../test_file_with_unbalanced_markers.rs\", print_each)]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let ctx = create_test_context_with_config(|config| {
+        config
+            .set("preprocessor.include-rs.verify-parse", true)
+            .unwrap();
+    });
+    let processed_content =
+        run_and_extract_content_with_context(book.clone(), "Chapter 1", ctx.clone());
+
+    // Display markers that cut a loop in half leave an unmatched brace in the visible code,
+    // which is only warned about, keeping the snippet
+    assert!(
+        !processed_content.contains("doesn't parse as Rust")
+            && processed_content.contains("println!(\"{}\", i);\n}"),
+        "Unexpected output: {}",
+        processed_content
+    );
+
+    // The warning names the chapter, line and directive
+    let input = serde_json::to_vec(&(ctx, book.clone())).unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_mdbook-include-rs"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(&input).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let warnings = stderr
+        .lines()
+        .filter(|line| line.starts_with("Warning: "))
+        .collect::<Vec<_>>();
+    assert_eq!(warnings.len(), 1, "Unexpected stderr: {}", stderr);
+    assert!(
+        warnings[0].starts_with(
+            "Warning: ./tests/fixtures/src/chapter_1.md:6:1: Emitted code of function_body! doesn't parse as Rust: "
+        ),
+        "Unexpected stderr: {}",
+        stderr
+    );
+
+    // With strict set, it fails the build
+    let ctx = create_test_context_with_config(|config| {
+        config
+            .set("preprocessor.include-rs.verify-parse", true)
+            .unwrap();
+        config.set("preprocessor.include-rs.strict", true).unwrap();
+    });
+    let error = IncludeRsPreprocessor.run(&ctx, book).unwrap_err();
+    assert!(
+        error.to_string().contains("doesn't parse as Rust"),
+        "Unexpected error: {}",
+        error
+    );
+}
+
+#[test]
+fn test_module_path() {
+    let content = "\
+```rust
+#![struct!(mod = \"crate::net::config\", Endpoint)]
+```
+
+```rust
+#![function!(mod = \"crate::net\", connect)]
+```
+
+#![struct!(mod = \"crate::net::missing\", Endpoint)]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let ctx = create_test_context_with_config(|config| {
+        config
+            .set("preprocessor.include-rs.crate-root", "module_crate")
+            .unwrap();
+    });
+    let processed_content = run_and_extract_content_with_context(book, "Chapter 1", ctx);
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_fingerprint() {
+    let dir = std::env::temp_dir().join(format!("include-rs-fp-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("fingerprinted.rs");
+    let run = |directive: &str| {
+        let content = format!(
+            "#![{}]",
+            directive.replace("$FILE", &source.display().to_string())
+        );
+        let book = create_test_book("Chapter 1", &content, "chapter_1.md");
+        run_and_extract_content(book, "Chapter 1")
+    };
+    let fingerprint_of = |output: &str| {
+        let last_line = output.lines().last().unwrap_or_default();
+        last_line
+            .strip_prefix("// fp: ")
+            .unwrap_or_else(|| panic!("No fingerprint in: {}", output))
+            .to_string()
+    };
+
+    std::fs::write(&source, "struct Point {\n    x: i32,\n}\n").unwrap();
+    let first = run("struct!(\"$FILE\", Point, fingerprint = true)");
+    let second = run("struct!(\"$FILE\", Point, fingerprint = true)");
+    assert_eq!(first, second);
+    let recorded = fingerprint_of(&first);
+    assert_eq!(recorded.len(), 8);
+
+    // A recorded fingerprint is accepted while the code is unchanged
+    let validated = run(&format!(
+        "struct!(\"$FILE\", Point, fingerprint = \"{}\")",
+        recorded
+    ));
+    assert_eq!(validated, first);
+
+    std::fs::write(&source, "struct Point {\n    x: i64,\n}\n").unwrap();
+    let changed = run("struct!(\"$FILE\", Point, fingerprint = true)");
+    assert_ne!(fingerprint_of(&changed), recorded);
+
+    // ...and flagged once the code changes
+    let validated = run(&format!(
+        "struct!(\"$FILE\", Point, fingerprint = \"{}\")",
+        recorded
+    ));
+    assert!(
+        validated.contains(&format!("Fingerprint changed from {}", recorded)),
+        "Unexpected output: {}",
+        validated
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_trait_method_body() {
+    test_directive(
+        "trait_method_body",
+        "#![trait_method_body!(\"../test_file.rs\", \"TestTrait::default_method\")]",
+        "Chapter 1",
+        "trait method body preamble",
+    );
+}
+
+#[test]
+fn test_error_annotations() {
+    let book = create_test_book(
+        "Chapter 1",
+        "#![function!(\"../test_file_with_moves.rs\", use_after_move, errors = \"3: value moved here; 4: E0382 borrow of moved value: `buffer`\")]",
+        "chapter_1.md",
+    );
+    let output = run_and_extract_content(book, "Chapter 1");
+    let visible = output
+        .lines()
+        .filter(|line| !line.starts_with("# ") && *line != "#")
+        .collect::<Vec<_>>();
+    assert_eq!(
+        visible[2],
+        "    let size = consume(buffer);  // error: value moved here"
+    );
+    assert_eq!(
+        visible[3],
+        "    println!(\"{} bytes, first is {}\", size, buffer.data[0]);  // error[E0382]: borrow of moved value: `buffer`"
+    );
+    assert_eq!(output.matches("// error").count(), 2);
+
+    let book = create_test_book(
+        "Chapter 1",
+        "#![function!(\"../test_file_with_moves.rs\", use_after_move, errors = \"9: E0382 moved\")]",
+        "chapter_1.md",
+    );
+    let output = run_and_extract_content(book, "Chapter 1");
+    assert!(
+        output.contains("Annotated line 9 is out of range, the snippet has 5 visible lines"),
+        "{}",
+        output
+    );
+}
+
+#[test]
+fn test_async_function_body() {
+    let content = "\
+```rust
+#![function_body!(\"../test_file_with_async.rs\", greet_all)]
+```
+
+```rust
+#![function_body!(\"../test_file_with_async.rs\", greet_checked, wrapper_return = \"Result<(), String>\", async_runtime = \"futures\")]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_readme_example() {
+    let content = "\
+Sending a greeting:
+
+#![readme_example!(\"../test_readme.md\", n = 1)]
+
+#![readme_example!(\"../test_readme.md\", n = 2)]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_source_file_line_range() {
+    let content = "\
+```rust
+#![source_file!(\"../test_file.rs\", 6..10)]
+```
+
+```rust
+#![source_file!(\"../test_file.rs\", 2..=4)]
+```
+
+```rust
+#![source_file!(\"../test_file.rs\", 37..)]
+```
+
+```rust
+#![source_file!(\"../test_file.rs\", 38..=40)]
+```
+
+```rust
+#![source_file!(\"../test_file.rs\", ..2)]
+```
+
+```rust
+#![source_file!(\"../test_file.rs\", 40..50)]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_const_and_static() {
+    let content = "\
+```rust
+#![const!(\"../test_file_with_consts.rs\", RETRY_DELAYS)]
+```
+
+```rust
+#![static!(\"../test_file_with_consts.rs\", REQUEST_COUNT)]
+```
+
+```rust
+#![const!(\"../test_file_with_consts.rs\", REQUEST_COUNT)]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_type_alias() {
+    let content = "\
+```rust
+#![type!(\"../test_file_with_type_aliases.rs\", Result)]
+```
+
+```rust
+#![type!(\"../test_file_with_type_aliases.rs\", Lookup)]
+```
+
+```rust
+#![type!(\"../test_file_with_type_aliases.rs\", MyError)]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_item_from_included_file() {
+    let content = "\
+```rust
+#![struct!(\"../test_file_with_include.rs\", Point)]
+```
+
+```rust
+#![enum!(\"../test_file_with_include.rs\", Axis)]
+```
+
+```rust
+#![function!(\"../test_file_with_include.rs\", describe)]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_macro_rules() {
+    let content = "\
+```rust
+#![macro!(\"../test_file_with_macro_expansion.rs\", newtype)]
+```
+
+```rust
+#![macro!(\"../test_file_with_macro_expansion.rs\", UserId)]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_mod() {
+    let content = "\
+```rust
+#![mod!(\"../test_file_with_inline_module.rs\", examples)]
+```
+
+```rust
+#![mod!(\"../test_file_with_inline_module.rs\", generated)]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_module_paths() {
+    let content = "\
+```rust
+#![struct!(\"../test_file_with_module_paths.rs\", network::Packet)]
+```
+
+```rust
+#![struct!(\"../test_file_with_module_paths.rs\", crate::storage::Packet)]
+```
+
+```rust
+#![enum!(\"../test_file_with_module_paths.rs\", network::Protocol)]
+```
+
+```rust
+#![trait!(\"../test_file_with_module_paths.rs\", network::tcp::Transport)]
+```
+
+```rust
+#![struct!(\"../test_file_with_module_paths.rs\", tcp::Packet)]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_dossier() {
+    test_directive(
+        "dossier",
+        "#![dossier!(\"../test_file.rs\", TestStruct)]",
+        "Chapter 1",
+        "dossier preamble",
+    );
+}
+
+#[test]
+fn test_items() {
+    let content = "\
+```rust
+#![items!(\"../test_file.rs\", [enum TestEnum, struct TestStruct, impl TestStruct])]
+```
+
+```rust
+#![items!(\"../test_file.rs\", [struct TestStruct, struct Missing])]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_trim_body_blanks() {
+    let content = "#![function_body!(\"../test_file_with_blank_lines.rs\", padded_body)]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    assert_eq!(
+        run_and_extract_content(book, "Chapter 1"),
+        "# fn main() {\nlet greeting = \"hello\";\n\nprintln!(\"{}\", greeting);\n# }"
+    );
+
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let ctx = create_test_context_with_config(|config| {
+        config
+            .set("preprocessor.include-rs.trim-body-blanks", false)
+            .unwrap();
+    });
+    assert_eq!(
+        run_and_extract_content_with_context(book, "Chapter 1", ctx),
+        "# fn main() {\n\nlet greeting = \"hello\";\n\nprintln!(\"{}\", greeting);\n\n# }"
+    );
+}
+
+#[test]
+fn test_with_macros() {
+    let content = "\
+```rust
+#![function!(\"../test_file_with_local_macros.rs\", Point::norm_squared, with_macros = true)]
+```
+
+```rust
+#![function_body!(\"../test_file_with_local_macros.rs\", Point::is_origin, with_macros = true)]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_dedent_tabs_and_non_ascii() {
+    let content = "\
+```rust
+#![struct!(\"../test_file_with_tabs.rs\", Length)]
+```
+
+```rust
+#![function!(\"../test_file_with_tabs.rs\", Length::doubled)]
+```
+
+```rust
+#![source_file!(\"../test_file_with_tabs.rs\", anchor = \"conversion\")]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_body_indentation() {
+    let content = "#![function_body!(\"../test_file_with_two_space_indent.rs\", two_space_body)]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    // The rest of the file comes first, as hidden lines
+    assert!(run_and_extract_content(book, "Chapter 1").ends_with(
+        "# fn main() {\nlet total = 1 + 2;\nif total > 2 {\n  println!(\"{}\", total);\n}\n# }"
+    ));
+
+    let content = "#![function_body!(\"../test_file_with_two_space_indent.rs\", Counter::bump)]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    assert_eq!(
+        run_and_extract_content(book, "Chapter 1"),
+        "# fn main() {\nself.count += 1;\nif self.count > 9 {\n  self.count = 0;\n}\n# }"
+    );
+
+    let content = "#![function_body!(\"../test_file_with_tabs.rs\", Length::doubled)]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    assert_eq!(
+        run_and_extract_content(book, "Chapter 1"),
+        "# fn main() {\n// ×2, keeping the unit\nLength {\n\tmeters: self.meters * 2.0,\n}\n# }"
+    );
+}
+
+#[test]
+fn test_with_path_header() {
+    // Without `crate-root`, the path is relative to the nearest crate, which is this one
+    let content =
+        "#![type!(\"../test_file_with_type_aliases.rs\", Result, with_path_header = true)]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_eq!(
+        processed_content.lines().next(),
+        Some("// path: tests/fixtures/test_file_with_type_aliases.rs")
+    );
+
+    let content = "#![struct!(mod = \"crate::net::config\", Endpoint, with_path_header = true)]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let ctx = create_test_context_with_config(|config| {
+        config
+            .set("preprocessor.include-rs.crate-root", "module_crate")
+            .unwrap();
+        config
+            .set("preprocessor.include-rs.path-header-style", "fixture")
+            .unwrap();
+    });
+    let processed_content = run_and_extract_content_with_context(book, "Chapter 1", ctx);
+    assert_eq!(
+        processed_content.lines().next(),
+        Some("//- src/net/config.rs")
+    );
+}
+
+#[test]
+fn test_impl_summary() {
+    let content = "\
+```rust
+#![impl_summary!(\"../test_file.rs\", TestStruct)]
+```
+
+```rust
+#![impl_summary!(\"../test_file_with_impl_summary.rs\", Buffer)]
+```
+
+```rust
+#![impl_summary!(\"../test_file_with_impl_summary.rs\", \"Iterator for Buffer\")]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_first_of_duplicate_names() {
+    let visible = |content: &str| {
+        let book = create_test_book("Chapter 1", content, "chapter_1.md");
+        run_and_extract_content(book, "Chapter 1")
+            .lines()
+            .filter(|line| !line.starts_with("# ") && *line != "#")
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    assert_eq!(
+        visible("#![function!(\"../test_file_with_duplicates.rs\", run)]"),
+        "#[cfg(unix)]\npub fn run() -> &'static str {\n    \"unix\"\n}"
+    );
+    assert_eq!(
+        visible("#![function!(\"../test_file_with_duplicates.rs\", Task::spawn)]"),
+        "#[cfg(unix)]\npub fn spawn(&self) -> u32 {\n    1\n}"
+    );
+}
+
+#[test]
+fn test_usage_anchor() {
+    let content = "\
+```rust
+#![struct!(\"../test_file_with_usage.rs\", Config, usage_anchor = \"config-usage\")]
+```
+
+#![struct!(\"../test_file_with_usage.rs\", Config, usage_anchor = \"missing\")]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let ctx = create_test_context_with_config(|config| {
+        config
+            .set("preprocessor.include-rs.hidden-placement", "after")
+            .unwrap();
+    });
+    let processed_content = run_and_extract_content_with_context(book, "Chapter 1", ctx);
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_normalize_line_endings() {
+    let dir = std::env::temp_dir().join(format!("include-rs-crlf-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("crlf.rs");
+    std::fs::write(
+        &source,
+        "struct Point {\r\n    x: i32,\r\n}\r\n\r\nfn origin() -> Point {\r\n    Point { x: 0 }\r\n}\r\n",
+    )
+    .unwrap();
+    let content = format!(
+        "Intro\r\n\r\n```rust\r\n#![source_file!(\"{file}\")]\r\n```\r\n\r\n```rust\r\n#![function!(\"{file}\", origin)]\r\n```\r\n",
+        file = source.display()
+    );
+
+    let book = create_test_book("Chapter 1", &content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert!(!processed_content.contains('\r'));
+    assert!(
+        processed_content.contains("# struct Point {\n#     x: i32,\n# }\nfn origin() -> Point {")
+    );
+
+    let book = create_test_book("Chapter 1", &content, "chapter_1.md");
+    let ctx = create_test_context_with_config(|config| {
+        config
+            .set("preprocessor.include-rs.normalize-line-endings", false)
+            .unwrap();
+    });
+    let processed_content = run_and_extract_content_with_context(book, "Chapter 1", ctx);
+    assert!(processed_content.starts_with("Intro\r\n"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_select_among_duplicates() {
+    let content = "\
+```rust
+#![function!(\"../test_file_with_duplicates.rs\", run#2)]
+```
+
+```rust
+#![function!(\"../test_file_with_duplicates.rs\", run#cfg(not(unix)))]
+```
+
+```rust
+#![function_body!(\"../test_file_with_duplicates.rs\", Task::spawn#2)]
+```
+
+#![function!(\"../test_file_with_duplicates.rs\", run#3)]
+
+#![function!(\"../test_file_with_duplicates.rs\", run#cfg(windows))]
+
+#![function!(\"../test_file_with_duplicates.rs\", Task::spawn#0)]
+
+#![function!(\"../test_file_with_duplicates.rs\", run#999999999999999999999)]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_source_file_anchor() {
+    let content = "\
+```rust
+#![source_file!(\"../test_file_with_anchors.rs\", anchor = \"setup\")]
+```
+
+```rust
+#![source_file!(\"../test_file_with_anchors.rs\", anchor = \"insert\")]
+```
+
+```rust
+#![source_file!(\"../test_file_with_anchors.rs\", anchor = \"report\")]
+```
+
+```rust
+#![source_file!(\"../test_file_with_anchors.rs\", anchor = \"all\")]
+```
+
+```rust
+#![source_file!(\"../test_file_with_anchors.rs\", anchor = \"teardown\")]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_source_file_glob() {
+    let content = "\
+```rust
+#![source_file!(\"../parts/part_*.rs\")]
+```
+
+```rust
+#![source_file!(\"../parts/part_?.rs\", separator = \"// ---\")]
+```
+
+```rust
+#![source_file!(\"../parts/chapter_*.rs\")]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_macro_expansion() {
+    let content = "\
+#![macro_expansion!(\"../test_file_with_macro_expansion.rs\", anchor = \"newtype_demo\")]
+
+#![macro_expansion!(\"../test_file_with_macro_expansion.rs\", anchor = \"missing\")]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_with_file_link() {
+    let content =
+        "#![const!(\"../test_file_with_consts.rs\", RETRY_DELAYS, with_file_link = true)]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let ctx = create_test_context_with_config(|config| {
+        config
+            .set("preprocessor.include-rs.wrap-fences", true)
+            .unwrap();
+    });
+    let processed_content = run_and_extract_content_with_context(book, "Chapter 1", ctx);
+    let (item, details) = processed_content
+        .split_once("\n\n")
+        .expect("the full source follows the item");
+    assert_eq!(
+        item,
+        "```rust\n# const STATUS_CODES: [(&str, u32); 3] = [\n#     (\"OK\", 200),\n#     (\"Not Found\", 404),\n#     (\"Internal Server Error\", 500),\n# ];\n# const MIME_TYPES: &[(&str, &str, bool)] = &[\n#     (\"html\", \"text/html\", true),\n#     (\"png\", \"image/png\", false),\n# ];\n# /// How many requests have been served\n# static REQUEST_COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);\nconst RETRY_DELAYS: [u64; 3] = [100, 200, 400];\n```"
+    );
+    let source = std::fs::read_to_string("tests/fixtures/test_file_with_consts.rs").unwrap();
+    assert_eq!(
+        details,
+        format!(
+            "<details>\n<summary>Full source: ../test_file_with_consts.rs</summary>\n\n```rust\n{}\n```\n\n</details>",
+            source.trim_end()
+        )
+    );
+}
+
+#[test]
+fn test_skip_marker() {
+    let content = "\
+<!-- include-rs: skip -->
+# Writing directives
+
+```rust
+#![function!(\"../test_file.rs\", free_function)]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_eq!(processed_content, content);
+}
+
+#[test]
+fn test_error_output_is_not_reprocessed() {
+    let content = "\
+```rust
+#![struct!(\"../test_file.rs\", Missing)]
+```
+
+```rust
+#![struct!(\"../test_file.rs\" Missing]
+```
+
+#![struct \"not a directive\"]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let first_run = run_and_extract_content(book, "Chapter 1");
+    assert!(
+        first_run.contains("syn::token::Struct 'Missing' not found"),
+        "Unexpected output: {}",
+        first_run
+    );
+
+    // Feeding the output back in, as a second preprocessor or a re-run would, changes nothing
+    let book = create_test_book("Chapter 1", &first_run, "chapter_1.md");
+    let second_run = run_and_extract_content(book, "Chapter 1");
+    assert_eq!(second_run, first_run);
+}
+
+#[test]
+fn test_require_tracked() {
+    // Only meaningful when the fixtures are checked out from git
+    let in_git = std::process::Command::new("git")
+        .args(["ls-files", "--error-unmatch", "tests/fixtures/test_file.rs"])
+        .output()
+        .is_ok_and(|output| output.status.success());
+    if !in_git {
+        return;
+    }
+
+    // The target directory is inside the repository, but ignored
+    let untracked = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("untracked_include.rs");
+    std::fs::write(&untracked, "struct Generated;\n").unwrap();
+    let content = format!(
+        "#![struct!(\"../test_file.rs\", TestStruct)]\n\n#![struct!(\"{}\", Generated)]",
+        untracked.display()
+    );
+    let book = create_test_book("Chapter 1", &content, "chapter_1.md");
+    let ctx = create_test_context_with_config(|config| {
+        config
+            .set("preprocessor.include-rs.require-tracked", true)
+            .unwrap();
+    });
+    let error = IncludeRsPreprocessor
+        .run(&ctx, book)
+        .unwrap_err()
+        .to_string();
+    assert!(
+        error.contains(":3:1: ")
+            && error.ends_with(
+                "untracked_include.rs is not tracked by git, so it may be missing from a fresh checkout"
+            ),
+        "Unexpected error: {}",
+        error
+    );
+}
+
+// Create a mock PreprocessorContext for testing
+fn create_test_context() -> PreprocessorContext {
+    create_test_context_with_config(|_| {})
+}
+
+// Create a mock PreprocessorContext with additional configuration for testing
+fn create_test_context_with_config(configure: impl FnOnce(&mut Config)) -> PreprocessorContext {
+    let mut config = Config::default();
+    config.set("book.title", "Test Book").unwrap();
+    configure(&mut config);
+
+    let manifest_dir = env!("CARGO_MANIFEST_DIR");
+    let fixtures_dir = PathBuf::from(manifest_dir).join("tests").join("fixtures");
+    // Use a test-specific approach since PreprocessorContext has private fields
+    let ctx_json = format!(
+        r#"{{
+            "root": "{root}",
+            "config": {config},
+            "renderer": "html",
+            "mdbook_version": "0.4.47"
+        }}"#,
+        root = fixtures_dir.display(),
+        config = serde_json::to_string(&config).unwrap()
+    );
+
+    serde_json::from_str(&ctx_json).unwrap()
+}
+
+#[test]
+fn test_rustfmt() {
+    let content = "\
+#![struct!(\"../test_file_unformatted.rs\", Point)]
+
+#![function!(\"../test_file_unformatted.rs\", Point::manhattan)]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let ctx = create_test_context_with_config(|config| {
+        config.set("preprocessor.include-rs.rustfmt", true).unwrap();
+    });
+    let processed_content = run_and_extract_content_with_context(book, "Chapter 1", ctx);
+
+    // Without rustfmt the code is included as it is written
+    if std::process::Command::new("rustfmt")
+        .arg("--version")
+        .output()
+        .is_err()
+    {
+        assert!(processed_content.contains("pub struct Point { pub x: i32,"));
+        return;
+    }
+    assert!(processed_content.contains("pub struct Point {\n    pub x: i32,\n    pub y: i32,\n}"));
+    assert!(
+        processed_content
+            .contains("pub fn manhattan(&self) -> i32 {\n    self.x.abs() + self.y.abs()\n}")
+    );
+}
+
+#[test]
+fn test_match_coverage() {
+    let content = "\
+#![match_coverage!(\"../test_file_with_match.rs\", Opcode, handle)]
+
+#![match_coverage!(\"../test_file_with_match.rs\", Opcode, Connection::respond)]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_struct_doc_table() {
+    let content = "\
+#![struct_doc_table!(\"../test_file_with_field_docs.rs\", Config)]
+
+#![struct_doc_table!(\"../test_file_with_field_docs.rs\", Point)]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_strip_docs_and_attrs() {
+    let content = "\
+```rust
+#![struct!(\"../test_file_with_attributes.rs\", User, strip_docs)]
+```
+
+```rust
+#![struct!(\"../test_file_with_attributes.rs\", User, strip_attrs = true)]
+```
+
+```rust
+#![mod!(\"../test_file_with_attributes.rs\", users, strip_docs, strip_attrs)]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_directive_fence_language() {
+    // The directive writes its own fence without `wrap-fences`
+    let content = "\
+#![source_file!(\"../test_script.py\", lang = \"python\")]
+
+#![function!(\"../test_file.rs\", free_function, fence_attrs = \"no_run\")]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert!(
+        processed_content.starts_with("```python\ndef greet(name):\n"),
+        "Unexpected output: {}",
+        processed_content
+    );
+    assert!(
+        processed_content.contains("\n```rust,no_run\n"),
+        "Unexpected output: {}",
+        processed_content
+    );
+}
+
+#[test]
+fn test_directive_fence_around_fences() {
+    // The fences in the included Markdown are shorter than the one around it
+    let content = "#![source_file!(\"../test_readme.md\", lang = \"markdown\")]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert!(
+        processed_content.starts_with("````markdown\n# greeter\n"),
+        "Unexpected output: {}",
+        processed_content
+    );
+    assert!(
+        processed_content.ends_with("# }\n```\n````"),
+        "Unexpected output: {}",
+        processed_content
+    );
+}
+
+#[test]
+fn test_assert_signature() {
+    // Whitespace, including line breaks, doesn't count
+    let content = "\
+```rust,ignore
+#![assert_signature!(\"../test_file_with_signatures.rs\", connect, expected = \"fn connect(addr: SocketAddr) -> io::Result<Stream>\")]
+```
+
+```rust,ignore
+#![assert_signature!(\"../test_file_with_signatures.rs\", Stream::send, expected = \"fn send(&mut self, bytes: &[u8]) -> io::Result<usize>\")]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_assert_signature_mismatch() {
+    let content = "\
+#![assert_signature!(\"../test_file_with_signatures.rs\", connect, expected = \"fn connect(addr: &str) -> io::Result<Stream>\")]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let error = IncludeRsPreprocessor
+        .run(&create_test_context(), book)
+        .unwrap_err()
+        .to_string();
+    assert!(
+        error.contains("Signature of 'connect' doesn't match the expected signature")
+            && error.contains("actual:   fn connect(addr: SocketAddr) -> io::Result<Stream>"),
+        "Unexpected error: {}",
+        error
+    );
+
+    // With `warn`, the actual signature is shown anyway
+    let content = "\
+#![assert_signature!(\"../test_file_with_signatures.rs\", connect, expected = \"fn connect(addr: &str) -> io::Result<Stream>\", warn = true)]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert!(processed_content.contains("fn connect(addr: SocketAddr) -> io::Result<Stream>"));
+}
+
+#[test]
+fn test_cfg_option() {
+    let content = "\
+```rust
+#![function!(\"../test_file_with_cfg.rs\", data_dir, cfg = \"windows\")]
+```
+
+```rust
+#![struct!(\"../test_file_with_cfg.rs\", Codec, cfg = \"feature = \\\"binary\\\"\")]
+```
+
+```rust
+#![struct!(\"../test_file_with_cfg.rs\", Codec, cfg = \"unix\")]
+```
+
+```rust
+#![function!(\"../test_file_with_cfg.rs\", Server::bind, cfg = \"windows\")]
+```
+
+```rust
+#![struct!(\"../test_file_with_cfg.rs\", Server, cfg = \"windows\")]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_source_file_other_languages() {
+    // Neither is parsed as Rust or given Rust comments, even with verify-parse and with_path_header
+    let content = "\
+#![source_file!(\"../test_config.toml\", lang = \"toml\", 1..3, with_path_header = true)]
+
+#![source_file!(\"../test_config.toml\", anchor = \"deps\", fingerprint = true)]
+
+#![source_file!(\"../test_script.sh\")]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let ctx = create_test_context_with_config(|config| {
+        config
+            .set("preprocessor.include-rs.wrap-fences", true)
+            .unwrap();
+        config
+            .set("preprocessor.include-rs.verify-parse", true)
+            .unwrap();
+    });
+    let processed_content = run_and_extract_content_with_context(book, "Chapter 1", ctx);
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_strict_mode() {
+    let content = "#![struct!(\"../test_file.rs\", MissingStruct)]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let ctx = create_test_context_with_config(|config| {
+        config.set("preprocessor.include-rs.strict", true).unwrap();
+    });
+    let error = IncludeRsPreprocessor
+        .run(&ctx, book)
+        .unwrap_err()
+        .to_string();
+    assert!(
+        error.contains("chapter_1.md:1:1:") && error.contains("'MissingStruct' not found"),
+        "Unexpected error: {}",
+        error
+    );
+}
+
+#[test]
+fn test_expand_requires_allow_expand() {
+    let content = "#![expand!(\"../test_file.rs\", TestStruct)]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert!(
+        processed_content.contains("it must be enabled with allow-expand = true"),
+        "Unexpected output: {}",
+        processed_content
+    );
+}
+
+#[test]
+fn test_expand() {
+    let dir = std::env::temp_dir().join(format!("include-rs-expand-test-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"expand_example\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[workspace]\n",
+    )
+    .unwrap();
+    let source = dir.join("src").join("lib.rs");
+    std::fs::write(
+        &source,
+        "#[derive(Debug)]\npub struct Point {\n    x: i32,\n}\n",
+    )
+    .unwrap();
+    let content = format!("#![expand!(\"{}\", Point)]", source.display());
+
+    let book = create_test_book("Chapter 1", &content, "chapter_1.md");
+    let ctx = create_test_context_with_config(|config| {
+        config
+            .set("preprocessor.include-rs.allow-expand", true)
+            .unwrap();
+    });
+    let processed_content = run_and_extract_content_with_context(book, "Chapter 1", ctx);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // Expanding needs cargo-expand, which isn't always installed
+    let installed = std::process::Command::new("cargo")
+        .args(["expand", "--version"])
+        .output()
+        .is_ok_and(|output| output.status.success());
+    if !installed {
+        assert!(
+            processed_content.contains("cargo-expand isn't installed"),
+            "Unexpected output: {}",
+            processed_content
+        );
+        return;
+    }
+    assert!(
+        processed_content.starts_with("```rust\npub struct Point {")
+            && processed_content.contains("impl ::core::fmt::Debug for Point"),
+        "Unexpected output: {}",
+        processed_content
+    );
+}
+
+#[test]
+fn test_expand_is_not_cached() {
+    // The expansion depends on the whole crate, not just the file the directive names
+    let dir = std::env::temp_dir().join(format!(
+        "include-rs-expand-cache-test-{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"expand_cache_example\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[workspace]\n",
+    )
+    .unwrap();
+    let source = dir.join("src").join("lib.rs");
+    std::fs::write(&source, "pub mod shapes;\n").unwrap();
+    let module = dir.join("src").join("shapes.rs");
+    std::fs::write(&module, "pub struct Point {\n    x: i32,\n}\n").unwrap();
+    let content = format!("#![expand!(\"{}\", Point)]", source.display());
+    let cache_file = dir.join("cache.json");
+    let run = || {
+        let book = create_test_book("Chapter 1", &content, "chapter_1.md");
+        let ctx = create_test_context_with_config(|config| {
+            config
+                .set("preprocessor.include-rs.allow-expand", true)
+                .unwrap();
+            config
+                .set(
+                    "preprocessor.include-rs.cache-file",
+                    cache_file.display().to_string(),
+                )
+                .unwrap();
+        });
+        run_and_extract_content_with_context(book, "Chapter 1", ctx)
+    };
+
+    let first = run();
+    std::fs::write(&module, "pub struct Point {\n    x: u64,\n}\n").unwrap();
+    let second = run();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // Expanding needs cargo-expand, which isn't always installed
+    let installed = std::process::Command::new("cargo")
+        .args(["expand", "--version"])
+        .output()
+        .is_ok_and(|output| output.status.success());
+    if !installed {
+        assert!(
+            second.contains("cargo-expand isn't installed"),
+            "Unexpected output: {}",
+            second
+        );
+        return;
+    }
+    assert!(first.contains("x: i32"), "Unexpected output: {}", first);
+    assert!(second.contains("x: u64"), "Unexpected output: {}", second);
+}
+
+#[test]
+fn test_trait_stub() {
+    let content = "\
+```rust,ignore
+#![trait_stub!(\"../test_file.rs\", TestTrait)]
+```
+
+```rust,ignore
+#![trait_stub!(\"../test_file_with_trait_stub.rs\", Store)]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_unknown_directive() {
+    // Without strict mode, a misspelled directive is warned about and left as it is
+    let content = "#![fucntion!(\"../test_file.rs\", free_function)]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_eq!(processed_content, content);
+
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let ctx = create_test_context_with_config(|config| {
+        config.set("preprocessor.include-rs.strict", true).unwrap();
+    });
+    let error = IncludeRsPreprocessor
+        .run(&ctx, book)
+        .unwrap_err()
+        .to_string();
+    assert!(
+        error
+            .contains("chapter_1.md:1:1: Unknown directive 'fucntion!', did you mean 'function!'?"),
+        "Unexpected error: {}",
+        error
+    );
+
+    // Nothing is suggested for a name that isn't close to any directive
+    let content = "/* include: frobnicate!(\"../test_file.rs\", free_function) */";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let ctx = create_test_context_with_config(|config| {
+        config.set("preprocessor.include-rs.strict", true).unwrap();
+    });
+    let error = IncludeRsPreprocessor
+        .run(&ctx, book)
+        .unwrap_err()
+        .to_string();
+    assert!(
+        error.ends_with("Unknown directive 'frobnicate!'"),
+        "Unexpected error: {}",
+        error
+    );
+}
+
+#[test]
+fn test_unknown_directive_warning() {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    // The warning names the chapter and line of a well-formed directive with no handler
+    let content = "Text\n\n#![strukt!(\"../test_file.rs\", TestStruct)]\n";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let input = serde_json::to_vec(&(create_test_context(), book)).unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_mdbook-include-rs"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(&input).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains(
+            "Warning: ./tests/fixtures/src/chapter_1.md:3:1: Unknown directive 'strukt!', did you mean 'struct!'?"
+        ),
+        "Unexpected stderr: {}",
+        stderr
+    );
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("#![strukt!"),
+        "Unexpected stdout: {}",
+        stdout
+    );
+}
+
+#[test]
+fn test_chapter_sidecar() {
+    // `sidecar.md.include.toml` sets `strip_attrs` by default and a `@fixtures` path alias
+    let content = "\
+```rust
+#![struct!(\"@fixtures/test_file_with_attributes.rs\", User)]
+```
+
+```rust
+#![struct!(\"@fixtures/test_file_with_attributes.rs\", User, strip_attrs = false)]
+```
+
+```rust
+#![struct!(\"@missing/test_file_with_attributes.rs\", User)]
+```";
+    let book = create_test_book("Chapter 1", content, "sidecar.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+
+    // Chapters without a sidecar are unaffected
+    let content = "#![struct!(\"../test_file_with_attributes.rs\", User)]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert!(
+        processed_content.contains("#[derive(Debug, Clone, PartialEq)]"),
+        "Unexpected output: {}",
+        processed_content
+    );
+}
+
+#[test]
+fn test_redact() {
+    let content = "\
+```rust
+#![const!(\"../test_file_with_secrets.rs\", DEFAULT_TOKEN, redact = \"sk_live_\\w+\")]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert!(
+        processed_content.contains("pub const DEFAULT_TOKEN: &str = \"***\";")
+            && !processed_content.contains("sk_live_"),
+        "Unexpected output: {}",
+        processed_content
+    );
+
+    // Book-wide patterns apply to every directive, including the hidden lines
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let ctx = create_test_context_with_config(|config| {
+        config
+            .set(
+                "preprocessor.include-rs.redact",
+                vec![r#"https://[\w.]+\.corp[^\s"]*"#],
+            )
+            .unwrap();
+    });
+    let processed_content = run_and_extract_content_with_context(book, "Chapter 1", ctx);
+    assert!(
+        processed_content.contains("# pub const API_URL: &str = \"***\";")
+            && !processed_content.contains("sk_live_"),
+        "Unexpected output: {}",
+        processed_content
+    );
+}
+
+#[test]
+fn test_nested_sub_chapter() {
+    // Includes are relative to the directory of the chapter's own file, however deep it's nested
+    let chapter = |name: &str, content: &str, path: &str, sub_items: Vec<BookItem>| Chapter {
+        name: name.to_string(),
+        content: content.to_string(),
+        number: None,
+        sub_items,
+        path: Some(PathBuf::from(path)),
+        source_path: Some(PathBuf::from(path)),
+        parent_names: vec![],
+    };
+    let c = chapter(
+        "Part C",
+        "```rust\n#![source_file!(\"../shared.rs\")]\n```",
+        "a/b/c.md",
+        vec![],
+    );
+    let b = chapter("Part B", "", "a/b/index.md", vec![BookItem::Chapter(c)]);
+    let a = chapter("Part A", "", "a/index.md", vec![BookItem::Chapter(b)]);
+    let mut book = Book::new();
+    book.push_item(BookItem::Chapter(a));
+
+    let processed_content = run_and_extract_content(book, "Part C");
+    assert_eq!(
+        processed_content,
+        "```rust\n/// Shared between the chapters of part A\npub fn shared_helper() -> u32 {\n    42\n}\n```"
+    );
+}
+
+#[test]
+fn test_error_shows_chapter_source_path() {
+    let mut book = Book::new();
+    book.push_item(BookItem::Chapter(Chapter {
+        name: "Part C".to_string(),
+        content: "Intro\n\n#![struct!(\"../missing.rs\", Point)]".to_string(),
+        number: None,
+        sub_items: vec![],
+        path: Some(PathBuf::from("a/b/c.md")),
+        source_path: Some(PathBuf::from("a/b/c.md")),
+        parent_names: vec![],
+    }));
+
+    let processed_content = run_and_extract_content(book, "Part C");
+    assert!(
+        processed_content.contains("src/a/b/c.md:3:1: "),
+        "error should name the chapter file, got: {}",
+        processed_content
+    );
+}
+
+#[test]
+fn test_hidden_preamble() {
+    let content = "\
+```rust
+#![struct!(\"../test_file.rs\", TestStruct)]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let ctx = create_test_context_with_config(|config| {
+        config
+            .set(
+                "preprocessor.include-rs.hidden-preamble",
+                "#![allow(unused)]\nuse std::collections::HashMap;\ntype Map = HashMap<String, i32>;\n",
+            )
+            .unwrap();
+    });
+    let processed_content = run_and_extract_content_with_context(book, "Chapter 1", ctx);
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_dyn_ify() {
+    let content = "\
+```rust
+#![function!(\"../test_file_with_generic_params.rs\", count_bytes, dyn_ify)]
+```
+
+```rust
+#![function!(\"../test_file_with_generic_params.rs\", greet, dyn_ify)]
+```
+
+```rust
+#![function!(\"../test_file_with_generic_params.rs\", show_all, dyn_ify)]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_usages() {
+    let content = "\
+#![usages!(\"../test_file_with_usages.rs\", Account)]
+
+#![usages!(\"../test_file_with_usages.rs\", Missing)]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_assoc() {
+    let content = "\
+```rust
+#![assoc!(\"../test_file_with_assoc_items.rs\", Meters::MARATHON)]
+```
+
+```rust
+#![assoc!(\"../test_file_with_assoc_items.rs\", Meters::Output)]
+```
+
+```rust
+#![assoc!(\"../test_file_with_assoc_items.rs\", Add for Meters::Output)]
+```
+
+```rust
+#![assoc!(\"../test_file_with_assoc_items.rs\", Meters::new)]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_variant() {
+    let content = "\
+```rust
+#![variant!(\"../test_file.rs\", TestEnum::A)]
+```
+
+```rust
+#![variant!(\"../test_file.rs\", TestEnum::B)]
+```
+
+```rust
+#![variant!(\"../test_file.rs\", TestEnum::C)]
+```
+
+```rust
+#![variant!(\"../test_file.rs\", TestEnum::D)]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_impl_methods() {
+    let content = "\
+```rust
+#![impl!(\"../test_file_with_impl_summary.rs\", Buffer, methods = [new, empty])]
+```
+
+```rust
+#![impl!(\"../test_file_with_impl_summary.rs\", Buffer, methods = [extend])]
+```
+
+```rust
+#![impl!(\"../test_file_with_impl_summary.rs\", Buffer, methods = [new, clear, reset])]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_impl_methods_empty() {
+    let fixtures = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    for directive in [
+        "impl!(\"test_file.rs\", TestStruct, methods = [])",
+        "impl!(\"test_file.rs\", TestStruct, merge, methods = [])",
+    ] {
+        let error = extract(&fixtures, directive).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("methods needs at least one name"),
+            "{}",
+            error
+        );
+    }
+}
+
+#[test]
+fn test_with_test_harness() {
+    let content = "\
+```rust
+#![function!(\"../test_file_with_test_harness.rs\", parse_list, with_test_harness, test_args = \"\\\"1, 2, 3\\\", ','\")]
+```
+
+```rust
+#![function!(\"../test_file_with_test_harness.rs\", greet, with_test_harness = true, test_args = \"\\\"Ferris\\\"\")]
+```
+
+```rust
+#![function!(\"../test_file_with_test_harness.rs\", greet, with_test_harness, test_args = \"(\")]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_impl_merge() {
+    let content = "\
+```rust
+#![impl!(\"../test_file_with_split_impls.rs\", Counter, merge)]
+```
+
+```rust
+#![impl!(\"../test_file_with_split_impls.rs\", Counter, merge, methods = [new, reset])]
+```
+
+```rust
+#![impl!(\"../test_file_with_split_impls.rs\", Counter, merge, methods = [increment])]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_impl_with_generic_arguments() {
+    let content = "\
+```rust
+#![impl!(\"../test_file_with_generic_impls.rs\", \"Wrapper<String>\")]
+```
+
+```rust
+#![impl!(\"../test_file_with_generic_impls.rs\", Wrapper < u8 >)]
+```
+
+```rust
+#![impl!(\"../test_file_with_generic_impls.rs\", Wrapper)]
+```
+
+```rust
+#![impl!(\"../test_file_with_generic_impls.rs\", other::Wrapper)]
+```
+
+```rust
+#![impl!(\"../test_file_with_generic_impls.rs\", \"Wrapper<i32>\")]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_closure() {
+    let content = "\
+```rust
+#![struct!(\"../test_file_with_dependencies.rs\", Catalog, closure)]
+```
+
+```rust
+#![struct!(\"../test_file_with_dependencies.rs\", Node, closure = true)]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_source_file_numbered() {
+    let content = "\
+```rust
+#![source_file!(\"../test_file.rs\", 6..=12, numbered)]
+```
+
+```rust
+#![source_file!(\"../test_file_with_anchors.rs\", anchor = \"setup\", numbered = true)]
+```
+
+```rust
+#![source_file!(\"../test_file_with_hidden_lines.rs\", numbered)]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_field() {
+    let content = "\
+```rust
+#![field!(\"../test_file_with_field_docs.rs\", Config::jobs)]
+```
+
+```rust
+#![field!(\"../test_file_with_field_docs.rs\", Config::verbose)]
+```
+
+```rust
+#![field!(\"../test_file_with_field_docs.rs\", Config::timeout)]
+```
+
+```rust
+#![field!(\"../test_file_with_field_docs.rs\", Point::x)]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_hide_prefix() {
+    // The hidden lines of a fence that isn't a doctest, and a snippet without them
+    let content = "\
+```rust
+#![function_body!(\"../test_file.rs\", free_function, [struct TestStruct], hide_prefix = \"//\")]
+```
+
+```rust
+#![struct!(\"../test_file.rs\", TestStruct, hide_prefix = \"\")]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_book_hide_prefix_with_highlight() {
+    // Highlighted lines are counted past the book's own hidden-line prefix
+    let content =
+        "#![struct!(\"../test_file.rs\", TestStruct, lang = \"rust\", highlight = \"1\")]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let ctx = create_test_context_with_config(|config| {
+        config
+            .set("preprocessor.include-rs.hide-prefix", "//")
+            .unwrap();
+    });
+    let processed_content = run_and_extract_content_with_context(book, "Chapter 1", ctx);
+    let lines = processed_content.lines().collect::<Vec<_>>();
+    assert!(
+        lines[1].starts_with("// "),
+        "Unexpected output: {}",
+        processed_content
+    );
+    let first_visible = lines
+        .iter()
+        .position(|line| *line == "struct TestStruct {")
+        .expect("the struct is shown");
+    assert_eq!(lines[0], format!("```rust,hl_lines={}", first_visible));
+}
+
+#[test]
+fn test_focus() {
+    // The dependencies are shown dimmed, as escaped HTML, rather than hidden
+    let content = "#![function_body!(\"../test_file.rs\", free_function, [struct TestStruct], lang = \"rust\", focus)]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert!(
+        processed_content.starts_with(
+            "<pre><code class=\"nohighlight language-rust\"><span class=\"include-rs-dim\">use std::fmt;\nimpl TestStruct {\n"
+        ),
+        "Unexpected output: {}",
+        processed_content
+    );
+    assert!(
+        processed_content.contains(
+            "}\n</span>struct TestStruct {\n    name: String,\n    value: i32,\n}\n<span class=\"include-rs-dim\">fn main() {\n</span>println!(&quot;Hello, world! \\\\{&quot;);\n<span class=\"include-rs-dim\">}</span></code></pre>"
+        ),
+        "Unexpected output: {}",
+        processed_content
+    );
+    assert!(!processed_content.contains("\n# "));
+}
+
+#[test]
+fn test_source_file_highlight_item() {
+    // The whole file, with the lines of the struct highlighted
+    let content = "#![source_file!(\"../test_file.rs\", highlight_item = \"TestStruct\")]";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let ctx = create_test_context_with_config(|config| {
+        config
+            .set("preprocessor.include-rs.wrap-fences", true)
+            .unwrap();
+    });
+    let processed_content = run_and_extract_content_with_context(book, "Chapter 1", ctx);
+    let source = std::fs::read_to_string("tests/fixtures/test_file.rs").unwrap();
+    assert_eq!(
+        processed_content,
+        format!("```rust,hl_lines=6-9\n{}\n```", source.trim())
+    );
+    let lines = source.lines().collect::<Vec<_>>();
+    assert_eq!(lines[5], "struct TestStruct {");
+    assert_eq!(lines[8], "}");
+}
+
+#[test]
+fn test_with_imports() {
+    // A method hides nothing else from its file, so its imports are brought along
+    let content = "\
+```rust
+#![function_body!(\"../test_file_with_imports.rs\", WordCount::summary, with_imports)]
+```
+
+```rust
+#![struct!(\"../test_file_with_imports.rs\", WordCount, with_imports)]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_auto_deps() {
+    // The types a function or method refers to are hidden, and the rest of the file isn't
+    let content = "\
+```rust
+#![function_body!(\"../test_file_with_type_refs.rs\", count_nodes, auto_deps)]
+```
+
+```rust
+#![function!(\"../test_file_with_type_refs.rs\", Node::leaf, auto_deps)]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_implementors() {
+    let content = "\
+```rust
+#![implementors!(\"../test_file_with_implementors.rs\", Shape)]
+```";
+    let book = create_test_book("Chapter 1", content, "chapter_1.md");
+    let processed_content = run_and_extract_content(book, "Chapter 1");
+    assert_snapshot!(processed_content);
+}
+
+#[test]
+fn test_render_fallback() {
+    // An item built in code has no source text to include
+    let item: syn::Item = syn::parse_quote! {
+        struct Point { x: i32, y: i32 }
+    };
+
+    let error = render_item(&item, &mdbook_include_rs::Config::default()).unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .contains("set render-fallback = \"pretty\""),
+        "{}",
+        error
+    );
+
+    let mut config = mdbook_include_rs::Config::default();
+    config.render_fallback = RenderFallback::Pretty;
+    assert_eq!(
+        render_item(&item, &config).unwrap(),
+        "struct Point {\n    x: i32,\n    y: i32,\n}"
+    );
 }